@@ -1,10 +1,38 @@
-use async_graphql::{self, SimpleObject};
+use async_graphql::{self, dataloader::DataLoader, ComplexObject, Enum, SimpleObject};
 use chrono::NaiveDateTime;
 use sea_orm::entity::prelude::*;
 
+use crate::{
+    loader::{
+        FavoriteLoader, PriceHistoryLoader, PromotionLoader, ReviewLoader, SpecificationsLoader,
+        UserLoader,
+    },
+    money::Money,
+    promotion::PromotionKind,
+};
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, EnumIter, DeriveActiveEnum)]
+#[sea_orm(enum_name = "advert_status", db_type = "Enum", rs_type = "String")]
+#[derive(Default)]
+pub enum AdvertStatus {
+    #[sea_orm(string_value = "draft")]
+    Draft,
+    #[sea_orm(string_value = "active")]
+    #[default]
+    Active,
+    #[sea_orm(string_value = "reserved")]
+    Reserved,
+    #[sea_orm(string_value = "sold")]
+    Sold,
+    #[sea_orm(string_value = "expired")]
+    Expired,
+    #[sea_orm(string_value = "archived")]
+    Archived,
+}
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject, Default)]
 #[sea_orm(table_name = "advert")]
-#[graphql(name = "Advert")]
+#[graphql(name = "Advert", complex)]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
@@ -16,23 +44,18 @@ pub struct Model {
     pub photo_url: String,
     pub additional_photos: Option<Vec<String>>,
     pub available: bool,
-    pub price: f32,
+    pub price: Money,
+    pub currency: String,
     pub lat: f32,
     pub lon: f32,
     pub user_id: i32,
-    pub old_price: f32,
+    pub old_price: Money,
     pub sold_to: Option<i32>,
-
-    #[sea_orm(ignore)]
-    pub is_favorited: bool,
-    #[sea_orm(ignore)]
-    pub specs: Vec<super::specifications::Model>,
-
-    #[sea_orm(ignore)]
-    pub user: super::user::Model,
-
-    #[sea_orm(ignore)]
-    pub review: Option<super::reviews::Model>,
+    pub sold_at: Option<NaiveDateTime>,
+    pub status: AdvertStatus,
+    pub expires_at: Option<NaiveDateTime>,
+    #[graphql(visible = false)]
+    pub expiry_notified_at: Option<NaiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -47,8 +70,10 @@ pub enum Relation {
     Specifications,
     #[sea_orm(has_many = "super::favorites::Entity", on_delete = "Cascade")]
     Favorites,
-    #[sea_orm(has_one = "super::reviews::Entity", on_delete = "Cascade")]
+    #[sea_orm(has_many = "super::reviews::Entity", on_delete = "Cascade")]
     Review,
+    #[sea_orm(has_many = "super::price_history::Entity", on_delete = "Cascade")]
+    PriceHistory,
     // #[sea_orm(
     //     belongs_to = "super::user::Entity",
     //     from = "Column::SoldTo",
@@ -87,6 +112,12 @@ impl Related<super::reviews::Entity> for Entity {
     }
 }
 
+impl Related<super::price_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PriceHistory.def()
+    }
+}
+
 impl Related<super::chat::Entity> for Entity {
     fn to() -> RelationDef {
         // This tells SeaORM that one advert may have many chats.
@@ -95,3 +126,81 @@ impl Related<super::chat::Entity> for Entity {
 }
 
 impl ActiveModelBehavior for ActiveModel {}
+
+#[ComplexObject]
+impl Model {
+    async fn specs(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<super::specifications::Model>> {
+        let loader = ctx.data::<DataLoader<SpecificationsLoader>>()?;
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    async fn user(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<super::user::Model> {
+        let loader = ctx.data::<DataLoader<UserLoader>>()?;
+        loader
+            .load_one(self.user_id)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("User not found"))
+    }
+
+    /// The seller without contact details, for listings.
+    async fn seller(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<super::user::PublicUser> {
+        let loader = ctx.data::<DataLoader<UserLoader>>()?;
+        loader
+            .load_one(self.user_id)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| async_graphql::Error::new("User not found"))
+    }
+
+    /// The buyer's review of the seller.
+    async fn review(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<super::reviews::Model>> {
+        let loader = ctx.data::<DataLoader<ReviewLoader>>()?;
+        let reviews = loader.load_one(self.id).await?.unwrap_or_default();
+        Ok(reviews
+            .into_iter()
+            .find(|review| review.role == super::reviews::ReviewRole::AsBuyer))
+    }
+
+    async fn reviews(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<super::reviews::Model>> {
+        let loader = ctx.data::<DataLoader<ReviewLoader>>()?;
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    /// Price changes, oldest first.
+    async fn price_history(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<super::price_history::Model>> {
+        let loader = ctx.data::<DataLoader<PriceHistoryLoader>>()?;
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    async fn is_favorited(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<bool> {
+        let loader = ctx.data::<DataLoader<FavoriteLoader>>()?;
+        Ok(loader.load_one(self.id).await?.unwrap_or(false))
+    }
+
+    /// Promotions running right now, e.g. to highlight the card.
+    async fn promotions(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Vec<PromotionKind>> {
+        let loader = ctx.data::<DataLoader<PromotionLoader>>()?;
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+}
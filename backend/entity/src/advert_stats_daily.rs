@@ -0,0 +1,31 @@
+use chrono::NaiveDate;
+use sea_orm::entity::prelude::*;
+
+/// Deduplicated views of an advert on one (UTC) day, flushed from Redis.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "advert_stats_daily")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub advert_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub day: NaiveDate,
+    pub views: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::advert::Entity",
+        from = "Column::AdvertId",
+        to = "super::advert::Column::Id"
+    )]
+    Advert,
+}
+
+impl Related<super::advert::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Advert.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use async_graphql::{self, SimpleObject};
+use chrono::NaiveDateTime;
+use sea_orm::entity::prelude::*;
+
+/// A logged-in user looked at the phone number on an advert. Kept once per
+/// viewer and advert so sellers can see how many people did.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "contact_reveal")]
+#[graphql(name = "ContactReveal")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub advert_id: i32,
+    pub seller_id: i32,
+    #[graphql(visible = false)]
+    pub viewer_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::advert::Entity",
+        from = "Column::AdvertId",
+        to = "super::advert::Column::Id"
+    )]
+    Advert,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::SellerId",
+        to = "super::user::Column::Id"
+    )]
+    Seller,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::ViewerId",
+        to = "super::user::Column::Id"
+    )]
+    Viewer,
+}
+
+impl Related<super::advert::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Advert.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
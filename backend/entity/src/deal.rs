@@ -2,6 +2,8 @@ use async_graphql::{self, SimpleObject};
 use chrono::NaiveDateTime;
 use sea_orm::entity::prelude::*;
 
+use crate::money::Money;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject, Default)]
 #[sea_orm(table_name = "deal")]
 #[graphql(name = "Deal")]
@@ -9,7 +11,8 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub chat_id: i32,
-    pub price: f32,
+    pub price: Money,
+    pub currency: String,
     pub created_at: NaiveDateTime,
     pub requester_id: i32,
     pub status: String,
@@ -0,0 +1,19 @@
+use async_graphql::{self, SimpleObject};
+use chrono::NaiveDateTime;
+use sea_orm::entity::prelude::*;
+
+/// Units of `currency` per one unit of the configured base currency.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "exchange_rate")]
+#[graphql(name = "ExchangeRate")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub currency: String,
+    pub rate: Decimal,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -11,6 +11,12 @@ pub struct Model {
     pub created_at: NaiveDateTime,
     pub user_id: i32,
     pub advert_id: i32,
+    pub collection_id: Option<i32>,
+    /// Private note, only ever shown to the owner.
+    pub note: Option<String>,
+
+    #[sea_orm(ignore)]
+    pub advert: Option<super::advert::Model>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -27,6 +33,12 @@ pub enum Relation {
         to = "super::user::Column::Id"
     )]
     User,
+    #[sea_orm(
+        belongs_to = "super::favorite_collection::Entity",
+        from = "Column::CollectionId",
+        to = "super::favorite_collection::Column::Id"
+    )]
+    Collection,
 }
 
 impl Related<super::advert::Entity> for Entity {
@@ -41,4 +53,10 @@ impl Related<super::user::Entity> for Entity {
     }
 }
 
+impl Related<super::favorite_collection::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Collection.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
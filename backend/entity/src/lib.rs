@@ -1,8 +1,22 @@
 pub mod advert;
+pub mod advert_stats_daily;
 pub mod chat;
+pub mod contact_reveal;
 pub mod deal;
+pub mod exchange_rate;
+pub mod favorite_collection;
 pub mod favorites;
+pub mod job;
+pub mod loader;
 pub mod message;
+pub mod money;
+pub mod notification;
+pub mod notification_preference;
+pub mod price_history;
+pub mod promotion;
+pub mod push_subscription;
+pub mod recovery_code;
 pub mod reviews;
 pub mod specifications;
 pub mod user;
+pub mod user_identity;
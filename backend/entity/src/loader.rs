@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_graphql::dataloader::Loader;
+use chrono::Utc;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, RelationTrait,
+};
+
+use crate::{
+    advert, chat, deal, favorites, price_history,
+    promotion::{self, PromotionKind},
+    reviews, specifications, user,
+};
+
+pub struct SpecificationsLoader(pub DatabaseConnection);
+
+#[async_trait::async_trait]
+impl Loader<i32> for SpecificationsLoader {
+    type Value = Vec<specifications::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, advert_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let specs = specifications::Entity::find()
+            .filter(specifications::Column::AdvertId.is_in(advert_ids.iter().copied()))
+            .all(&self.0)
+            .await?;
+
+        let mut specs_map: HashMap<i32, Vec<specifications::Model>> = HashMap::new();
+        for spec in specs {
+            specs_map.entry(spec.advert_id).or_default().push(spec);
+        }
+        Ok(specs_map)
+    }
+}
+
+pub struct UserLoader(pub DatabaseConnection);
+
+#[async_trait::async_trait]
+impl Loader<i32> for UserLoader {
+    type Value = user::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, user_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let users = user::Entity::find()
+            .filter(user::Column::Id.is_in(user_ids.iter().copied()))
+            .all(&self.0)
+            .await?;
+
+        Ok(users.into_iter().map(|user| (user.id, user)).collect())
+    }
+}
+
+/// Visible reviews keyed by the advert they were written for, with the author attached.
+pub struct ReviewLoader(pub DatabaseConnection);
+
+#[async_trait::async_trait]
+impl Loader<i32> for ReviewLoader {
+    type Value = Vec<reviews::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, advert_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let reviews = reviews::Entity::find()
+            .filter(reviews::Column::AdvertId.is_in(advert_ids.iter().copied()))
+            .filter(reviews::Column::Hidden.eq(false))
+            .all(&self.0)
+            .await?;
+
+        let reviewer_ids: HashSet<i32> = reviews.iter().map(|review| review.user_id).collect();
+        let reviewers: HashMap<i32, user::Model> = user::Entity::find()
+            .filter(user::Column::Id.is_in(reviewer_ids))
+            .all(&self.0)
+            .await?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect();
+
+        let mut reviews_map: HashMap<i32, Vec<reviews::Model>> = HashMap::new();
+        for mut review in reviews {
+            review.user = reviewers.get(&review.user_id).cloned().unwrap_or_default();
+            reviews_map
+                .entry(review.advert_id)
+                .or_default()
+                .push(review);
+        }
+        Ok(reviews_map)
+    }
+}
+
+/// Whether the requesting user has favorited an advert; always false for guests.
+pub struct FavoriteLoader {
+    pub db: DatabaseConnection,
+    pub viewer_id: Option<i32>,
+}
+
+#[async_trait::async_trait]
+impl Loader<i32> for FavoriteLoader {
+    type Value = bool;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, advert_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let Some(viewer_id) = self.viewer_id else {
+            return Ok(HashMap::new());
+        };
+
+        let favorites = favorites::Entity::find()
+            .filter(favorites::Column::UserId.eq(viewer_id))
+            .filter(favorites::Column::AdvertId.is_in(advert_ids.iter().copied()))
+            .all(&self.db)
+            .await?;
+
+        Ok(favorites
+            .into_iter()
+            .map(|favorite| (favorite.advert_id, true))
+            .collect())
+    }
+}
+
+/// Kinds of promotion running right now, keyed by advert.
+pub struct PromotionLoader(pub DatabaseConnection);
+
+#[async_trait::async_trait]
+impl Loader<i32> for PromotionLoader {
+    type Value = Vec<PromotionKind>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, advert_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let promotions = promotion::Entity::find_active(Utc::now().naive_utc())
+            .filter(promotion::Column::AdvertId.is_in(advert_ids.iter().copied()))
+            .all(&self.0)
+            .await?;
+
+        let mut kinds: HashMap<i32, Vec<PromotionKind>> = HashMap::new();
+        for promotion in promotions {
+            let advert_kinds = kinds.entry(promotion.advert_id).or_default();
+            if !advert_kinds.contains(&promotion.kind) {
+                advert_kinds.push(promotion.kind);
+            }
+        }
+        Ok(kinds)
+    }
+}
+
+/// Price changes keyed by advert, oldest first.
+pub struct PriceHistoryLoader(pub DatabaseConnection);
+
+#[async_trait::async_trait]
+impl Loader<i32> for PriceHistoryLoader {
+    type Value = Vec<price_history::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, advert_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let changes = price_history::Entity::find()
+            .filter(price_history::Column::AdvertId.is_in(advert_ids.iter().copied()))
+            .order_by_asc(price_history::Column::ChangedAt)
+            .all(&self.0)
+            .await?;
+
+        let mut history: HashMap<i32, Vec<price_history::Model>> = HashMap::new();
+        for change in changes {
+            history.entry(change.advert_id).or_default().push(change);
+        }
+        Ok(history)
+    }
+}
+
+/// How much of a user's profile the requesting user may see.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UserAccess {
+    Public,
+    /// Contact details, for the other side of an open chat or a deal.
+    Contact,
+    /// Everything, for the user themselves and admins.
+    Full,
+}
+
+/// `UserAccess` of the requesting user to each user; `Public` for guests.
+pub struct UserAccessLoader {
+    pub db: DatabaseConnection,
+    pub viewer_id: Option<i32>,
+}
+
+#[async_trait::async_trait]
+impl Loader<i32> for UserAccessLoader {
+    type Value = UserAccess;
+    type Error = Arc<DbErr>;
+
+    async fn load(&self, user_ids: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        let mut access: HashMap<i32, UserAccess> = user_ids
+            .iter()
+            .map(|user_id| (*user_id, UserAccess::Public))
+            .collect();
+        let Some(viewer_id) = self.viewer_id else {
+            return Ok(access);
+        };
+
+        let viewer = user::Entity::find_by_id(viewer_id).one(&self.db).await?;
+        if viewer.is_some_and(|viewer| viewer.role == user::Role::Admin) {
+            access
+                .values_mut()
+                .for_each(|level| *level = UserAccess::Full);
+            return Ok(access);
+        }
+        if let Some(level) = access.get_mut(&viewer_id) {
+            *level = UserAccess::Full;
+        }
+
+        // Chats between the viewer and these users on either one's advert that
+        // are still open or led to a deal.
+        let deal_chats = deal::Entity::find()
+            .select_only()
+            .column(deal::Column::ChatId)
+            .into_query();
+        let pairs: Vec<(i32, i32)> = chat::Entity::find()
+            .select_only()
+            .column(chat::Column::ParticipantId)
+            .column(advert::Column::UserId)
+            .join(JoinType::InnerJoin, chat::Relation::Advert.def())
+            .filter(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(chat::Column::ParticipantId.eq(viewer_id))
+                            .add(advert::Column::UserId.is_in(user_ids.iter().copied())),
+                    )
+                    .add(
+                        Condition::all()
+                            .add(advert::Column::UserId.eq(viewer_id))
+                            .add(chat::Column::ParticipantId.is_in(user_ids.iter().copied())),
+                    ),
+            )
+            .filter(
+                Condition::any()
+                    .add(chat::Column::Archived.eq(false))
+                    .add(chat::Column::Id.in_subquery(deal_chats)),
+            )
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        for (participant_id, seller_id) in pairs {
+            let other = if participant_id == viewer_id {
+                seller_id
+            } else {
+                participant_id
+            };
+            if let Some(level) = access.get_mut(&other) {
+                *level = (*level).max(UserAccess::Contact);
+            }
+        }
+
+        Ok(access)
+    }
+}
@@ -0,0 +1,39 @@
+use async_graphql::{InputValueError, InputValueResult, Scalar, ScalarType, Value};
+use sea_orm::entity::prelude::*;
+use std::{fmt, str::FromStr};
+
+/// Exact monetary amount. Serialized to GraphQL as a decimal string so cents
+/// survive the trip through JSON; numbers are accepted on input as well.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, DeriveValueType)]
+pub struct Money(pub Decimal);
+
+impl Money {
+    pub fn is_negative(&self) -> bool {
+        self.0.is_sign_negative() && !self.0.is_zero()
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.0)
+    }
+}
+
+#[Scalar(name = "Money")]
+impl ScalarType for Money {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        let parsed = match &value {
+            Value::String(s) => Decimal::from_str(s.trim()),
+            Value::Number(n) => Decimal::from_str(&n.to_string()),
+            _ => return Err(InputValueError::expected_type(value)),
+        };
+
+        parsed
+            .map(|amount| Money(amount.round_dp(2)))
+            .map_err(|_| InputValueError::custom("Invalid money amount"))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
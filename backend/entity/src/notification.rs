@@ -0,0 +1,64 @@
+use async_graphql::{self, Enum, SimpleObject};
+use chrono::NaiveDateTime;
+use sea_orm::entity::prelude::*;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Hash, Debug, EnumIter, DeriveActiveEnum)]
+#[sea_orm(enum_name = "notification_kind", db_type = "Enum", rs_type = "String")]
+pub enum NotificationKind {
+    /// Sent by the chat service, in-app and Web Push only.
+    #[sea_orm(string_value = "new_message")]
+    NewMessage,
+    /// Sent by the chat service, in-app and Web Push only.
+    #[sea_orm(string_value = "deal_offer")]
+    DealOffer,
+    #[sea_orm(string_value = "review_received")]
+    ReviewReceived,
+    #[sea_orm(string_value = "price_drop")]
+    PriceDrop,
+    #[sea_orm(string_value = "advert_expiring")]
+    AdvertExpiring,
+}
+
+impl NotificationKind {
+    /// Whether this kind can be sent by email. The chat service writes its
+    /// notifications straight to the table, so those are in-app and push only.
+    pub fn emailed(self) -> bool {
+        !matches!(
+            self,
+            NotificationKind::NewMessage | NotificationKind::DealOffer
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "notification")]
+#[graphql(name = "Notification")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+    pub read_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,37 @@
+use async_graphql::{self, SimpleObject};
+use sea_orm::entity::prelude::*;
+
+use crate::notification::NotificationKind;
+
+/// Channels a user wants for one kind of notification. Kinds without a row
+/// use every channel.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "notification_preference")]
+#[graphql(name = "NotificationPreference")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    #[graphql(visible = false)]
+    pub user_id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub kind: NotificationKind,
+    pub in_app: bool,
+    pub email: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
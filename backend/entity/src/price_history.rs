@@ -0,0 +1,37 @@
+use async_graphql::{self, SimpleObject};
+use chrono::NaiveDateTime;
+use sea_orm::entity::prelude::*;
+
+use crate::money::Money;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "price_history")]
+#[graphql(name = "PriceHistory")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub advert_id: i32,
+    pub old_price: Money,
+    pub old_currency: String,
+    pub new_price: Money,
+    pub currency: String,
+    pub changed_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::advert::Entity",
+        from = "Column::AdvertId",
+        to = "super::advert::Column::Id"
+    )]
+    Advert,
+}
+
+impl Related<super::advert::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Advert.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,64 @@
+use async_graphql::{self, Enum, SimpleObject};
+use chrono::NaiveDateTime;
+use sea_orm::entity::prelude::*;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Hash, Debug, EnumIter, DeriveActiveEnum)]
+#[sea_orm(enum_name = "promotion_kind", db_type = "Enum", rs_type = "String")]
+pub enum PromotionKind {
+    /// Shown in the promoted slots of the main listing and search.
+    #[sea_orm(string_value = "bump")]
+    Bump,
+    /// Shown in the promoted slots when browsing its category.
+    #[sea_orm(string_value = "featured")]
+    Featured,
+    /// Rendered with a highlighted card wherever it appears.
+    #[sea_orm(string_value = "highlight")]
+    Highlight,
+}
+
+/// A paid or admin-granted promotion of an advert, active between
+/// `starts_at` and `ends_at`.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
+#[sea_orm(table_name = "promotion")]
+#[graphql(name = "Promotion")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub advert_id: i32,
+    pub kind: PromotionKind,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    /// The admin who granted it; `None` for bought promotions.
+    #[graphql(visible = false)]
+    pub granted_by: Option<i32>,
+    #[graphql(visible = false)]
+    pub payment_reference: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::advert::Entity",
+        from = "Column::AdvertId",
+        to = "super::advert::Column::Id"
+    )]
+    Advert,
+}
+
+impl Related<super::advert::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Advert.def()
+    }
+}
+
+impl Entity {
+    /// Promotions running at `now`.
+    pub fn find_active(now: NaiveDateTime) -> Select<Entity> {
+        Self::find()
+            .filter(Column::StartsAt.lte(now))
+            .filter(Column::EndsAt.gt(now))
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,8 +1,17 @@
-use async_graphql::{self, SimpleObject};
+use async_graphql::{self, Enum, SimpleObject};
 use chrono::NaiveDateTime;
 use sea_orm::entity::prelude::*;
 
-
+/// The side of the sale the review author was on: a buyer rates the seller,
+/// a seller rates the buyer.
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, EnumIter, DeriveActiveEnum)]
+#[sea_orm(enum_name = "review_role", db_type = "Enum", rs_type = "String")]
+pub enum ReviewRole {
+    #[sea_orm(string_value = "as_buyer")]
+    AsBuyer,
+    #[sea_orm(string_value = "as_seller")]
+    AsSeller,
+}
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject)]
 #[sea_orm(table_name = "reviews")]
@@ -12,10 +21,19 @@ pub struct Model {
     pub id: i32,
     pub created_at: NaiveDateTime,
     pub user_id: i32,
-    #[sea_orm(unique)]
+    pub subject_id: i32,
+    pub role: ReviewRole,
     pub advert_id: i32,
     pub message: String,
     pub rating: i32,
+    pub edited_at: Option<NaiveDateTime>,
+    /// The reviewed user's public response.
+    pub reply: Option<String>,
+    pub replied_at: Option<NaiveDateTime>,
+    pub hidden: bool,
+    pub hidden_reason: Option<String>,
+    #[graphql(visible = false)]
+    pub hidden_by: Option<i32>,
 
     #[sea_orm(ignore)]
     pub user: super::user::Model,
@@ -35,6 +53,12 @@ pub enum Relation {
         to = "super::user::Column::Id"
     )]
     User,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::SubjectId",
+        to = "super::user::Column::Id"
+    )]
+    Subject,
 }
 
 impl Related<super::advert::Entity> for Entity {
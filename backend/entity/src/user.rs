@@ -1,7 +1,9 @@
-use async_graphql::{self, Enum, SimpleObject};
+use async_graphql::{self, dataloader::DataLoader, ComplexObject, Enum, SimpleObject};
 use chrono::NaiveDateTime;
 use sea_orm::entity::prelude::*;
 
+use crate::loader::{UserAccess, UserAccessLoader};
+
 #[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, EnumIter, DeriveActiveEnum)]
 #[sea_orm(enum_name = "role", db_type = "Enum", rs_type = "String")]
 #[derive(Default)]
@@ -15,9 +17,20 @@ pub enum Role {
     Moderator,
 }
 
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, EnumIter, DeriveActiveEnum)]
+#[sea_orm(enum_name = "account_type", db_type = "Enum", rs_type = "String")]
+#[derive(Default)]
+pub enum AccountType {
+    #[sea_orm(string_value = "private")]
+    #[default]
+    Private,
+    #[sea_orm(string_value = "business")]
+    Business,
+}
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, SimpleObject, Default)]
 #[sea_orm(table_name = "user")]
-#[graphql(name = "User")]
+#[graphql(name = "User", complex)]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
@@ -27,10 +40,15 @@ pub struct Model {
     pub name: Option<String>,
     pub surname: Option<String>,
     pub company_name: Option<String>,
+    #[graphql(skip)]
     #[sea_orm(unique)]
     pub email: Option<String>,
+    #[graphql(skip)]
     pub phone: Option<String>,
+    pub phone_verified: bool,
+    #[graphql(skip)]
     pub email_verified: bool,
+    #[graphql(skip)]
     pub banned: bool,
     #[graphql(visible = false)]
     pub password_hash: Option<String>,
@@ -40,9 +58,74 @@ pub struct Model {
     pub adverts_with_reviews: Vec<super::advert::Model>,
     #[sea_orm(ignore)]
     pub reviewed_adverts: Vec<super::advert::Model>,
-    #[sea_orm(ignore)]
-    pub rating: f32,
+    #[graphql(name = "rating")]
+    pub rating_avg: f32,
+    pub rating_count: i32,
+    #[graphql(name = "buyerRating")]
+    pub buyer_rating_avg: f32,
+    pub buyer_rating_count: i32,
+    #[graphql(skip)]
     pub role: Role,
+    /// Base32 TOTP secret; set during enrolment, before `totp_enabled`.
+    #[graphql(visible = false)]
+    pub totp_secret: Option<String>,
+    #[graphql(visible = false)]
+    pub totp_enabled: bool,
+    /// When a requested account deletion takes effect, unless cancelled first.
+    #[graphql(skip)]
+    pub deletion_scheduled_at: Option<NaiveDateTime>,
+    /// Set once the account has been anonymised.
+    pub deleted_at: Option<NaiveDateTime>,
+    pub account_type: AccountType,
+    /// Custom public profile address; without one the profile is at `user-{id}`.
+    #[sea_orm(unique)]
+    pub slug: Option<String>,
+    pub registration_number: Option<String>,
+    pub vat_number: Option<String>,
+    pub address: Option<String>,
+    pub opening_hours: Option<String>,
+    pub logo_url: Option<String>,
+    /// Granted by an admin after checking the business details.
+    pub business_verified: bool,
+    pub business_verified_at: Option<NaiveDateTime>,
+    /// Lets any verified user reveal the phone number with `revealContact`.
+    /// On by default; when off, only chat and deal partners see it.
+    pub show_phone: bool,
+}
+
+/// What anyone may see of a user; used where users are listed.
+#[derive(Clone, Debug, SimpleObject)]
+#[graphql(name = "PublicUser")]
+pub struct PublicUser {
+    pub id: i32,
+    pub name: Option<String>,
+    pub company_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub logo_url: Option<String>,
+    pub account_type: AccountType,
+    pub business_verified: bool,
+    pub slug: Option<String>,
+    pub rating: f32,
+    pub rating_count: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<Model> for PublicUser {
+    fn from(user: Model) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            company_name: user.company_name,
+            avatar_url: user.avatar_url,
+            logo_url: user.logo_url,
+            account_type: user.account_type,
+            business_verified: user.business_verified,
+            slug: user.slug,
+            rating: user.rating_avg,
+            rating_count: user.rating_count,
+            created_at: user.created_at,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -84,6 +167,73 @@ impl Related<super::chat::Entity> for Entity {
 
 impl ActiveModelBehavior for ActiveModel {}
 
+impl Model {
+    async fn access(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<UserAccess> {
+        // Outside a normal request (e.g. subscriptions) nobody is known to be looking.
+        let Some(loader) = ctx.data_opt::<DataLoader<UserAccessLoader>>() else {
+            return Ok(UserAccess::Public);
+        };
+        Ok(loader
+            .load_one(self.id)
+            .await?
+            .unwrap_or(UserAccess::Public))
+    }
+}
+
+/// Contact details go to the user, admins and their chat or deal partners
+/// (everyone else asks for the phone number with `revealContact`); account
+/// state only to the user and admins.
+#[ComplexObject]
+impl Model {
+    async fn email(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<String>> {
+        Ok(match self.access(ctx).await? {
+            UserAccess::Public => None,
+            _ => self.email.clone(),
+        })
+    }
+
+    async fn phone(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<String>> {
+        Ok(match self.access(ctx).await? {
+            UserAccess::Public => None,
+            _ => self.phone.clone(),
+        })
+    }
+
+    async fn email_verified(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<bool>> {
+        Ok((self.access(ctx).await? == UserAccess::Full).then_some(self.email_verified))
+    }
+
+    async fn banned(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<bool>> {
+        Ok((self.access(ctx).await? == UserAccess::Full).then_some(self.banned))
+    }
+
+    async fn role(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Option<Role>> {
+        Ok((self.access(ctx).await? == UserAccess::Full).then_some(self.role))
+    }
+
+    async fn deletion_scheduled_at(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<NaiveDateTime>> {
+        Ok(match self.access(ctx).await? {
+            UserAccess::Full => self.deletion_scheduled_at,
+            _ => None,
+        })
+    }
+}
+
 impl Entity {
     pub fn find_by_email(email: String) -> Select<Entity> {
         Self::find().filter(Column::Email.eq(email))
@@ -92,4 +242,8 @@ impl Entity {
     pub fn find_by_phone(phone: String) -> Select<Entity> {
         Self::find().filter(Column::Phone.eq(phone))
     }
+
+    pub fn find_by_slug(slug: String) -> Select<Entity> {
+        Self::find().filter(Column::Slug.eq(slug))
+    }
 }
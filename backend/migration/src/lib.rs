@@ -1,6 +1,27 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20241101_000001_advert_lifecycle;
+mod m20241101_000002_price_history;
+mod m20241101_000003_money;
+mod m20241101_000004_user_rating;
+mod m20241101_000005_two_way_reviews;
+mod m20241101_000006_review_moderation;
+mod m20241101_000007_favorite_collections;
+mod m20241101_000008_notifications;
+mod m20241101_000009_push_subscription;
+mod m20241101_000010_job_queue;
+mod m20241101_000011_two_factor;
+mod m20241101_000012_user_identity;
+mod m20241101_000013_phone_verification;
+mod m20241101_000014_account_deletion;
+mod m20241101_000015_business_accounts;
+mod m20241101_000016_show_phone;
+mod m20241101_000017_contact_reveal;
+mod m20241101_000018_advert_stats;
+mod m20241101_000019_promotions;
+mod m20241101_000020_show_phone_default;
+mod m20241101_000021_price_history_currency;
 
 pub struct Migrator;
 
@@ -9,6 +30,27 @@ impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
         vec![
             Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20241101_000001_advert_lifecycle::Migration),
+            Box::new(m20241101_000002_price_history::Migration),
+            Box::new(m20241101_000003_money::Migration),
+            Box::new(m20241101_000004_user_rating::Migration),
+            Box::new(m20241101_000005_two_way_reviews::Migration),
+            Box::new(m20241101_000006_review_moderation::Migration),
+            Box::new(m20241101_000007_favorite_collections::Migration),
+            Box::new(m20241101_000008_notifications::Migration),
+            Box::new(m20241101_000009_push_subscription::Migration),
+            Box::new(m20241101_000010_job_queue::Migration),
+            Box::new(m20241101_000011_two_factor::Migration),
+            Box::new(m20241101_000012_user_identity::Migration),
+            Box::new(m20241101_000013_phone_verification::Migration),
+            Box::new(m20241101_000014_account_deletion::Migration),
+            Box::new(m20241101_000015_business_accounts::Migration),
+            Box::new(m20241101_000016_show_phone::Migration),
+            Box::new(m20241101_000017_contact_reveal::Migration),
+            Box::new(m20241101_000018_advert_stats::Migration),
+            Box::new(m20241101_000019_promotions::Migration),
+            Box::new(m20241101_000020_show_phone_default::Migration),
+            Box::new(m20241101_000021_price_history_currency::Migration),
         ]
     }
 }
@@ -0,0 +1,119 @@
+use sea_orm::{ActiveEnum, DbBackend, DeriveActiveEnum, EnumIter, Schema};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(DbBackend::Postgres);
+
+        manager
+            .create_type(schema.create_enum_from_active_enum::<AdvertStatus>())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Advert::Table)
+                    .add_column(
+                        ColumnDef::new(Advert::Status)
+                            .custom(AdvertStatus::name())
+                            .not_null()
+                            .default(Expr::value("active")),
+                    )
+                    .add_column(ColumnDef::new(Advert::ExpiresAt).date_time().null())
+                    .add_column(ColumnDef::new(Advert::ExpiryNotifiedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Sold adverts keep their buyer, adverts taken off the market by an accepted
+        // deal are reserved, everything else is live for another 30 days.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE advert SET status = CASE \
+                    WHEN sold_to IS NOT NULL THEN 'sold'::advert_status \
+                    WHEN available THEN 'active'::advert_status \
+                    ELSE 'reserved'::advert_status \
+                 END",
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE advert SET expires_at = GREATEST(updated_at, CURRENT_TIMESTAMP) + INTERVAL '30 days' \
+                 WHERE status = 'active'",
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-advert-status-expires_at")
+                    .table(Advert::Table)
+                    .col(Advert::Status)
+                    .col(Advert::ExpiresAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-advert-status-expires_at")
+                    .table(Advert::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Advert::Table)
+                    .drop_column(Advert::Status)
+                    .drop_column(Advert::ExpiresAt)
+                    .drop_column(Advert::ExpiryNotifiedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(Alias::new("advert_status")).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    Status,
+    ExpiresAt,
+    ExpiryNotifiedAt,
+}
+
+#[derive(EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "advert_status")]
+enum AdvertStatus {
+    #[sea_orm(string_value = "draft")]
+    Draft,
+    #[sea_orm(string_value = "active")]
+    Active,
+    #[sea_orm(string_value = "reserved")]
+    Reserved,
+    #[sea_orm(string_value = "sold")]
+    Sold,
+    #[sea_orm(string_value = "expired")]
+    Expired,
+    #[sea_orm(string_value = "archived")]
+    Archived,
+}
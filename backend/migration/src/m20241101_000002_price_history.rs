@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PriceHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PriceHistory::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PriceHistory::AdvertId).integer().not_null())
+                    .col(ColumnDef::new(PriceHistory::OldPrice).float().not_null())
+                    .col(ColumnDef::new(PriceHistory::NewPrice).float().not_null())
+                    .col(
+                        ColumnDef::new(PriceHistory::ChangedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-price_history-advert_id")
+                            .from(PriceHistory::Table, PriceHistory::AdvertId)
+                            .to(Advert::Table, Advert::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-price_history-advert_id")
+                    .table(PriceHistory::Table)
+                    .col(PriceHistory::AdvertId)
+                    .col(PriceHistory::ChangedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PriceHistory::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PriceHistory {
+    Table,
+    Id,
+    AdvertId,
+    OldPrice,
+    NewPrice,
+    ChangedAt,
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    Id,
+}
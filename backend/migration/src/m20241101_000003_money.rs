@@ -0,0 +1,152 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Floats cannot represent cents exactly; round once while converting.
+        db.execute_unprepared(
+            "ALTER TABLE advert \
+                ALTER COLUMN price TYPE NUMERIC(14, 2) USING ROUND(price::numeric, 2), \
+                ALTER COLUMN old_price TYPE NUMERIC(14, 2) USING ROUND(old_price::numeric, 2)",
+        )
+        .await?;
+        db.execute_unprepared(
+            "ALTER TABLE deal \
+                ALTER COLUMN price TYPE NUMERIC(14, 2) USING ROUND(price::numeric, 2)",
+        )
+        .await?;
+        db.execute_unprepared(
+            "ALTER TABLE price_history \
+                ALTER COLUMN old_price TYPE NUMERIC(14, 2) USING ROUND(old_price::numeric, 2), \
+                ALTER COLUMN new_price TYPE NUMERIC(14, 2) USING ROUND(new_price::numeric, 2)",
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Advert::Table)
+                    .add_column(
+                        ColumnDef::new(Advert::Currency)
+                            .string_len(3)
+                            .not_null()
+                            .default("EUR"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Deal::Table)
+                    .add_column(
+                        ColumnDef::new(Deal::Currency)
+                            .string_len(3)
+                            .not_null()
+                            .default("EUR"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExchangeRate::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ExchangeRate::Currency)
+                            .string_len(3)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ExchangeRate::Rate)
+                            .decimal_len(18, 8)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ExchangeRate::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("CURRENT_TIMESTAMP")),
+                    )
+                    .check(Expr::cust("rate > 0"))
+                    .to_owned(),
+            )
+            .await?;
+
+        db.execute_unprepared("INSERT INTO exchange_rate (currency, rate) VALUES ('EUR', 1)")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        manager
+            .drop_table(Table::drop().table(ExchangeRate::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Deal::Table)
+                    .drop_column(Deal::Currency)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Advert::Table)
+                    .drop_column(Advert::Currency)
+                    .to_owned(),
+            )
+            .await?;
+
+        db.execute_unprepared(
+            "ALTER TABLE price_history \
+                ALTER COLUMN old_price TYPE REAL, \
+                ALTER COLUMN new_price TYPE REAL",
+        )
+        .await?;
+        db.execute_unprepared("ALTER TABLE deal ALTER COLUMN price TYPE REAL")
+            .await?;
+        db.execute_unprepared(
+            "ALTER TABLE advert \
+                ALTER COLUMN price TYPE REAL, \
+                ALTER COLUMN old_price TYPE REAL",
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    Currency,
+}
+
+#[derive(DeriveIden)]
+enum Deal {
+    Table,
+    Currency,
+}
+
+#[derive(DeriveIden)]
+enum ExchangeRate {
+    Table,
+    Currency,
+    Rate,
+    UpdatedAt,
+}
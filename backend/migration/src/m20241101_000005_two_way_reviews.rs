@@ -0,0 +1,221 @@
+use sea_orm::{ActiveEnum, DbBackend, DeriveActiveEnum, EnumIter, Schema};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(DbBackend::Postgres);
+
+        manager
+            .create_type(schema.create_enum_from_active_enum::<ReviewRole>())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Reviews::Table)
+                    .add_column(ColumnDef::new(Reviews::SubjectId).integer().null())
+                    .add_column(
+                        ColumnDef::new(Reviews::Role)
+                            .custom(ReviewRole::name())
+                            .not_null()
+                            .default(Expr::value("as_buyer")),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every existing review was written by the buyer about the seller.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE reviews SET subject_id = advert.user_id \
+                 FROM advert WHERE advert.id = reviews.advert_id",
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE reviews \
+                    ALTER COLUMN subject_id SET NOT NULL, \
+                    ALTER COLUMN role DROP DEFAULT, \
+                    DROP CONSTRAINT IF EXISTS reviews_advert_id_key",
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk-reviews-subject_id")
+                    .from(Reviews::Table, Reviews::SubjectId)
+                    .to(User::Table, User::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-reviews-advert_id-user_id-subject_id")
+                    .table(Reviews::Table)
+                    .col(Reviews::AdvertId)
+                    .col(Reviews::UserId)
+                    .col(Reviews::SubjectId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-reviews-subject_id-role")
+                    .table(Reviews::Table)
+                    .col(Reviews::SubjectId)
+                    .col(Reviews::Role)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(User::BuyerRatingAvg)
+                            .float()
+                            .not_null()
+                            .default(0.0),
+                    )
+                    .add_column(
+                        ColumnDef::new(User::BuyerRatingCount)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Advert::Table)
+                    .add_column(ColumnDef::new(Advert::SoldAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared("UPDATE advert SET sold_at = updated_at WHERE status = 'sold'")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Advert::Table)
+                    .drop_column(Advert::SoldAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::BuyerRatingAvg)
+                    .drop_column(User::BuyerRatingCount)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Only one review per advert survives going back to the old schema.
+        manager
+            .get_connection()
+            .execute_unprepared("DELETE FROM reviews WHERE role = 'as_seller'")
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-reviews-subject_id-role")
+                    .table(Reviews::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-reviews-advert_id-user_id-subject_id")
+                    .table(Reviews::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Reviews::Table)
+                    .drop_column(Reviews::SubjectId)
+                    .drop_column(Reviews::Role)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "ALTER TABLE reviews ADD CONSTRAINT reviews_advert_id_key UNIQUE (advert_id)",
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(Alias::new("review_role")).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reviews {
+    Table,
+    AdvertId,
+    UserId,
+    SubjectId,
+    Role,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    BuyerRatingAvg,
+    BuyerRatingCount,
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    SoldAt,
+}
+
+#[derive(EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "review_role")]
+enum ReviewRole {
+    #[sea_orm(string_value = "as_buyer")]
+    AsBuyer,
+    #[sea_orm(string_value = "as_seller")]
+    AsSeller,
+}
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Reviews::Table)
+                    .add_column(ColumnDef::new(Reviews::EditedAt).date_time().null())
+                    .add_column(ColumnDef::new(Reviews::Reply).string().null())
+                    .add_column(ColumnDef::new(Reviews::RepliedAt).date_time().null())
+                    .add_column(
+                        ColumnDef::new(Reviews::Hidden)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .add_column(ColumnDef::new(Reviews::HiddenReason).string().null())
+                    .add_column(ColumnDef::new(Reviews::HiddenBy).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-reviews-hidden_by")
+                            .from_tbl(Reviews::Table)
+                            .from_col(Reviews::HiddenBy)
+                            .to_tbl(User::Table)
+                            .to_col(User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Reviews::Table)
+                    .drop_foreign_key(Alias::new("fk-reviews-hidden_by"))
+                    .drop_column(Reviews::EditedAt)
+                    .drop_column(Reviews::Reply)
+                    .drop_column(Reviews::RepliedAt)
+                    .drop_column(Reviews::Hidden)
+                    .drop_column(Reviews::HiddenReason)
+                    .drop_column(Reviews::HiddenBy)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reviews {
+    Table,
+    EditedAt,
+    Reply,
+    RepliedAt,
+    Hidden,
+    HiddenReason,
+    HiddenBy,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,146 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FavoriteCollection::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FavoriteCollection::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(FavoriteCollection::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(FavoriteCollection::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(FavoriteCollection::ShareToken)
+                            .string()
+                            .null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(FavoriteCollection::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-favorite_collection-user_id")
+                            .from(FavoriteCollection::Table, FavoriteCollection::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Keep the oldest row of any duplicated (user, advert) pair before
+        // the unique index goes on.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "DELETE FROM favorites f USING favorites d \
+                 WHERE f.user_id = d.user_id AND f.advert_id = d.advert_id AND f.id > d.id",
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Favorites::Table)
+                    .add_column(ColumnDef::new(Favorites::CollectionId).integer().null())
+                    .add_column(ColumnDef::new(Favorites::Note).string().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-favorites-collection_id")
+                            .from_tbl(Favorites::Table)
+                            .from_col(Favorites::CollectionId)
+                            .to_tbl(FavoriteCollection::Table)
+                            .to_col(FavoriteCollection::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-favorites-user_id-advert_id")
+                    .table(Favorites::Table)
+                    .col(Favorites::UserId)
+                    .col(Favorites::AdvertId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-favorites-user_id-advert_id")
+                    .table(Favorites::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Favorites::Table)
+                    .drop_foreign_key(Alias::new("fk-favorites-collection_id"))
+                    .drop_column(Favorites::CollectionId)
+                    .drop_column(Favorites::Note)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(FavoriteCollection::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum FavoriteCollection {
+    Table,
+    Id,
+    UserId,
+    Name,
+    ShareToken,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Favorites {
+    Table,
+    UserId,
+    AdvertId,
+    CollectionId,
+    Note,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
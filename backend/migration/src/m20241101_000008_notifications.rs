@@ -0,0 +1,213 @@
+use sea_orm::{ActiveEnum, DbBackend, DeriveActiveEnum, EnumIter, Schema};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(DbBackend::Postgres);
+
+        manager
+            .create_type(schema.create_enum_from_active_enum::<NotificationKind>())
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Notification::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Notification::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Notification::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(Notification::Kind)
+                            .custom(NotificationKind::name())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Notification::Title).string().not_null())
+                    .col(ColumnDef::new(Notification::Body).text().not_null())
+                    .col(ColumnDef::new(Notification::Link).string().null())
+                    .col(ColumnDef::new(Notification::ReadAt).date_time().null())
+                    .col(
+                        ColumnDef::new(Notification::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-notification-user_id")
+                            .from(Notification::Table, Notification::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-notification-user_id-created_at")
+                    .table(Notification::Table)
+                    .col(Notification::UserId)
+                    .col(Notification::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(NotificationPreference::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(NotificationPreference::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationPreference::Kind)
+                            .custom(NotificationKind::name())
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationPreference::InApp)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationPreference::Email)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(NotificationPreference::UserId)
+                            .col(NotificationPreference::Kind),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-notification_preference-user_id")
+                            .from(
+                                NotificationPreference::Table,
+                                NotificationPreference::UserId,
+                            )
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Both the backend and the chat service insert notifications; the
+        // backend LISTENs on this channel to feed the subscription stream.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE OR REPLACE FUNCTION notification_inserted() RETURNS trigger AS $$ \
+                 BEGIN \
+                    PERFORM pg_notify('notification', NEW.id::text); \
+                    RETURN NEW; \
+                 END; \
+                 $$ LANGUAGE plpgsql",
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE TRIGGER notification_inserted AFTER INSERT ON notification \
+                 FOR EACH ROW EXECUTE FUNCTION notification_inserted()",
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP TRIGGER IF EXISTS notification_inserted ON notification")
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared("DROP FUNCTION IF EXISTS notification_inserted()")
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(NotificationPreference::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Notification::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(
+                Type::drop()
+                    .name(Alias::new("notification_kind"))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Notification {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    Title,
+    Body,
+    Link,
+    ReadAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum NotificationPreference {
+    Table,
+    UserId,
+    Kind,
+    InApp,
+    Email,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "notification_kind")]
+enum NotificationKind {
+    #[sea_orm(string_value = "new_message")]
+    NewMessage,
+    #[sea_orm(string_value = "deal_offer")]
+    DealOffer,
+    #[sea_orm(string_value = "review_received")]
+    ReviewReceived,
+    #[sea_orm(string_value = "price_drop")]
+    PriceDrop,
+    #[sea_orm(string_value = "advert_expiring")]
+    AdvertExpiring,
+}
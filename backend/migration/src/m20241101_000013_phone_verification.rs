@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(User::PhoneVerified)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Strip the formatting people typed; anything that still isn't E.164
+        // gets normalised the next time its owner edits their profile.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE "user" SET phone = NULLIF(regexp_replace(phone, '[\s().-]', '', 'g'), '')
+                WHERE phone IS NOT NULL"#,
+            )
+            .await?;
+
+        // A verified number can log its owner in, so it may belong to one account only.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"CREATE UNIQUE INDEX "idx-user-verified_phone" ON "user" (phone) WHERE phone_verified"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(r#"DROP INDEX IF EXISTS "idx-user-verified_phone""#)
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::PhoneVerified)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    PhoneVerified,
+}
@@ -0,0 +1,132 @@
+use sea_orm::{ActiveEnum, DbBackend, DeriveActiveEnum, EnumIter, Schema};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(DbBackend::Postgres);
+
+        manager
+            .create_type(schema.create_enum_from_active_enum::<AccountType>())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(User::AccountType)
+                            .custom(AccountType::name())
+                            .not_null()
+                            .default(Expr::value("private")),
+                    )
+                    .add_column(ColumnDef::new(User::Slug).string().null())
+                    .add_column(ColumnDef::new(User::RegistrationNumber).string().null())
+                    .add_column(ColumnDef::new(User::VatNumber).string().null())
+                    .add_column(ColumnDef::new(User::Address).string().null())
+                    .add_column(ColumnDef::new(User::OpeningHours).string().null())
+                    .add_column(ColumnDef::new(User::LogoUrl).string().null())
+                    .add_column(
+                        ColumnDef::new(User::BusinessVerified)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .add_column(ColumnDef::new(User::BusinessVerifiedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user-slug")
+                    .table(User::Table)
+                    .col(User::Slug)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user-account_type")
+                    .table(User::Table)
+                    .col(User::AccountType)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-user-account_type")
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-user-slug")
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::AccountType)
+                    .drop_column(User::Slug)
+                    .drop_column(User::RegistrationNumber)
+                    .drop_column(User::VatNumber)
+                    .drop_column(User::Address)
+                    .drop_column(User::OpeningHours)
+                    .drop_column(User::LogoUrl)
+                    .drop_column(User::BusinessVerified)
+                    .drop_column(User::BusinessVerifiedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(Alias::new("account_type")).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    AccountType,
+    Slug,
+    RegistrationNumber,
+    VatNumber,
+    Address,
+    OpeningHours,
+    LogoUrl,
+    BusinessVerified,
+    BusinessVerifiedAt,
+}
+
+#[derive(EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "account_type")]
+enum AccountType {
+    #[sea_orm(string_value = "private")]
+    Private,
+    #[sea_orm(string_value = "business")]
+    Business,
+}
@@ -0,0 +1,120 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ContactReveal::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ContactReveal::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ContactReveal::AdvertId).integer().not_null())
+                    .col(ColumnDef::new(ContactReveal::SellerId).integer().not_null())
+                    .col(ColumnDef::new(ContactReveal::ViewerId).integer().not_null())
+                    .col(
+                        ColumnDef::new(ContactReveal::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-contact_reveal-advert_id")
+                            .from(ContactReveal::Table, ContactReveal::AdvertId)
+                            .to(Advert::Table, Advert::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-contact_reveal-seller_id")
+                            .from(ContactReveal::Table, ContactReveal::SellerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-contact_reveal-viewer_id")
+                            .from(ContactReveal::Table, ContactReveal::ViewerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // One row per viewer and advert, however often they look.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-contact_reveal-advert_id-viewer_id")
+                    .table(ContactReveal::Table)
+                    .col(ContactReveal::AdvertId)
+                    .col(ContactReveal::ViewerId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-contact_reveal-seller_id")
+                    .table(ContactReveal::Table)
+                    .col(ContactReveal::SellerId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-contact_reveal-viewer_id-created_at")
+                    .table(ContactReveal::Table)
+                    .col(ContactReveal::ViewerId)
+                    .col(ContactReveal::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ContactReveal::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum ContactReveal {
+    Table,
+    Id,
+    AdvertId,
+    SellerId,
+    ViewerId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,94 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AdvertStatsDaily::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AdvertStatsDaily::AdvertId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AdvertStatsDaily::Day).date().not_null())
+                    .col(
+                        ColumnDef::new(AdvertStatsDaily::Views)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(AdvertStatsDaily::AdvertId)
+                            .col(AdvertStatsDaily::Day),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-advert_stats_daily-advert_id")
+                            .from(AdvertStatsDaily::Table, AdvertStatsDaily::AdvertId)
+                            .to(Advert::Table, Advert::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Chat starts per day are counted from `chat.created_at`.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-chat-advert_id-created_at")
+                    .table(Chat::Table)
+                    .col(Chat::AdvertId)
+                    .col(Chat::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-chat-advert_id-created_at")
+                    .table(Chat::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AdvertStatsDaily::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AdvertStatsDaily {
+    Table,
+    AdvertId,
+    Day,
+    Views,
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Chat {
+    Table,
+    AdvertId,
+    CreatedAt,
+}
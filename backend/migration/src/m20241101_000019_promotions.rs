@@ -0,0 +1,134 @@
+use sea_orm::{ActiveEnum, DbBackend, DeriveActiveEnum, EnumIter, Schema};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(DbBackend::Postgres);
+
+        manager
+            .create_type(schema.create_enum_from_active_enum::<PromotionKind>())
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Promotion::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Promotion::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Promotion::AdvertId).integer().not_null())
+                    .col(
+                        ColumnDef::new(Promotion::Kind)
+                            .custom(PromotionKind::name())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Promotion::StartsAt).date_time().not_null())
+                    .col(ColumnDef::new(Promotion::EndsAt).date_time().not_null())
+                    .col(ColumnDef::new(Promotion::GrantedBy).integer().null())
+                    .col(ColumnDef::new(Promotion::PaymentReference).string().null())
+                    .col(
+                        ColumnDef::new(Promotion::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("CURRENT_TIMESTAMP")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-promotion-advert_id")
+                            .from(Promotion::Table, Promotion::AdvertId)
+                            .to(Advert::Table, Advert::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-promotion-granted_by")
+                            .from(Promotion::Table, Promotion::GrantedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-promotion-advert_id")
+                    .table(Promotion::Table)
+                    .col(Promotion::AdvertId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Listings look up what is running right now.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-promotion-kind-ends_at")
+                    .table(Promotion::Table)
+                    .col(Promotion::Kind)
+                    .col(Promotion::EndsAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Promotion::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(Alias::new("promotion_kind")).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Promotion {
+    Table,
+    Id,
+    AdvertId,
+    Kind,
+    StartsAt,
+    EndsAt,
+    GrantedBy,
+    PaymentReference,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Advert {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "promotion_kind")]
+enum PromotionKind {
+    #[sea_orm(string_value = "bump")]
+    Bump,
+    #[sea_orm(string_value = "featured")]
+    Featured,
+    #[sea_orm(string_value = "highlight")]
+    Highlight,
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PriceHistory::Table)
+                    .add_column(
+                        ColumnDef::new(PriceHistory::OldCurrency)
+                            .string_len(3)
+                            .not_null()
+                            .default("EUR"),
+                    )
+                    .add_column(
+                        ColumnDef::new(PriceHistory::Currency)
+                            .string_len(3)
+                            .not_null()
+                            .default("EUR"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Earlier rows were written without a currency; the advert's current one
+        // is the best guess.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE price_history SET old_currency = advert.currency, currency = advert.currency \
+                 FROM advert WHERE advert.id = price_history.advert_id",
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PriceHistory::Table)
+                    .drop_column(PriceHistory::OldCurrency)
+                    .drop_column(PriceHistory::Currency)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PriceHistory {
+    Table,
+    OldCurrency,
+    Currency,
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Write},
+};
+
+use chrono::Utc;
+use deadpool_redis::{redis::cmd, Pool};
+use entity::{
+    advert::{self, AdvertStatus, Entity as Advert},
+    chat::{self, Entity as Chat},
+    contact_reveal::{self, Entity as ContactReveal},
+    favorite_collection::{self, Entity as FavoriteCollection},
+    favorites::{self, Entity as Favorites},
+    message::{self, Entity as Message},
+    notification::{self, Entity as Notification},
+    notification_preference::{self, Entity as NotificationPreference},
+    push_subscription::{self, Entity as PushSubscription},
+    recovery_code::{self, Entity as RecoveryCode},
+    reviews::{self, Entity as Reviews},
+    specifications::{self, Entity as Specifications},
+    user::{self, AccountType, Entity as User},
+    user_identity::{self, Entity as UserIdentity},
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QuerySelect, Set, TransactionTrait,
+};
+use serde_json::Value;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Shown in place of an anonymised account's name, e.g. on its reviews.
+pub const DELETED_USER_NAME: &str = "Deleted user";
+
+/// Logs the user out everywhere by dropping their refresh token, and drops a
+/// pending email change.
+async fn revoke_sessions(redis_pool: &Pool, user_id: i32) -> Result<(), String> {
+    let mut conn = redis_pool.get().await.map_err(|e| e.to_string())?;
+    cmd("DEL")
+        .arg(user_id.to_string())
+        .arg(format!("email_change:{}", user_id))
+        .query_async::<()>(&mut conn)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Strips everything personal from an account whose deletion is due. The row
+/// itself stays, so reviews, deals and chats still point at a "Deleted user".
+pub async fn anonymise_user(
+    db: &DatabaseConnection,
+    redis_pool: &Pool,
+    user_id: i32,
+) -> Result<(), DbErr> {
+    let Some(user) = User::find_by_id(user_id).one(db).await? else {
+        return Ok(());
+    };
+
+    let now = Utc::now().naive_utc();
+    // Cancelled, or requested again later, since this job was queued.
+    if user.deleted_at.is_some() || user.deletion_scheduled_at.is_none_or(|at| at > now) {
+        return Ok(());
+    }
+
+    // Before anything else, so a retry after a failure below still signs out.
+    revoke_sessions(redis_pool, user_id)
+        .await
+        .map_err(|e| DbErr::Custom(format!("Failed to revoke sessions: {}", e)))?;
+
+    let txn = db.begin().await?;
+
+    Advert::update_many()
+        .col_expr(
+            advert::Column::Status,
+            advert::Column::Status.save_as(Expr::val(AdvertStatus::Archived)),
+        )
+        .col_expr(advert::Column::Available, Expr::value(false))
+        .col_expr(advert::Column::UpdatedAt, Expr::value(now))
+        .filter(advert::Column::UserId.eq(user_id))
+        .filter(advert::Column::Status.ne(AdvertStatus::Sold))
+        .exec(&txn)
+        .await?;
+
+    Favorites::delete_many()
+        .filter(favorites::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    FavoriteCollection::delete_many()
+        .filter(favorite_collection::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    Notification::delete_many()
+        .filter(notification::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    NotificationPreference::delete_many()
+        .filter(notification_preference::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    PushSubscription::delete_many()
+        .filter(push_subscription::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    RecoveryCode::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    UserIdentity::delete_many()
+        .filter(user_identity::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    ContactReveal::delete_many()
+        .filter(contact_reveal::Column::ViewerId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    user::ActiveModel {
+        avatar_url: Set(None),
+        name: Set(Some(DELETED_USER_NAME.to_string())),
+        surname: Set(None),
+        company_name: Set(None),
+        email: Set(None),
+        phone: Set(None),
+        phone_verified: Set(false),
+        email_verified: Set(false),
+        password_hash: Set(None),
+        totp_secret: Set(None),
+        totp_enabled: Set(false),
+        account_type: Set(AccountType::Private),
+        slug: Set(None),
+        registration_number: Set(None),
+        vat_number: Set(None),
+        address: Set(None),
+        opening_hours: Set(None),
+        logo_url: Set(None),
+        business_verified: Set(false),
+        business_verified_at: Set(None),
+        show_phone: Set(false),
+        deletion_scheduled_at: Set(None),
+        deleted_at: Set(Some(now)),
+        updated_at: Set(now),
+        ..user.into()
+    }
+    .update(&txn)
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// Everything stored about a user, keyed by section. Secrets (password and
+/// TOTP material, recovery codes) are left out.
+pub async fn export_user_data(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<BTreeMap<&'static str, Value>, DbErr> {
+    let mut data = BTreeMap::new();
+
+    let mut profile = User::find_by_id(user_id)
+        .into_json()
+        .one(db)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("user {}", user_id)))?;
+    if let Value::Object(fields) = &mut profile {
+        fields.remove("password_hash");
+        fields.remove("totp_secret");
+    }
+    data.insert("profile", profile);
+
+    let advert_ids: Vec<i32> = Advert::find()
+        .select_only()
+        .column(advert::Column::Id)
+        .filter(advert::Column::UserId.eq(user_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+    let chat_ids: Vec<i32> = Chat::find()
+        .select_only()
+        .column(chat::Column::Id)
+        .filter(
+            Condition::any()
+                .add(chat::Column::ParticipantId.eq(user_id))
+                .add(chat::Column::AdvertId.is_in(advert_ids.clone())),
+        )
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    data.insert(
+        "identities",
+        Value::Array(
+            UserIdentity::find()
+                .filter(user_identity::Column::UserId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "adverts",
+        Value::Array(
+            Advert::find()
+                .filter(advert::Column::UserId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "specifications",
+        Value::Array(
+            Specifications::find()
+                .filter(specifications::Column::AdvertId.is_in(advert_ids))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "favorite_collections",
+        Value::Array(
+            FavoriteCollection::find()
+                .filter(favorite_collection::Column::UserId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "favorites",
+        Value::Array(
+            Favorites::find()
+                .filter(favorites::Column::UserId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "reviews_written",
+        Value::Array(
+            Reviews::find()
+                .filter(reviews::Column::UserId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "reviews_received",
+        Value::Array(
+            Reviews::find()
+                .filter(reviews::Column::SubjectId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "contact_reveals",
+        Value::Array(
+            ContactReveal::find()
+                .filter(contact_reveal::Column::ViewerId.eq(user_id))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "chats",
+        Value::Array(
+            Chat::find()
+                .filter(chat::Column::Id.is_in(chat_ids.clone()))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+    data.insert(
+        "messages",
+        Value::Array(
+            Message::find()
+                .filter(message::Column::ChatId.is_in(chat_ids))
+                .into_json()
+                .all(db)
+                .await?,
+        ),
+    );
+
+    Ok(data)
+}
+
+/// The whole export as one JSON document.
+pub fn export_to_json(data: &BTreeMap<&'static str, Value>) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(data).map_err(|e| e.to_string())
+}
+
+/// The export as a ZIP archive with one `<section>.json` file per section.
+pub fn export_to_zip(data: &BTreeMap<&'static str, Value>) -> Result<Vec<u8>, String> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (section, value) in data {
+        zip.start_file(format!("{}.json", section), options)
+            .map_err(|e| e.to_string())?;
+        let contents = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+        zip.write_all(&contents).map_err(|e| e.to_string())?;
+    }
+
+    Ok(zip.finish().map_err(|e| e.to_string())?.into_inner())
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use hmac::Hmac;
+use jwt::SignWithKey;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::mail::escape_html;
+
+/// An email with a signed account link. The job queue stores only what is
+/// needed to sign the link, so it never holds a usable token.
+pub struct Email {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+fn sign(email_key: &Hmac<Sha256>, claims: BTreeMap<&str, Value>) -> Result<String, String> {
+    claims.sign_with_key(email_key).map_err(|e| e.to_string())
+}
+
+pub fn verification(
+    email_key: &Hmac<Sha256>,
+    email: &str,
+    recipient: &str,
+    exp: usize,
+) -> Result<Email, String> {
+    let mut claims = BTreeMap::new();
+    claims.insert("sub", json!("someone"));
+    claims.insert("email", json!(email));
+    claims.insert("exp", json!(exp));
+    let link = format!(
+        "https://ad-ee.tech/verify_email/{}",
+        sign(email_key, claims)?
+    );
+
+    Ok(Email {
+        subject: "You are awesome!".to_string(),
+        text: format!(
+            "Hi {}, here is your verification link: {}",
+            recipient, link
+        ),
+        html: format!(
+            "<p>Hi {},</p><p>Here is your verification link:</p><p><a href=\"{}\">Verify Email</a></p>",
+            escape_html(recipient),
+            link
+        ),
+    })
+}
+
+/// `token_id` is kept in Redis until the link is used, so it works once.
+pub fn password_reset(
+    email_key: &Hmac<Sha256>,
+    email: &str,
+    token_id: &str,
+    exp: usize,
+) -> Result<Email, String> {
+    let mut claims = BTreeMap::new();
+    claims.insert("sub", json!("reset"));
+    claims.insert("email", json!(email));
+    claims.insert("jti", json!(token_id));
+    claims.insert("exp", json!(exp));
+    let link = format!("https://ad-ee.tech/reset/{}", sign(email_key, claims)?);
+
+    Ok(Email {
+        subject: "Reset your password".to_string(),
+        text: format!("Click here to reset your password: {}", link),
+        html: format!(
+            "<p>Click <a href=\"{}\">here</a> to reset your password.</p>",
+            link
+        ),
+    })
+}
+
+/// Sent to the new address; only the latest `change_id` is honoured.
+pub fn email_change_confirmation(
+    email_key: &Hmac<Sha256>,
+    user_id: i32,
+    new_email: &str,
+    change_id: &str,
+    exp: usize,
+) -> Result<Email, String> {
+    let mut claims = BTreeMap::new();
+    claims.insert("sub", json!("email_change"));
+    claims.insert("id", json!(user_id));
+    claims.insert("email", json!(new_email));
+    claims.insert("change", json!(change_id));
+    claims.insert("exp", json!(exp));
+    let link = format!(
+        "https://ad-ee.tech/confirm_email_change/{}",
+        sign(email_key, claims)?
+    );
+
+    Ok(Email {
+        subject: "Confirm your new email".to_string(),
+        text: format!(
+            "Confirm that you want to use this address for your Adee account: {}",
+            link
+        ),
+        html: format!(
+            "<p>Confirm that you want to use this address for your Adee account.</p><p><a href=\"{}\">Confirm email</a></p>",
+            link
+        ),
+    })
+}
+
+/// Sent to the old address with a link that cancels the change.
+pub fn email_change_notice(
+    email_key: &Hmac<Sha256>,
+    user_id: i32,
+    new_email: &str,
+    change_id: &str,
+    exp: usize,
+) -> Result<Email, String> {
+    let mut claims = BTreeMap::new();
+    claims.insert("sub", json!("email_change_cancel"));
+    claims.insert("id", json!(user_id));
+    claims.insert("change", json!(change_id));
+    claims.insert("exp", json!(exp));
+    let link = format!(
+        "https://ad-ee.tech/cancel_email_change/{}",
+        sign(email_key, claims)?
+    );
+
+    Ok(Email {
+        subject: "Your email is about to change".to_string(),
+        text: format!(
+            "Someone asked to move your Adee account to {}. If this wasn't you, cancel it and change your password: {}",
+            new_email, link
+        ),
+        html: format!(
+            "<p>Someone asked to move your Adee account to {}.</p><p>If this wasn't you, <a href=\"{}\">cancel the change</a> and change your password.</p>",
+            escape_html(new_email),
+            link
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hmac::Mac;
+    use jwt::VerifyWithKey;
+
+    #[test]
+    fn reset_link_carries_a_signed_token() {
+        let key = Hmac::<Sha256>::new_from_slice(b"email key").unwrap();
+        let email = password_reset(&key, "user@example.com", "token-id", 4_000_000_000).unwrap();
+
+        let token = email.text.rsplit("/reset/").next().unwrap().to_string();
+        let claims: BTreeMap<String, Value> = token.verify_with_key(&key).unwrap();
+        assert_eq!(claims["sub"], "reset");
+        assert_eq!(claims["email"], "user@example.com");
+        assert_eq!(claims["jti"], "token-id");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    account::{export_to_json, export_to_zip, export_user_data},
+    jobs::{enqueue, enqueue_at, Task},
+    rate_limit::{self, AuthOperation},
+    user_id_from_token, Context,
+};
+
+use actix_web::Result;
+use async_graphql::{Enum, Object, SimpleObject};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use entity::user::{self, Entity as User};
+use sea_orm::{ActiveModelTrait, EntityTrait, Set, TransactionTrait};
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    /// One JSON file per section.
+    Zip,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "DataExport")]
+pub struct DataExport {
+    filename: String,
+    content_type: String,
+    /// The file, base64-encoded.
+    data: String,
+}
+
+async fn find_user(my_ctx: &Context, user_id: i32) -> Result<user::Model, async_graphql::Error> {
+    User::find_by_id(user_id)
+        .one(&my_ctx.db)
+        .await?
+        .filter(|user| user.deleted_at.is_none())
+        .ok_or_else(|| async_graphql::Error::new("Wrong token"))
+}
+
+#[derive(Default)]
+pub struct AccountQuery;
+
+#[Object]
+impl AccountQuery {
+    /// A copy of everything stored about the caller.
+    async fn export_my_data(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        #[graphql(default_with = "ExportFormat::Json")] format: ExportFormat,
+    ) -> Result<DataExport, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let data = export_user_data(&my_ctx.db, user.id).await?;
+        let date = Utc::now().format("%Y-%m-%d");
+
+        let (filename, content_type, bytes) = match format {
+            ExportFormat::Json => (
+                format!("adee-export-{}-{}.json", user.id, date),
+                "application/json",
+                export_to_json(&data).map_err(async_graphql::Error::new)?,
+            ),
+            ExportFormat::Zip => (
+                format!("adee-export-{}-{}.zip", user.id, date),
+                "application/zip",
+                export_to_zip(&data).map_err(async_graphql::Error::new)?,
+            ),
+        };
+
+        Ok(DataExport {
+            filename,
+            content_type: content_type.to_string(),
+            data: STANDARD.encode(bytes),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct AccountMutation;
+
+#[Object]
+impl AccountMutation {
+    /// Schedules the caller's account to be anonymised once the grace period
+    /// is over. Accounts with a password have to confirm it.
+    async fn request_account_deletion(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        password: Option<String>,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+        rate_limit::check(ctx, AuthOperation::Login, Some(&user.id.to_string())).await?;
+
+        if user.deletion_scheduled_at.is_some() {
+            return Err(async_graphql::Error::new(
+                "Account deletion already requested",
+            ));
+        }
+        if user.password_hash.is_some() {
+            let password_ok = my_ctx
+                .passwords
+                .confirm(
+                    password.as_deref().unwrap_or_default(),
+                    user.password_hash.as_deref(),
+                )
+                .await?;
+            if !password_ok {
+                return Err(async_graphql::Error::new("Wrong password"));
+            }
+        }
+
+        let now = Utc::now().naive_utc();
+        let delete_at = now + chrono::Duration::days(my_ctx.account_deletion_grace_days);
+
+        let txn = my_ctx.db.begin().await?;
+        let user: user::Model = user::ActiveModel {
+            deletion_scheduled_at: Set(Some(delete_at)),
+            updated_at: Set(now),
+            ..user.into()
+        }
+        .update(&txn)
+        .await?;
+        enqueue_at(&txn, Task::AnonymiseUser { user_id: user.id }, delete_at).await?;
+        if let Some(email) = &user.email {
+            let date = delete_at.format("%Y-%m-%d");
+            enqueue(
+                &txn,
+                Task::SendEmail {
+                    to: email.clone(),
+                    subject: "Your account will be deleted".to_string(),
+                    text: format!(
+                        "Your Adee account will be deleted on {}. If you change your mind, log in and cancel the deletion before then: https://ad-ee.tech/settings",
+                        date
+                    ),
+                    html: format!(
+                        "<p>Your Adee account will be deleted on {}.</p><p>If you change your mind, <a href=\"https://ad-ee.tech/settings\">log in and cancel the deletion</a> before then.</p>",
+                        date
+                    ),
+                },
+            )
+            .await?;
+        }
+        txn.commit().await?;
+
+        Ok(user)
+    }
+
+    async fn cancel_account_deletion(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        if user.deletion_scheduled_at.is_none() {
+            return Err(async_graphql::Error::new("No account deletion requested"));
+        }
+
+        // The queued job sees the cleared date and does nothing.
+        let user: user::Model = user::ActiveModel {
+            deletion_scheduled_at: Set(None),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        Ok(user)
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use entity::{
+    advert::{self, AdvertStatus, Entity as Advert},
+    notification::NotificationKind,
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    Set,
+};
+
+use crate::notifications::{notify, NewNotification};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub fn spawn(db: DatabaseConnection, notice_days: i64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = notify_expiring(&db, notice_days).await {
+                eprintln!("Failed to notify expiring adverts: {}", err);
+            }
+            if let Err(err) = expire_adverts(&db).await {
+                eprintln!("Failed to expire adverts: {}", err);
+            }
+        }
+    });
+}
+
+async fn expire_adverts(db: &DatabaseConnection) -> Result<(), sea_orm::DbErr> {
+    let now = Utc::now().naive_utc();
+
+    Advert::update_many()
+        .col_expr(
+            advert::Column::Status,
+            advert::Column::Status.save_as(Expr::val(AdvertStatus::Expired)),
+        )
+        .col_expr(advert::Column::Available, Expr::value(false))
+        .col_expr(advert::Column::UpdatedAt, Expr::value(now))
+        .filter(advert::Column::Status.eq(AdvertStatus::Active))
+        .filter(advert::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn notify_expiring(db: &DatabaseConnection, notice_days: i64) -> Result<(), sea_orm::DbErr> {
+    let now = Utc::now().naive_utc();
+    let threshold = now + chrono::Duration::days(notice_days);
+
+    let expiring = Advert::find()
+        .filter(advert::Column::Status.eq(AdvertStatus::Active))
+        .filter(advert::Column::ExpiresAt.lte(threshold))
+        .filter(advert::Column::ExpiresAt.gt(now))
+        .filter(advert::Column::ExpiryNotifiedAt.is_null())
+        .all(db)
+        .await?;
+
+    for advert in expiring {
+        let expires_at = advert
+            .expires_at
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+
+        notify(
+            db,
+            NewNotification {
+                user_id: advert.user_id,
+                kind: NotificationKind::AdvertExpiring,
+                title: "Your advert is about to expire".to_string(),
+                body: format!(
+                    "Your advert \"{}\" expires on {}.",
+                    advert.title, expires_at
+                ),
+                link: Some(format!("https://ad-ee.tech/advert/{}", advert.id)),
+            },
+        )
+        .await?;
+
+        advert::ActiveModel {
+            id: Set(advert.id),
+            expiry_notified_at: Set(Some(now)),
+            ..Default::default()
+        }
+        .update(db)
+        .await?;
+    }
+
+    Ok(())
+}
@@ -1,31 +1,40 @@
-use crate::{verify_access_token, Context, Token};
+use crate::{
+    advert_stats, currency,
+    notifications::{notify, NewNotification},
+    price_drop::notify_favorites,
+    promotion,
+    reputation::refresh_user_ratings,
+    verify_access_token, Context, Token,
+};
 use std::collections::{HashMap, HashSet};
 
 use actix_web::Result;
 use async_graphql::{Json, Object};
 use chrono::Utc;
 use entity::{
-    advert::{self, Entity as Advert},
+    advert::{self, AdvertStatus, Entity as Advert},
+    exchange_rate::{self, Entity as ExchangeRate},
+    favorite_collection::Entity as FavoriteCollection,
     favorites::{self, Entity as Favorites},
-    reviews::{self, Entity as Reviews},
+    money::Money,
+    notification::NotificationKind,
+    price_history,
+    reviews::{self, Entity as Reviews, ReviewRole},
     specifications::{self, Entity as Specifications},
-    user::{self, Entity as User, Role},
+    user::{self, AccountType, Entity as User, Role},
 };
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DbErr, DeleteResult, EntityTrait, ModelTrait, Order,
-    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set,
+    prelude::Decimal, sea_query::OnConflict, ActiveModelTrait, ColumnTrait, DbErr, DeleteResult,
+    EntityTrait, ModelTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+    QueryTrait, Set, TransactionTrait,
 };
-use serde::{Deserialize, Serialize};
+
+/// How long after posting the author may still edit a review.
+const REVIEW_EDIT_WINDOW_HOURS: i64 = 48;
 
 #[derive(Default)]
 pub struct AdvertQuery;
 
-#[derive(Deserialize, Serialize)]
-struct CustomSpecField {
-    key: String,
-    value: String,
-}
-
 #[Object]
 impl AdvertQuery {
     async fn advert(
@@ -40,83 +49,34 @@ impl AdvertQuery {
             .await?
             .ok_or_else(|| async_graphql::Error::new("Advert not found"))?;
 
-        let specs = Specifications::find()
-            .filter(specifications::Column::AdvertId.eq(id))
-            .all(&my_ctx.db)
-            .await?;
-
-        let user = User::find_by_id(advert.user_id)
-            .one(&my_ctx.db)
-            .await?
-            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
-
-        let mut updated_advert = advert.clone();
-        updated_advert.specs = specs;
-
-        let mut is_favorited = false;
-        if let Some(token) = ctx.data_opt::<Token>() {
-            if let Ok(claims) = verify_access_token(token.0.clone(), &my_ctx.access_key) {
-                let req_user_id: i32 =
-                    if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
-                        id_str
-                            .parse()
-                            .map_err(|_| async_graphql::Error::new("Invalid user ID in token"))?
-                    } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
-                        id_num as i32
-                    } else {
-                        return Err(async_graphql::Error::new("Invalid user ID in token"));
-                    };
-
-                is_favorited = Favorites::find()
-                    .filter(favorites::Column::UserId.eq(req_user_id))
-                    .filter(favorites::Column::AdvertId.eq(id))
-                    .one(&my_ctx.db)
-                    .await?
-                    .is_some();
+        if advert.status == AdvertStatus::Draft {
+            let mut is_owner = false;
+            if let Some(token) = ctx.data_opt::<Token>() {
+                if let Ok(claims) = verify_access_token(token.0.clone(), &my_ctx.access_key) {
+                    let req_user_id: i32 =
+                        if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+                            id_str.parse().map_err(|_| {
+                                async_graphql::Error::new("Invalid user ID in token")
+                            })?
+                        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+                            id_num as i32
+                        } else {
+                            return Err(async_graphql::Error::new("Invalid user ID in token"));
+                        };
+
+                    is_owner = req_user_id == advert.user_id;
+                }
+            }
+            if !is_owner {
+                return Err(async_graphql::Error::new("Advert not found"));
             }
         }
-        updated_advert.is_favorited = is_favorited;
-        updated_advert.user = user.clone();
-
-        let seller_advert_ids = Advert::find()
-            .filter(advert::Column::UserId.eq(user.id))
-            .select_only()
-            .column(advert::Column::Id)
-            .into_query();
 
-        let seller_reviews = Reviews::find()
-            .filter(reviews::Column::AdvertId.in_subquery(seller_advert_ids))
-            .all(&my_ctx.db)
-            .await?;
-
-        let (total_seller, count_seller) = seller_reviews.iter().fold((0.0, 0), |acc, review| {
-            (acc.0 + review.rating as f32, acc.1 + 1)
-        });
-        let seller_rating = if count_seller > 0 {
-            total_seller / count_seller as f32
-        } else {
-            0.0
-        };
-        updated_advert.user.rating = seller_rating;
-
-        let review = Reviews::find()
-            .filter(reviews::Column::AdvertId.eq(id))
-            .one(&my_ctx.db)
-            .await?;
-
-        if let Some(mut review) = review {
-            let review_user = User::find_by_id(review.user_id)
-                .one(&my_ctx.db)
-                .await?
-                .ok_or_else(|| async_graphql::Error::new("User not found"))?;
-
-            review.user = review_user;
-            updated_advert.review = Some(review.clone());
-        } else {
-            updated_advert.review = None;
+        if let Err(err) = advert_stats::record_view(ctx, &advert).await {
+            eprintln!("Failed to record view of advert {}: {:?}", advert.id, err);
         }
 
-        Ok(updated_advert)
+        Ok(advert)
     }
 
     async fn similar_adverts(
@@ -143,7 +103,7 @@ impl AdvertQuery {
         let adverts_with_specs = Advert::find()
             .filter(advert::Column::Category.eq(advert.category.clone()))
             .filter(advert::Column::Id.ne(id))
-            .filter(advert::Column::Available.eq(true))
+            .filter(advert::Column::Status.eq(AdvertStatus::Active))
             .find_with_related(specifications::Entity)
             .all(&my_ctx.db)
             .await?;
@@ -175,7 +135,7 @@ impl AdvertQuery {
                     (other_advert.clone(), matching_specs_count)
                 })
                 .collect();
-            adverts_by_matching_specs.sort_by(|a, b| b.1.cmp(&a.1));
+            adverts_by_matching_specs.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
             for (advert, _) in adverts_by_matching_specs {
                 if !matching_adverts.contains(&advert) {
                     matching_adverts.push(advert);
@@ -190,95 +150,13 @@ impl AdvertQuery {
             matching_adverts = Advert::find()
                 .filter(advert::Column::Category.eq(advert.category.clone()))
                 .filter(advert::Column::Id.ne(id))
-                .filter(advert::Column::Available.eq(true))
+                .filter(advert::Column::Status.eq(AdvertStatus::Active))
                 .limit(4)
                 .all(&my_ctx.db)
                 .await?;
         }
 
-        let advert_ids: Vec<i32> = matching_adverts.iter().map(|adv| adv.id).collect();
-        let specs = Specifications::find()
-            .filter(specifications::Column::AdvertId.is_in(advert_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-        let mut specs_map: HashMap<i32, Vec<specifications::Model>> = HashMap::new();
-        for spec in specs {
-            specs_map.entry(spec.advert_id).or_default().push(spec);
-        }
-
-        let user_ids: HashSet<i32> = matching_adverts.iter().map(|adv| adv.user_id).collect();
-        let users = User::find()
-            .filter(user::Column::Id.is_in(user_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-        let users_map: HashMap<i32, user::Model> =
-            users.into_iter().map(|user| (user.id, user)).collect();
-
-        let user_adverts = Advert::find()
-            .filter(advert::Column::UserId.is_in(user_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-        let user_advert_ids: Vec<i32> = user_adverts.iter().map(|adv| adv.id).collect();
-
-        let reviews = Reviews::find()
-            .filter(reviews::Column::AdvertId.is_in(user_advert_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-        let mut user_ratings: HashMap<i32, (f32, usize)> = HashMap::new();
-        let advert_user_map: HashMap<i32, i32> = user_adverts
-            .iter()
-            .map(|adv| (adv.id, adv.user_id))
-            .collect();
-        for review in reviews {
-            if let Some(&user_id) = advert_user_map.get(&review.advert_id) {
-                let entry = user_ratings.entry(user_id).or_insert((0.0, 0));
-                entry.0 += review.rating as f32;
-                entry.1 += 1;
-            }
-        }
-
-        let mut favorite_advert_ids = HashSet::new();
-        if let Some(token) = ctx.data_opt::<Token>() {
-            if let Ok(claims) = verify_access_token(token.0.clone(), &my_ctx.access_key) {
-                let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
-                    id_str.parse().map_err(|_| {
-                        async_graphql::Error::new(
-                            "Invalid user ID in token: failed to parse string",
-                        )
-                    })?
-                } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
-                    id_num as i32
-                } else {
-                    return Err(async_graphql::Error::new(
-                        "Invalid user ID in token: missing id",
-                    ));
-                };
-                let favorite_adverts = Favorites::find()
-                    .filter(favorites::Column::UserId.eq(user_id))
-                    .all(&my_ctx.db)
-                    .await?;
-                favorite_advert_ids = favorite_adverts.iter().map(|fav| fav.advert_id).collect();
-            }
-        }
-
-        let enriched_adverts: Vec<advert::Model> = matching_adverts
-            .into_iter()
-            .map(|mut advert| {
-                advert.specs = specs_map.get(&advert.id).cloned().unwrap_or_default();
-                if let Some(user) = users_map.get(&advert.user_id) {
-                    advert.user = user.clone();
-                    let user_rating = user_ratings
-                        .get(&user.id)
-                        .map(|(total, count)| total / *count as f32)
-                        .unwrap_or(0.0);
-                    advert.user.rating = user_rating;
-                }
-                advert.is_favorited = favorite_advert_ids.contains(&advert.id);
-                advert
-            })
-            .collect();
-
-        Ok(enriched_adverts)
+        Ok(matching_adverts)
     }
 
     async fn get_adverts(
@@ -288,120 +166,47 @@ impl AdvertQuery {
         limit: i32,
     ) -> Result<Vec<advert::Model>, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
-        let adverts: Vec<advert::Model> = advert::Entity::find()
-            .order_by(advert::Column::Id, Order::Desc)
-            .offset(offset as u64)
-            .limit(limit as u64)
-            .filter(advert::Column::Available.eq(true))
-            .all(&my_ctx.db)
-            .await?;
-
-        let advert_ids: Vec<i32> = adverts.iter().map(|adv| adv.id).collect();
-
-        let specs = Specifications::find()
-            .filter(specifications::Column::AdvertId.is_in(advert_ids))
-            .all(&my_ctx.db)
-            .await?;
-
-        let mut specs_map: HashMap<i32, Vec<specifications::Model>> = HashMap::new();
-        for spec in specs {
-            specs_map.entry(spec.advert_id).or_default().push(spec);
-        }
-
-        let user_ids: HashSet<i32> = adverts.iter().map(|adv| adv.user_id).collect();
-        let users = User::find()
-            .filter(user::Column::Id.is_in(user_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-
-        let users_map: HashMap<i32, user::Model> =
-            users.into_iter().map(|user| (user.id, user)).collect();
-
-        let user_adverts = Advert::find()
-            .filter(advert::Column::UserId.is_in(user_ids.clone()))
+        let offset = offset.max(0) as usize;
+        let limit = limit.max(0) as usize;
+
+        let promoted_ids = promotion::promoted_ids(&my_ctx.db, None).await?;
+        let organic = advert::Entity::find()
+            .filter(advert::Column::Status.eq(AdvertStatus::Active))
+            .filter(advert::Column::Id.is_not_in(promoted_ids.iter().copied()));
+        let organic_count = organic.clone().count(&my_ctx.db).await? as usize;
+
+        let (promoted_shown, organic_shown) =
+            promotion::shown_before(offset, promoted_ids.len(), organic_count);
+        let (promoted_end, organic_end) =
+            promotion::shown_before(offset + limit, promoted_ids.len(), organic_count);
+        let page_promoted_ids = &promoted_ids[promoted_shown..promoted_end];
+
+        let mut promoted: Vec<advert::Model> = advert::Entity::find()
+            .filter(advert::Column::Id.is_in(page_promoted_ids.iter().copied()))
             .all(&my_ctx.db)
             .await?;
+        promoted.sort_by_key(|adv| page_promoted_ids.iter().position(|id| *id == adv.id));
 
-        let user_advert_ids: Vec<i32> = user_adverts.iter().map(|adv| adv.id).collect();
-
-        let reviews = Reviews::find()
-            .filter(reviews::Column::AdvertId.is_in(user_advert_ids.clone()))
+        let organic: Vec<advert::Model> = organic
+            .order_by(advert::Column::Id, Order::Desc)
+            .offset(organic_shown as u64)
+            .limit((organic_end - organic_shown) as u64)
             .all(&my_ctx.db)
             .await?;
 
-        let mut user_ratings: HashMap<i32, (f32, usize)> = HashMap::new();
-        let advert_user_map: HashMap<i32, i32> = user_adverts
-            .iter()
-            .map(|adv| (adv.id, adv.user_id))
-            .collect();
-
-        for review in reviews {
-            if let Some(&user_id) = advert_user_map.get(&review.advert_id) {
-                let entry = user_ratings.entry(user_id).or_insert((0.0, 0));
-                entry.0 += review.rating as f32;
-                entry.1 += 1;
-            }
-        }
-
-        let mut favorite_advert_ids = HashSet::new();
-        if let Some(token) = ctx.data_opt::<Token>() {
-            let claims = verify_access_token(token.0.clone(), &my_ctx.access_key);
-
-            if let Some(claims) = claims.ok() {
-                let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
-                    id_str.parse().map_err(|_| {
-                        async_graphql::Error::new(
-                            "Invalid user ID in token: failed to parse string",
-                        )
-                    })?
-                } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
-                    id_num as i32
-                } else {
-                    return Err(async_graphql::Error::new(
-                        "Invalid user ID in token: missing id",
-                    ));
-                };
-
-                let favorite_adverts = Favorites::find()
-                    .filter(favorites::Column::UserId.eq(user_id))
-                    .all(&my_ctx.db)
-                    .await?;
-
-                favorite_advert_ids = favorite_adverts.iter().map(|fav| fav.advert_id).collect();
-            }
-        }
-
-        let result: Vec<advert::Model> = adverts
-            .into_iter()
-            .map(|mut advert| {
-                let specs = specs_map.get(&advert.id).cloned().unwrap_or_default();
-                let user = users_map.get(&advert.user_id).cloned();
-                let user_rating = user
-                    .as_ref()
-                    .and_then(|u| user_ratings.get(&u.id))
-                    .map(|(total, count)| total / *count as f32);
-                let is_favorited = favorite_advert_ids.contains(&advert.id);
-
-                advert.is_favorited = is_favorited;
-                advert.specs = specs;
-                advert.user = user.unwrap();
-                advert.user.rating = user_rating.unwrap_or(0.0);
-
-                advert
-            })
-            .collect();
-
-        return Ok(result);
+        Ok(promotion::interleave(offset, limit, promoted, organic))
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn search_adverts(
         &self,
         ctx: &async_graphql::Context<'_>,
         category: Option<String>,
         offset: i32,
         title: String,
-        min_price: Option<f32>,
-        max_price: Option<f32>,
+        min_price: Option<Money>,
+        max_price: Option<Money>,
+        currency: Option<String>,
         min_rating: Option<f32>,
         sort_field: Option<String>,
         sort_order: Option<String>,
@@ -409,22 +214,36 @@ impl AdvertQuery {
         center_lon: Option<f32>,
         location_range: Option<f32>,
         custom_fields: Option<async_graphql::Json<serde_json::Value>>,
+        seller_type: Option<AccountType>,
     ) -> Result<Vec<advert::Model>, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
 
         let mut query = advert::Entity::find()
-            .filter(advert::Column::Available.eq(true))
+            .filter(advert::Column::Status.eq(AdvertStatus::Active))
             .filter(advert::Column::Title.contains(&title));
 
-        if let Some(cat) = category {
-            query = query.filter(advert::Column::Category.eq(cat));
+        if let Some(seller_type) = seller_type {
+            let sellers = User::find()
+                .filter(user::Column::AccountType.eq(seller_type))
+                .select_only()
+                .column(user::Column::Id)
+                .into_query();
+
+            query = query.filter(advert::Column::UserId.in_subquery(sellers));
         }
 
-        if let Some(min) = min_price {
-            query = query.filter(advert::Column::Price.gte(min));
+        if let Some(cat) = &category {
+            query = query.filter(advert::Column::Category.eq(cat.clone()));
         }
-        if let Some(max) = max_price {
-            query = query.filter(advert::Column::Price.lte(max));
+
+        if let Some(min_rating) = min_rating {
+            let rated_sellers = User::find()
+                .filter(user::Column::RatingAvg.gte(min_rating))
+                .select_only()
+                .column(user::Column::Id)
+                .into_query();
+
+            query = query.filter(advert::Column::UserId.in_subquery(rated_sellers));
         }
 
         if let Some(fields) = custom_fields {
@@ -456,38 +275,40 @@ impl AdvertQuery {
 
         let mut adverts = query.all(&my_ctx.db).await?;
 
-        let advert_ids: Vec<i32> = adverts.iter().map(|adv| adv.id).collect();
-        let reviews = Reviews::find()
-            .filter(reviews::Column::AdvertId.is_in(advert_ids.clone()))
+        let user_ids: HashSet<i32> = adverts.iter().map(|adv| adv.user_id).collect();
+        let seller_ratings: HashMap<i32, f32> = User::find()
+            .filter(user::Column::Id.is_in(user_ids))
             .all(&my_ctx.db)
-            .await?;
+            .await?
+            .into_iter()
+            .map(|user| (user.id, user.rating_avg))
+            .collect();
+        let rating_of = |adv: &advert::Model| -> f32 {
+            seller_ratings.get(&adv.user_id).copied().unwrap_or(0.0)
+        };
 
-        let mut ratings_map: std::collections::HashMap<i32, (f32, usize)> =
-            std::collections::HashMap::new();
-        for review in reviews {
-            let entry = ratings_map.entry(review.advert_id).or_insert((0.0, 0));
-            entry.0 += review.rating as f32;
-            entry.1 += 1;
-        }
+        let view_currency = match currency {
+            Some(code) => currency::normalize_code(&code)?,
+            None => my_ctx.base_currency.clone(),
+        };
+        let rates = currency::load_rates(&my_ctx.db).await?;
 
-        for advert in &mut adverts {
-            let avg_rating = if let Some(&(total, count)) = ratings_map.get(&advert.id) {
-                if count > 0 {
-                    total / count as f32
-                } else {
-                    0.0
-                }
-            } else {
-                0.0
-            };
-            advert.user.rating = avg_rating;
-        }
+        let converted_prices: HashMap<i32, Money> = adverts
+            .iter()
+            .filter_map(|adv| {
+                currency::convert(adv.price, &adv.currency, &view_currency, &rates)
+                    .map(|price| (adv.id, price))
+            })
+            .collect();
 
-        if let Some(min_rating) = min_rating {
-            adverts = adverts
-                .into_iter()
-                .filter(|adv| adv.user.rating >= min_rating)
-                .collect();
+        if min_price.is_some() || max_price.is_some() {
+            adverts.retain(|adv| match converted_prices.get(&adv.id) {
+                Some(price) => {
+                    min_price.is_none_or(|min| *price >= min)
+                        && max_price.is_none_or(|max| *price <= max)
+                }
+                None => false,
+            });
         }
 
         if let Some(field) = sort_field {
@@ -498,32 +319,24 @@ impl AdvertQuery {
                 "rating" => {
                     if order == "asc" {
                         adverts.sort_by(|a, b| {
-                            a.user
-                                .rating
-                                .partial_cmp(&b.user.rating)
+                            rating_of(a)
+                                .partial_cmp(&rating_of(b))
                                 .unwrap_or(std::cmp::Ordering::Equal)
                         });
                     } else {
                         adverts.sort_by(|a, b| {
-                            b.user
-                                .rating
-                                .partial_cmp(&a.user.rating)
+                            rating_of(b)
+                                .partial_cmp(&rating_of(a))
                                 .unwrap_or(std::cmp::Ordering::Equal)
                         });
                     }
                 }
                 "price" => {
                     if order == "asc" {
-                        adverts.sort_by(|a, b| {
-                            a.price
-                                .partial_cmp(&b.price)
-                                .unwrap_or(std::cmp::Ordering::Equal)
-                        });
+                        adverts.sort_by_key(|adv| converted_prices.get(&adv.id).copied());
                     } else {
-                        adverts.sort_by(|a, b| {
-                            b.price
-                                .partial_cmp(&a.price)
-                                .unwrap_or(std::cmp::Ordering::Equal)
+                        adverts.sort_by_key(|adv| {
+                            std::cmp::Reverse(converted_prices.get(&adv.id).copied())
                         });
                     }
                 }
@@ -538,12 +351,22 @@ impl AdvertQuery {
             }
         }
 
-        let offset = offset as usize;
-        let adverts = if offset < adverts.len() {
-            adverts.into_iter().skip(offset).collect()
-        } else {
-            vec![]
-        };
+        let promoted_ids = promotion::promoted_ids(&my_ctx.db, category.as_deref()).await?;
+        let (mut promoted, organic): (Vec<advert::Model>, Vec<advert::Model>) = adverts
+            .into_iter()
+            .partition(|adv| promoted_ids.contains(&adv.id));
+        promoted.sort_by_key(|adv| promoted_ids.iter().position(|id| *id == adv.id));
+
+        let offset = offset.max(0) as usize;
+        let (promoted_shown, organic_shown) =
+            promotion::shown_before(offset, promoted.len(), organic.len());
+        let remaining = (promoted.len() + organic.len()).saturating_sub(offset);
+        let adverts = promotion::interleave(
+            offset,
+            remaining,
+            promoted.into_iter().skip(promoted_shown).collect(),
+            organic.into_iter().skip(organic_shown).collect(),
+        );
 
         Ok(adverts)
     }
@@ -551,6 +374,7 @@ impl AdvertQuery {
     async fn get_favorites(
         &self,
         ctx: &async_graphql::Context<'_>,
+        collection_id: Option<i32>,
     ) -> Result<Vec<advert::Model>, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
 
@@ -591,6 +415,9 @@ impl AdvertQuery {
 
         let favorites: Vec<favorites::Model> = Favorites::find()
             .filter(favorites::Column::UserId.eq(user_id))
+            .apply_if(collection_id, |query, collection_id| {
+                query.filter(favorites::Column::CollectionId.eq(collection_id))
+            })
             .all(&my_ctx.db)
             .await?;
 
@@ -611,72 +438,21 @@ impl AdvertQuery {
             ));
         }
 
-        let specs = Specifications::find()
-            .filter(specifications::Column::AdvertId.is_in(favorite_advert_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-
-        let mut specs_map: HashMap<i32, Vec<specifications::Model>> = HashMap::new();
-        for spec in specs {
-            specs_map.entry(spec.advert_id).or_default().push(spec);
-        }
-
-        let user_ids: HashSet<i32> = adverts.iter().map(|adv| adv.user_id).collect();
-        let users = User::find()
-            .filter(user::Column::Id.is_in(user_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-
-        let users_map: HashMap<i32, user::Model> = users.into_iter().map(|u| (u.id, u)).collect();
-
-        let user_adverts = Advert::find()
-            .filter(advert::Column::UserId.is_in(user_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
+        Ok(adverts)
+    }
 
-        let user_advert_ids: Vec<i32> = user_adverts.iter().map(|adv| adv.id).collect();
+    async fn exchange_rates(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<Vec<exchange_rate::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
 
-        let reviews = Reviews::find()
-            .filter(reviews::Column::AdvertId.is_in(user_advert_ids.clone()))
+        let rates = ExchangeRate::find()
+            .order_by(exchange_rate::Column::Currency, Order::Asc)
             .all(&my_ctx.db)
             .await?;
 
-        let mut user_ratings: HashMap<i32, (f32, usize)> = HashMap::new();
-        let advert_user_map: HashMap<i32, i32> = user_adverts
-            .iter()
-            .map(|adv| (adv.id, adv.user_id))
-            .collect();
-
-        for review in reviews {
-            if let Some(&u_id) = advert_user_map.get(&review.advert_id) {
-                let entry = user_ratings.entry(u_id).or_insert((0.0, 0));
-                entry.0 += review.rating as f32;
-                entry.1 += 1;
-            }
-        }
-
-        let result: Vec<advert::Model> = adverts
-            .into_iter()
-            .map(|mut advert| {
-                advert.specs = specs_map.get(&advert.id).cloned().unwrap_or_default();
-
-                if let Some(user) = users_map.get(&advert.user_id) {
-                    advert.user = user.clone();
-
-                    if let Some((total, count)) = user_ratings.get(&user.id) {
-                        advert.user.rating = total / (*count as f32);
-                    } else {
-                        advert.user.rating = 0.0;
-                    }
-                }
-
-                advert.is_favorited = true;
-
-                advert
-            })
-            .collect();
-
-        Ok(result)
+        Ok(rates)
     }
 }
 
@@ -685,11 +461,13 @@ pub struct AdvertMutation;
 
 #[Object]
 impl AdvertMutation {
+    #[allow(clippy::too_many_arguments)]
     async fn edit_advert(
         &self,
         ctx: &async_graphql::Context<'_>,
         id: i32,
-        #[graphql(validator(minimum = 0))] price: f32,
+        price: Money,
+        currency: Option<String>,
         lat: f32,
         lon: f32,
         title: String,
@@ -736,6 +514,25 @@ impl AdvertMutation {
             return Err(async_graphql::Error::new("you are not owner".to_string()));
         }
 
+        if price.is_negative() {
+            return Err(async_graphql::Error::new("Price cannot be negative"));
+        }
+
+        let currency = match currency {
+            Some(code) => {
+                let code = currency::normalize_code(&code)?;
+                if ExchangeRate::find_by_id(code.clone())
+                    .one(&my_ctx.db)
+                    .await?
+                    .is_none()
+                {
+                    return Err(async_graphql::Error::new("Unsupported currency"));
+                }
+                code
+            }
+            None => advert.currency.clone(),
+        };
+
         // let specs: Vec<specifications::Model> =
         //     advert.find_related(Specifications).all(&my_ctx.db).await?;
 
@@ -764,7 +561,7 @@ impl AdvertMutation {
 
         if !photos[0].is_empty() {
             photo_url = photos[0].clone();
-            additional_photos = photos[1..].iter().cloned().collect();
+            additional_photos = photos[1..].to_vec();
         } else {
             photo_url = advert.photo_url.clone();
             additional_photos = advert.additional_photos.clone().unwrap();
@@ -772,27 +569,67 @@ impl AdvertMutation {
 
         println!("photo_url: {}, {:?}", photo_url, additional_photos);
 
-        let new_advert = advert::ActiveModel {
+        let now = Utc::now().naive_utc();
+        let previous_price = advert.price;
+        let previous_currency = advert.currency.clone();
+        let same_currency = advert.currency == currency;
+        let price_changed = previous_price != price || !same_currency;
+
+        let mut new_advert = advert::ActiveModel {
             photo_url: Set(photo_url),
             additional_photos: Set(Some(additional_photos)),
             price: Set(price),
+            currency: Set(currency.clone()),
             lat: Set(lat),
             lon: Set(lon),
             title: Set(title),
             description: Set(description),
-            updated_at: Set(Utc::now().naive_utc()),
+            updated_at: Set(now),
             ..advert.into()
         };
 
-        let adv: advert::Model = new_advert.update(&my_ctx.db).await?;
+        if price_changed {
+            new_advert.old_price = Set(previous_price);
+        }
+
+        // The new price and its history row are saved together.
+        let txn = my_ctx.db.begin().await?;
+        let adv: advert::Model = new_advert.update(&txn).await?;
+
+        if price_changed {
+            price_history::ActiveModel {
+                advert_id: Set(adv.id),
+                old_price: Set(previous_price),
+                old_currency: Set(previous_currency),
+                new_price: Set(price),
+                currency: Set(adv.currency.clone()),
+                changed_at: Set(now),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+
+        if same_currency && price < previous_price {
+            let db = my_ctx.db.clone();
+            let dropped = adv.clone();
+            tokio::spawn(async move {
+                if let Err(err) = notify_favorites(&db, &dropped, previous_price).await {
+                    eprintln!("Failed to send price drop notifications: {}", err);
+                }
+            });
+        }
 
-        return Ok(adv);
+        Ok(adv)
     }
 
     async fn add_favorite(
         &self,
         ctx: &async_graphql::Context<'_>,
         advert_id: i32,
+        collection_id: Option<i32>,
+        note: Option<String>,
     ) -> Result<favorites::Model, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
 
@@ -828,16 +665,57 @@ impl AdvertMutation {
             None => return Err(async_graphql::Error::new("Wrong token".to_string())),
         };
 
+        if let Some(collection_id) = collection_id {
+            let collection = FavoriteCollection::find_by_id(collection_id)
+                .one(&my_ctx.db)
+                .await?;
+            if collection.map(|collection| collection.user_id) != Some(user.id) {
+                return Err(async_graphql::Error::new(
+                    "Collection not found".to_string(),
+                ));
+            }
+        }
+
+        // Favoriting again moves the existing favorite and replaces its note,
+        // for whichever of the two is given.
+        let mut on_conflict =
+            OnConflict::columns([favorites::Column::UserId, favorites::Column::AdvertId]);
+        let updated: Vec<favorites::Column> = [
+            (favorites::Column::CollectionId, collection_id.is_some()),
+            (favorites::Column::Note, note.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(column, given)| given.then_some(column))
+        .collect();
+        if updated.is_empty() {
+            on_conflict.do_nothing();
+        } else {
+            on_conflict.update_columns(updated);
+        }
+
         let favorite = favorites::ActiveModel {
             advert_id: Set(advert_id),
             user_id: Set(user.id),
+            collection_id: Set(collection_id),
+            note: Set(note),
             created_at: Set(Utc::now().naive_utc()),
             ..Default::default()
         };
 
-        let favorite: favorites::Model = favorite.insert(&my_ctx.db).await?;
+        // The (user, advert) unique index settles concurrent adds.
+        Favorites::insert(favorite)
+            .on_conflict(on_conflict)
+            .exec_without_returning(&my_ctx.db)
+            .await?;
+
+        let favorite = Favorites::find()
+            .filter(favorites::Column::UserId.eq(user.id))
+            .filter(favorites::Column::AdvertId.eq(advert_id))
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Wrong favorite".to_string()))?;
 
-        return Ok(favorite);
+        Ok(favorite)
     }
 
     async fn remove_favorite(
@@ -889,13 +767,15 @@ impl AdvertMutation {
 
         let _: DeleteResult = favorite.clone().delete(&my_ctx.db).await?;
 
-        return Ok(favorite);
+        Ok(favorite)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn create_advert(
         &self,
         ctx: &async_graphql::Context<'_>,
-        #[graphql(validator(minimum = 0))] price: f32,
+        price: Money,
+        currency: Option<String>,
         lat: f32,
         lon: f32,
         title: String,
@@ -903,6 +783,7 @@ impl AdvertMutation {
         category: String,
         #[graphql(validator(list, min_items = 1))] photos: Vec<String>,
         data: Json<serde_json::Value>,
+        draft: Option<bool>,
     ) -> Result<advert::Model, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
 
@@ -948,14 +829,42 @@ impl AdvertMutation {
             None => return Err(async_graphql::Error::new("Wrong token".to_string())),
         }
 
+        if price.is_negative() {
+            return Err(async_graphql::Error::new("Price cannot be negative"));
+        }
+
+        let currency = match currency {
+            Some(code) => currency::normalize_code(&code)?,
+            None => my_ctx.base_currency.clone(),
+        };
+        if ExchangeRate::find_by_id(currency.clone())
+            .one(&my_ctx.db)
+            .await?
+            .is_none()
+        {
+            return Err(async_graphql::Error::new("Unsupported currency"));
+        }
+
         let naive_date_time = Utc::now().naive_utc();
 
         let photo_url = photos[0].clone();
 
-        let additional_photos: Vec<String> = photos[1..].iter().cloned().collect();
+        let additional_photos: Vec<String> = photos[1..].to_vec();
+
+        let is_draft = draft.unwrap_or(false);
+        let (status, expires_at) = if is_draft {
+            (AdvertStatus::Draft, None)
+        } else {
+            (
+                AdvertStatus::Active,
+                Some(naive_date_time + chrono::Duration::days(my_ctx.advert_lifetime_days)),
+            )
+        };
 
         let advert = advert::ActiveModel {
-            available: Set(true),
+            available: Set(!is_draft),
+            status: Set(status),
+            expires_at: Set(expires_at),
             user_id: Set(
                 if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
                     id_str.parse().map_err(|_| {
@@ -975,6 +884,7 @@ impl AdvertMutation {
             updated_at: Set(naive_date_time),
             price: Set(price),
             old_price: Set(price),
+            currency: Set(currency),
             lat: Set(lat),
             lon: Set(lon),
             description: Set(description),
@@ -989,36 +899,33 @@ impl AdvertMutation {
 
         let features = data.as_object();
 
-        match features {
-            Some(features) => {
-                let mut specifications = Vec::new();
-                for (key, value) in features.iter() {
-                    let spec = specifications::ActiveModel {
-                        key: Set(key.clone()),
-                        value: Set(value.as_str().unwrap().to_string()),
-                        advert_id: Set(advert.id),
-                        ..Default::default()
-                    };
-
-                    specifications.push(spec);
-                }
+        if let Some(features) = features {
+            let mut specifications = Vec::new();
+            for (key, value) in features.iter() {
+                let spec = specifications::ActiveModel {
+                    key: Set(key.clone()),
+                    value: Set(value.as_str().unwrap().to_string()),
+                    advert_id: Set(advert.id),
+                    ..Default::default()
+                };
 
-                match Specifications::insert_many(specifications)
-                    .exec(&my_ctx.db)
-                    .await
-                {
-                    Ok(inserted_specifications) => {
-                        println!("{:?}", inserted_specifications);
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to insert specifications: {}", e);
-                    }
+                specifications.push(spec);
+            }
+
+            match Specifications::insert_many(specifications)
+                .exec(&my_ctx.db)
+                .await
+            {
+                Ok(inserted_specifications) => {
+                    println!("{:?}", inserted_specifications);
+                }
+                Err(e) => {
+                    eprintln!("Failed to insert specifications: {}", e);
                 }
             }
-            _ => (),
         }
 
-        return Ok(advert);
+        Ok(advert)
     }
 
     async fn delete_advert(
@@ -1065,7 +972,7 @@ impl AdvertMutation {
             Some(req_user) => {
                 if req_user.role == Role::Admin || req_user.role == Role::Moderator {
                 } else if advert.user_id == user_id {
-                    if !advert.sold_to.is_none() {
+                    if advert.sold_to.is_some() {
                         return Err(async_graphql::Error::new(
                             "You cannot delete this advert as it has already been sold.",
                         ));
@@ -1081,6 +988,11 @@ impl AdvertMutation {
 
         advert.clone().delete(&my_ctx.db).await?;
 
+        refresh_user_ratings(&my_ctx.db, advert.user_id).await?;
+        if let Some(buyer_id) = advert.sold_to {
+            refresh_user_ratings(&my_ctx.db, buyer_id).await?;
+        }
+
         Ok(advert)
     }
 
@@ -1126,21 +1038,49 @@ impl AdvertMutation {
             None => return Err(async_graphql::Error::new("advert not found".to_string())),
         };
 
-        if new_advert.user_id == user_id {
+        let buyer_id = match (new_advert.status, new_advert.sold_to) {
+            (AdvertStatus::Sold, Some(buyer_id)) => buyer_id,
+            _ => {
+                return Err(async_graphql::Error::new(
+                    "You can't review this advert as it has not been sold".to_string(),
+                ))
+            }
+        };
+
+        let (role, subject_id) = if user_id == buyer_id {
+            (ReviewRole::AsBuyer, new_advert.user_id)
+        } else if user_id == new_advert.user_id {
+            (ReviewRole::AsSeller, buyer_id)
+        } else {
+            return Err(async_graphql::Error::new(
+                "Only the buyer and the seller can review this sale".to_string(),
+            ));
+        };
+
+        let sold_at = new_advert.sold_at.unwrap_or(new_advert.updated_at);
+        if Utc::now().naive_utc() > sold_at + chrono::Duration::days(my_ctx.review_window_days) {
             return Err(async_graphql::Error::new(
-                "You can't review your own advert".to_string(),
+                "The review window for this sale has closed".to_string(),
             ));
         }
 
-        if new_advert.sold_to != Some(user_id) {
+        let existing = Reviews::find()
+            .filter(reviews::Column::AdvertId.eq(advert_id))
+            .filter(reviews::Column::UserId.eq(user_id))
+            .one(&my_ctx.db)
+            .await?;
+
+        if existing.is_some() {
             return Err(async_graphql::Error::new(
-                "You can't review this advert as it has not been sold to you".to_string(),
+                "You have already reviewed this sale".to_string(),
             ));
         }
 
         let review = reviews::ActiveModel {
             advert_id: Set(advert_id),
             user_id: Set(user_id),
+            subject_id: Set(subject_id),
+            role: Set(role),
             rating: Set(rating),
             message: Set(message),
             created_at: Set(Utc::now().naive_utc()),
@@ -1149,6 +1089,434 @@ impl AdvertMutation {
 
         let review: reviews::Model = review.insert(&my_ctx.db).await?;
 
-        return Ok(review);
+        refresh_user_ratings(&my_ctx.db, subject_id).await?;
+
+        let db = my_ctx.db.clone();
+        let received = NewNotification {
+            user_id: subject_id,
+            kind: NotificationKind::ReviewReceived,
+            title: "You received a new review".to_string(),
+            body: format!(
+                "You were rated {}/5 for \"{}\": {}",
+                review.rating, new_advert.title, review.message
+            ),
+            link: Some(format!("https://ad-ee.tech/advert/{}", advert_id)),
+        };
+        tokio::spawn(async move {
+            if let Err(err) = notify(&db, received).await {
+                eprintln!("Failed to send review notification: {}", err);
+            }
+        });
+
+        Ok(review)
+    }
+
+    async fn reply_to_review(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        review_id: i32,
+        reply: String,
+    ) -> Result<reviews::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+            Some(token) => token,
+            None => {
+                return Err(async_graphql::Error::new(
+                    "you are not logged in".to_string(),
+                ));
+            }
+        };
+
+        let claims = match verify_access_token(access_token, &my_ctx.access_key) {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+            id_str.parse().map_err(|_| {
+                async_graphql::Error::new("Invalid user ID in token: failed to parse string")
+            })?
+        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+            id_num as i32
+        } else {
+            return Err(async_graphql::Error::new(
+                "Invalid user ID in token: missing id",
+            ));
+        };
+
+        let review = Reviews::find_by_id(review_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("review not found"))?;
+
+        if review.subject_id != user_id {
+            return Err(async_graphql::Error::new(
+                "Only the reviewed user can reply to this review".to_string(),
+            ));
+        }
+
+        if review.reply.is_some() {
+            return Err(async_graphql::Error::new(
+                "You have already replied to this review".to_string(),
+            ));
+        }
+
+        let reply = reply.trim().to_string();
+        if reply.is_empty() {
+            return Err(async_graphql::Error::new(
+                "Reply can't be empty".to_string(),
+            ));
+        }
+
+        let replied = reviews::ActiveModel {
+            reply: Set(Some(reply)),
+            replied_at: Set(Some(Utc::now().naive_utc())),
+            ..review.into()
+        };
+
+        let review: reviews::Model = replied.update(&my_ctx.db).await?;
+
+        Ok(review)
+    }
+
+    async fn edit_review(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        review_id: i32,
+        #[graphql(validator(maximum = 5, minimum = 1))] rating: i32,
+        message: String,
+    ) -> Result<reviews::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+            Some(token) => token,
+            None => {
+                return Err(async_graphql::Error::new(
+                    "you are not logged in".to_string(),
+                ));
+            }
+        };
+
+        let claims = match verify_access_token(access_token, &my_ctx.access_key) {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+            id_str.parse().map_err(|_| {
+                async_graphql::Error::new("Invalid user ID in token: failed to parse string")
+            })?
+        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+            id_num as i32
+        } else {
+            return Err(async_graphql::Error::new(
+                "Invalid user ID in token: missing id",
+            ));
+        };
+
+        let review = Reviews::find_by_id(review_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("review not found"))?;
+
+        if review.user_id != user_id {
+            return Err(async_graphql::Error::new(
+                "You can only edit your own reviews".to_string(),
+            ));
+        }
+
+        if review.hidden {
+            return Err(async_graphql::Error::new(
+                "This review has been hidden by a moderator".to_string(),
+            ));
+        }
+
+        let now = Utc::now().naive_utc();
+        if now > review.created_at + chrono::Duration::hours(REVIEW_EDIT_WINDOW_HOURS) {
+            return Err(async_graphql::Error::new(
+                "This review can no longer be edited".to_string(),
+            ));
+        }
+
+        let subject_id = review.subject_id;
+        let edited = reviews::ActiveModel {
+            rating: Set(rating),
+            message: Set(message),
+            edited_at: Set(Some(now)),
+            ..review.into()
+        };
+
+        let review: reviews::Model = edited.update(&my_ctx.db).await?;
+
+        refresh_user_ratings(&my_ctx.db, subject_id).await?;
+
+        Ok(review)
+    }
+
+    /// Moderators hide a review with a reason, or restore it with `hidden: false`.
+    /// Hidden reviews are left out of every rating aggregate.
+    async fn hide_review(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        review_id: i32,
+        reason: Option<String>,
+        #[graphql(default = true)] hidden: bool,
+    ) -> Result<reviews::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+            Some(token) => token,
+            None => {
+                return Err(async_graphql::Error::new(
+                    "you are not logged in".to_string(),
+                ));
+            }
+        };
+
+        let claims = match verify_access_token(access_token, &my_ctx.access_key) {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+            id_str.parse().map_err(|_| {
+                async_graphql::Error::new("Invalid user ID in token: failed to parse string")
+            })?
+        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+            id_num as i32
+        } else {
+            return Err(async_graphql::Error::new(
+                "Invalid user ID in token: missing id",
+            ));
+        };
+
+        let req_user = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Invalid token."))?;
+
+        if req_user.role != Role::Admin && req_user.role != Role::Moderator {
+            return Err(async_graphql::Error::new(
+                "You are not authorized to moderate reviews".to_string(),
+            ));
+        }
+
+        let review = Reviews::find_by_id(review_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("review not found"))?;
+
+        let reason = reason
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty());
+
+        if hidden && reason.is_none() {
+            return Err(async_graphql::Error::new(
+                "A reason is required to hide a review".to_string(),
+            ));
+        }
+
+        let subject_id = review.subject_id;
+        let moderated = reviews::ActiveModel {
+            hidden: Set(hidden),
+            hidden_reason: Set(if hidden { reason } else { None }),
+            hidden_by: Set(if hidden { Some(user_id) } else { None }),
+            ..review.into()
+        };
+
+        let review: reviews::Model = moderated.update(&my_ctx.db).await?;
+
+        refresh_user_ratings(&my_ctx.db, subject_id).await?;
+
+        Ok(review)
+    }
+
+    async fn publish_advert(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+    ) -> Result<advert::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+            Some(token) => token,
+            None => {
+                return Err(async_graphql::Error::new(
+                    "you are not logged in".to_string(),
+                ));
+            }
+        };
+
+        let claims = match verify_access_token(access_token, &my_ctx.access_key) {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+            id_str.parse().map_err(|_| {
+                async_graphql::Error::new("Invalid user ID in token: failed to parse string")
+            })?
+        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+            id_num as i32
+        } else {
+            return Err(async_graphql::Error::new(
+                "Invalid user ID in token: missing id",
+            ));
+        };
+
+        let advert = Advert::find_by_id(advert_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("advert not found"))?;
+
+        if advert.user_id != user_id {
+            return Err(async_graphql::Error::new("you are not owner".to_string()));
+        }
+
+        if advert.status != AdvertStatus::Draft {
+            return Err(async_graphql::Error::new(
+                "Only drafts can be published".to_string(),
+            ));
+        }
+
+        let now = Utc::now().naive_utc();
+        let published = advert::ActiveModel {
+            status: Set(AdvertStatus::Active),
+            available: Set(true),
+            expires_at: Set(Some(
+                now + chrono::Duration::days(my_ctx.advert_lifetime_days),
+            )),
+            expiry_notified_at: Set(None),
+            updated_at: Set(now),
+            ..advert.into()
+        };
+
+        let adv: advert::Model = published.update(&my_ctx.db).await?;
+
+        Ok(adv)
+    }
+
+    async fn renew_advert(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+    ) -> Result<advert::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+            Some(token) => token,
+            None => {
+                return Err(async_graphql::Error::new(
+                    "you are not logged in".to_string(),
+                ));
+            }
+        };
+
+        let claims = match verify_access_token(access_token, &my_ctx.access_key) {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+            id_str.parse().map_err(|_| {
+                async_graphql::Error::new("Invalid user ID in token: failed to parse string")
+            })?
+        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+            id_num as i32
+        } else {
+            return Err(async_graphql::Error::new(
+                "Invalid user ID in token: missing id",
+            ));
+        };
+
+        let advert = Advert::find_by_id(advert_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("advert not found"))?;
+
+        if advert.user_id != user_id {
+            return Err(async_graphql::Error::new("you are not owner".to_string()));
+        }
+
+        if advert.status != AdvertStatus::Active && advert.status != AdvertStatus::Expired {
+            return Err(async_graphql::Error::new(
+                "Only active or expired adverts can be renewed".to_string(),
+            ));
+        }
+
+        let now = Utc::now().naive_utc();
+        let renewed = advert::ActiveModel {
+            status: Set(AdvertStatus::Active),
+            available: Set(true),
+            expires_at: Set(Some(
+                now + chrono::Duration::days(my_ctx.advert_lifetime_days),
+            )),
+            expiry_notified_at: Set(None),
+            updated_at: Set(now),
+            ..advert.into()
+        };
+
+        let adv: advert::Model = renewed.update(&my_ctx.db).await?;
+
+        Ok(adv)
+    }
+
+    async fn set_exchange_rate(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        currency: String,
+        rate: Decimal,
+    ) -> Result<exchange_rate::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+            Some(token) => token,
+            None => {
+                return Err(async_graphql::Error::new("You are not logged in."));
+            }
+        };
+
+        let claims = match verify_access_token(access_token, &my_ctx.access_key) {
+            Ok(claims) => claims,
+            Err(err) => return Err(err),
+        };
+
+        let user_id: i32 = if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
+            id_str.parse().map_err(|_| {
+                async_graphql::Error::new("Invalid user ID in token: failed to parse string")
+            })?
+        } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
+            id_num as i32
+        } else {
+            return Err(async_graphql::Error::new(
+                "Invalid user ID in token: missing id",
+            ));
+        };
+
+        let req_user = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Invalid token."))?;
+
+        if req_user.role != Role::Admin {
+            return Err(async_graphql::Error::new(
+                "You are not authorized to change exchange rates",
+            ));
+        }
+
+        let currency = currency::normalize_code(&currency)?;
+        if currency == my_ctx.base_currency {
+            return Err(async_graphql::Error::new(
+                "The base currency rate is fixed at 1",
+            ));
+        }
+        if rate <= Decimal::ZERO {
+            return Err(async_graphql::Error::new("Rate must be positive"));
+        }
+
+        let rate = currency::upsert_rate(&my_ctx.db, currency, rate).await?;
+
+        Ok(rate)
     }
 }
@@ -0,0 +1,133 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{NaiveDate, Utc};
+use deadpool_redis::{redis::cmd, Pool};
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement, TransactionTrait};
+
+use entity::advert;
+
+use crate::{user_id_from_token, ClientIp, Context};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// Views not yet written to `advert_stats_daily`, as `{advert_id}:{day}` -> count.
+const PENDING_KEY: &str = "advert_views:pending";
+/// `PENDING_KEY` is renamed to this while a flush is running, so views counted
+/// meanwhile start a fresh hash.
+const FLUSHING_KEY: &str = "advert_views:flushing";
+/// The dedup markers only have to outlive the day they are for.
+const SEEN_TTL_SECONDS: u64 = 2 * 24 * 60 * 60;
+
+/// Counts a view of `advert`, at most once per viewer (user, or IP for
+/// guests) and day. Owners looking at their own advert don't count.
+pub async fn record_view(
+    ctx: &async_graphql::Context<'_>,
+    advert: &advert::Model,
+) -> Result<(), async_graphql::Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+
+    let viewer = match (user_id_from_token(ctx).ok(), ctx.data_opt::<ClientIp>()) {
+        (Some(viewer_id), _) if viewer_id == advert.user_id => return Ok(()),
+        (Some(viewer_id), _) => format!("user:{}", viewer_id),
+        (None, Some(ClientIp(ip))) => format!("ip:{}", ip),
+        (None, None) => return Ok(()),
+    };
+    let day = Utc::now().date_naive();
+
+    let mut conn = my_ctx.redis_pool.get().await?;
+    let first_today: bool = cmd("SET")
+        .arg(format!("advert_view:{}:{}:{}", advert.id, day, viewer))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(SEEN_TTL_SECONDS)
+        .query_async::<Option<String>>(&mut conn)
+        .await?
+        .is_some();
+
+    if first_today {
+        cmd("HINCRBY")
+            .arg(PENDING_KEY)
+            .arg(format!("{}:{}", advert.id, day))
+            .arg(1)
+            .query_async::<()>(&mut conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub fn spawn_flusher(db: DatabaseConnection, redis_pool: Pool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = flush(&db, &redis_pool).await {
+                eprintln!("Failed to flush advert views: {}", err);
+            }
+        }
+    });
+}
+
+/// Moves the pending view counts into `advert_stats_daily`. A batch left over
+/// from a failed flush is retried before a new one is taken, so it is written
+/// in one transaction: a failure halfway leaves nothing to count twice.
+async fn flush(db: &DatabaseConnection, redis_pool: &Pool) -> Result<(), String> {
+    let mut conn = redis_pool.get().await.map_err(|e| e.to_string())?;
+
+    let leftover: bool = cmd("EXISTS")
+        .arg(FLUSHING_KEY)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !leftover {
+        // RENAMENX fails (and we stop) when nothing is pending.
+        let renamed: Result<bool, _> = cmd("RENAMENX")
+            .arg(PENDING_KEY)
+            .arg(FLUSHING_KEY)
+            .query_async(&mut conn)
+            .await;
+        if !matches!(renamed, Ok(true)) {
+            return Ok(());
+        }
+    }
+
+    let counts: HashMap<String, i64> = cmd("HGETALL")
+        .arg(FLUSHING_KEY)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let txn = db.begin().await.map_err(|e| e.to_string())?;
+    for (field, views) in counts {
+        let Some((advert_id, day)) = field.split_once(':').and_then(|(advert_id, day)| {
+            Some((
+                advert_id.parse::<i32>().ok()?,
+                day.parse::<NaiveDate>().ok()?,
+            ))
+        }) else {
+            continue;
+        };
+
+        // The advert may be gone by now; its views go with it.
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"INSERT INTO advert_stats_daily (advert_id, day, views)
+            SELECT id, $2, $3 FROM advert WHERE id = $1
+            ON CONFLICT (advert_id, day)
+            DO UPDATE SET views = advert_stats_daily.views + EXCLUDED.views"#,
+            [advert_id.into(), day.into(), (views as i32).into()],
+        ))
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    txn.commit().await.map_err(|e| e.to_string())?;
+
+    cmd("DEL")
+        .arg(FLUSHING_KEY)
+        .query_async::<()>(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use crate::{user_id_from_token, Context};
+use std::collections::BTreeMap;
+
+use actix_web::Result;
+use async_graphql::{Object, SimpleObject};
+use chrono::NaiveDate;
+use entity::{
+    advert::Entity as Advert,
+    advert_stats_daily::{self, Entity as AdvertStatsDaily},
+    chat::{self, Entity as Chat},
+    favorites::{self, Entity as Favorites},
+};
+use sea_orm::{
+    sea_query::{Alias, Expr, Func, SimpleExpr},
+    ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect,
+};
+
+/// Longest range `myAdvertStats` returns in one go.
+const MAX_STATS_DAYS: i64 = 366;
+
+#[derive(SimpleObject, Clone, Default)]
+#[graphql(name = "AdvertStatsDay")]
+pub struct AdvertStatsDay {
+    day: NaiveDate,
+    views: i64,
+    /// Times the advert was added to favorites that day.
+    favorites: i64,
+    /// Chats started about the advert that day.
+    chats: i64,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "AdvertStats")]
+pub struct AdvertStats {
+    advert_id: i32,
+    /// Totals over the requested range.
+    views: i64,
+    favorites: i64,
+    chats: i64,
+    /// Users who have the advert in their favorites right now.
+    current_favorites: i64,
+    /// One entry per day of the range, including days without activity.
+    days: Vec<AdvertStatsDay>,
+}
+
+/// `DATE(created_at)` for grouping timestamps by day.
+fn created_on(column: impl ColumnTrait) -> SimpleExpr {
+    Func::cust(Alias::new("DATE")).arg(Expr::col(column)).into()
+}
+
+#[derive(Default)]
+pub struct AdvertStatsQuery;
+
+#[Object]
+impl AdvertStatsQuery {
+    /// Daily views, favorites and chats of one of the caller's adverts between
+    /// `from` and `to` (UTC days, inclusive). Views show up within a minute.
+    async fn my_advert_stats(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<AdvertStats, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let advert = Advert::find_by_id(advert_id)
+            .one(&my_ctx.db)
+            .await?
+            .filter(|advert| advert.user_id == user_id)
+            .ok_or_else(|| async_graphql::Error::new("Advert not found"))?;
+
+        if to < from {
+            return Err(async_graphql::Error::new("`to` is before `from`"));
+        }
+        if (to - from).num_days() >= MAX_STATS_DAYS {
+            return Err(async_graphql::Error::new(format!(
+                "Ask for at most {} days at a time",
+                MAX_STATS_DAYS
+            )));
+        }
+
+        let mut days: BTreeMap<NaiveDate, AdvertStatsDay> = from
+            .iter_days()
+            .take_while(|day| *day <= to)
+            .map(|day| {
+                (
+                    day,
+                    AdvertStatsDay {
+                        day,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+
+        let views: Vec<(NaiveDate, i32)> = AdvertStatsDaily::find()
+            .select_only()
+            .column(advert_stats_daily::Column::Day)
+            .column(advert_stats_daily::Column::Views)
+            .filter(advert_stats_daily::Column::AdvertId.eq(advert.id))
+            .filter(advert_stats_daily::Column::Day.between(from, to))
+            .into_tuple()
+            .all(&my_ctx.db)
+            .await?;
+        for (day, count) in views {
+            if let Some(entry) = days.get_mut(&day) {
+                entry.views = count as i64;
+            }
+        }
+
+        let favorites: Vec<(NaiveDate, i64)> = Favorites::find()
+            .select_only()
+            .column_as(created_on(favorites::Column::CreatedAt), "day")
+            .column_as(Expr::col(favorites::Column::Id).count(), "count")
+            .filter(favorites::Column::AdvertId.eq(advert.id))
+            .filter(Expr::expr(created_on(favorites::Column::CreatedAt)).between(from, to))
+            .group_by(created_on(favorites::Column::CreatedAt))
+            .into_tuple()
+            .all(&my_ctx.db)
+            .await?;
+        for (day, count) in favorites {
+            if let Some(entry) = days.get_mut(&day) {
+                entry.favorites = count;
+            }
+        }
+
+        let chats: Vec<(NaiveDate, i64)> = Chat::find()
+            .select_only()
+            .column_as(created_on(chat::Column::CreatedAt), "day")
+            .column_as(Expr::col(chat::Column::Id).count(), "count")
+            .filter(chat::Column::AdvertId.eq(advert.id))
+            .filter(Expr::expr(created_on(chat::Column::CreatedAt)).between(from, to))
+            .group_by(created_on(chat::Column::CreatedAt))
+            .into_tuple()
+            .all(&my_ctx.db)
+            .await?;
+        for (day, count) in chats {
+            if let Some(entry) = days.get_mut(&day) {
+                entry.chats = count;
+            }
+        }
+
+        let current_favorites = Favorites::find()
+            .filter(favorites::Column::AdvertId.eq(advert.id))
+            .count(&my_ctx.db)
+            .await? as i64;
+
+        let days: Vec<AdvertStatsDay> = days.into_values().collect();
+        Ok(AdvertStats {
+            advert_id: advert.id,
+            views: days.iter().map(|day| day.views).sum(),
+            favorites: days.iter().map(|day| day.favorites).sum(),
+            chats: days.iter().map(|day| day.chats).sum(),
+            current_favorites,
+            days,
+        })
+    }
+}
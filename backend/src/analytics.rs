@@ -0,0 +1,235 @@
+use std::future::Future;
+
+use async_graphql::SimpleObject;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use deadpool_redis::redis::cmd;
+use entity::{
+    advert::{self, Entity as Advert},
+    user::{self, Entity as User},
+};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait, FromQueryResult,
+    PaginatorTrait, QueryFilter, Statement,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{Context, Statistics};
+
+/// Admin analytics may lag this far behind.
+const ANALYTICS_CACHE_SECONDS: u64 = 5 * 60;
+/// The public counters are cheap but asked for on every page load.
+const STATS_CACHE_SECONDS: u64 = 60;
+
+#[derive(SimpleObject, FromQueryResult, Serialize, Deserialize, Clone, Debug)]
+#[graphql(name = "AnalyticsDay")]
+pub struct AnalyticsDay {
+    pub day: NaiveDate,
+    pub signups: i64,
+    pub new_adverts: i64,
+    pub sold_adverts: i64,
+    /// Deals proposed, whatever became of them.
+    pub deals: i64,
+    pub messages: i64,
+    pub reviews: i64,
+    /// Users who posted, sold, messaged, favorited, reviewed or proposed a deal.
+    pub active_users: i64,
+}
+
+#[derive(SimpleObject, FromQueryResult, Serialize, Deserialize, Clone, Debug)]
+#[graphql(name = "CategoryAnalytics")]
+pub struct CategoryAnalytics {
+    pub category: String,
+    /// Posted within the range.
+    pub new_adverts: i64,
+    /// Sold within the range.
+    pub sold_adverts: i64,
+    /// Active right now.
+    pub active_adverts: i64,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone, Debug)]
+#[graphql(name = "Analytics")]
+pub struct Analytics {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: Vec<AnalyticsDay>,
+    pub categories: Vec<CategoryAnalytics>,
+    pub generated_at: NaiveDateTime,
+}
+
+/// Returns the value cached under `key`, or computes and caches it for
+/// `ttl_seconds`.
+async fn cached<T, F, Fut>(
+    my_ctx: &Context,
+    key: &str,
+    ttl_seconds: u64,
+    compute: F,
+) -> Result<T, async_graphql::Error>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T, DbErr>>,
+{
+    let mut conn = my_ctx.redis_pool.get().await?;
+
+    let hit: Option<String> = cmd("GET").arg(key).query_async(&mut conn).await?;
+    if let Some(value) = hit.and_then(|hit| serde_json::from_str(&hit).ok()) {
+        return Ok(value);
+    }
+
+    let value = compute().await?;
+    cmd("SET")
+        .arg(key)
+        .arg(serde_json::to_string(&value)?)
+        .arg("EX")
+        .arg(ttl_seconds)
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+/// Per-day series for `from..=to` (UTC), with zeroes on quiet days.
+const DAILY_SQL: &str = r#"
+WITH days AS (
+    SELECT generate_series($1::date, $2::date - 1, interval '1 day')::date AS day
+),
+signups AS (
+    SELECT DATE(created_at) AS day, COUNT(*) AS count FROM "user"
+    WHERE created_at >= $1 AND created_at < $2 GROUP BY 1
+),
+new_adverts AS (
+    SELECT DATE(created_at) AS day, COUNT(*) AS count FROM advert
+    WHERE created_at >= $1 AND created_at < $2 GROUP BY 1
+),
+sold_adverts AS (
+    SELECT DATE(sold_at) AS day, COUNT(*) AS count FROM advert
+    WHERE sold_at >= $1 AND sold_at < $2 GROUP BY 1
+),
+deals AS (
+    SELECT DATE(created_at) AS day, COUNT(*) AS count FROM deal
+    WHERE created_at >= $1 AND created_at < $2 GROUP BY 1
+),
+messages AS (
+    SELECT DATE(created_at) AS day, COUNT(*) AS count FROM message
+    WHERE created_at >= $1 AND created_at < $2 GROUP BY 1
+),
+reviews AS (
+    SELECT DATE(created_at) AS day, COUNT(*) AS count FROM reviews
+    WHERE created_at >= $1 AND created_at < $2 GROUP BY 1
+),
+activity AS (
+    SELECT created_at, user_id FROM advert WHERE created_at >= $1 AND created_at < $2
+    UNION ALL
+    SELECT sold_at, user_id FROM advert WHERE sold_at >= $1 AND sold_at < $2
+    UNION ALL
+    SELECT created_at, user_id FROM message WHERE created_at >= $1 AND created_at < $2
+    UNION ALL
+    SELECT created_at, user_id FROM favorites WHERE created_at >= $1 AND created_at < $2
+    UNION ALL
+    SELECT created_at, user_id FROM reviews WHERE created_at >= $1 AND created_at < $2
+    UNION ALL
+    SELECT created_at, requester_id FROM deal WHERE created_at >= $1 AND created_at < $2
+),
+active_users AS (
+    SELECT DATE(created_at) AS day, COUNT(DISTINCT user_id) AS count FROM activity GROUP BY 1
+)
+SELECT
+    days.day,
+    COALESCE(signups.count, 0) AS signups,
+    COALESCE(new_adverts.count, 0) AS new_adverts,
+    COALESCE(sold_adverts.count, 0) AS sold_adverts,
+    COALESCE(deals.count, 0) AS deals,
+    COALESCE(messages.count, 0) AS messages,
+    COALESCE(reviews.count, 0) AS reviews,
+    COALESCE(active_users.count, 0) AS active_users
+FROM days
+LEFT JOIN signups USING (day)
+LEFT JOIN new_adverts USING (day)
+LEFT JOIN sold_adverts USING (day)
+LEFT JOIN deals USING (day)
+LEFT JOIN messages USING (day)
+LEFT JOIN reviews USING (day)
+LEFT JOIN active_users USING (day)
+ORDER BY days.day
+"#;
+
+const CATEGORY_SQL: &str = r#"
+SELECT
+    category,
+    COUNT(*) FILTER (WHERE created_at >= $1 AND created_at < $2) AS new_adverts,
+    COUNT(*) FILTER (WHERE sold_at >= $1 AND sold_at < $2) AS sold_adverts,
+    COUNT(*) FILTER (WHERE status = 'active') AS active_adverts
+FROM advert
+GROUP BY category
+ORDER BY new_adverts DESC, category
+"#;
+
+async fn compute_analytics(
+    db: &DatabaseConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Analytics, DbErr> {
+    let start = from.and_hms_opt(0, 0, 0).unwrap();
+    let end = (to + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap();
+
+    let days = AnalyticsDay::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        DAILY_SQL,
+        [start.into(), end.into()],
+    ))
+    .all(db)
+    .await?;
+    let categories = CategoryAnalytics::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        CATEGORY_SQL,
+        [start.into(), end.into()],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(Analytics {
+        from,
+        to,
+        days,
+        categories,
+        generated_at: Utc::now().naive_utc(),
+    })
+}
+
+pub async fn analytics(
+    my_ctx: &Context,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Analytics, async_graphql::Error> {
+    let key = format!("analytics:{}:{}", from, to);
+    cached(my_ctx, &key, ANALYTICS_CACHE_SECONDS, || {
+        compute_analytics(&my_ctx.db, from, to)
+    })
+    .await
+}
+
+async fn compute_statistics(db: &DatabaseConnection) -> Result<Statistics, DbErr> {
+    let today = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap();
+
+    Ok(Statistics {
+        user_count: User::find().count(db).await?,
+        advert_count: Advert::find().count(db).await?,
+        today_user_count: User::find()
+            .filter(user::Column::CreatedAt.gte(today))
+            .count(db)
+            .await?,
+        today_advert_count: Advert::find()
+            .filter(advert::Column::CreatedAt.gte(today))
+            .count(db)
+            .await?,
+    })
+}
+
+/// The public site counters, recounted at most once a minute.
+pub async fn statistics(my_ctx: &Context) -> Result<Statistics, async_graphql::Error> {
+    cached(my_ctx, "analytics:stats", STATS_CACHE_SECONDS, || {
+        compute_statistics(&my_ctx.db)
+    })
+    .await
+}
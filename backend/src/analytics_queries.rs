@@ -0,0 +1,61 @@
+use crate::{
+    analytics::{self, Analytics},
+    user_id_from_token, Context,
+};
+
+use actix_web::Result;
+use async_graphql::Object;
+use chrono::{Duration, NaiveDate, Utc};
+use entity::user::{self, Entity as User, Role};
+use sea_orm::EntityTrait;
+
+/// Range shown when the admin doesn't pick one.
+const DEFAULT_ANALYTICS_DAYS: i64 = 30;
+const MAX_ANALYTICS_DAYS: i64 = 366;
+
+async fn require_admin(ctx: &async_graphql::Context<'_>) -> Result<(), async_graphql::Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    let user_id = user_id_from_token(ctx)?;
+
+    let caller: Option<user::Model> = User::find_by_id(user_id).one(&my_ctx.db).await?;
+    match caller {
+        Some(caller) if caller.role == Role::Admin => Ok(()),
+        Some(_) => Err(async_graphql::Error::new(
+            "You are not authorized to view analytics",
+        )),
+        None => Err(async_graphql::Error::new("Wrong token")),
+    }
+}
+
+#[derive(Default)]
+pub struct AnalyticsQuery;
+
+#[Object]
+impl AnalyticsQuery {
+    /// Daily activity and per-category advert counts between `from` and `to`
+    /// (UTC days, inclusive; the last 30 days by default). Cached for a few
+    /// minutes. Admins only.
+    async fn analytics(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Analytics, async_graphql::Error> {
+        require_admin(ctx).await?;
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let to = to.unwrap_or_else(|| Utc::now().date_naive());
+        let from = from.unwrap_or(to - Duration::days(DEFAULT_ANALYTICS_DAYS - 1));
+        if to < from {
+            return Err(async_graphql::Error::new("`to` is before `from`"));
+        }
+        if (to - from).num_days() >= MAX_ANALYTICS_DAYS {
+            return Err(async_graphql::Error::new(format!(
+                "Ask for at most {} days at a time",
+                MAX_ANALYTICS_DAYS
+            )));
+        }
+
+        analytics::analytics(my_ctx, from, to).await
+    }
+}
@@ -0,0 +1,91 @@
+use async_graphql::{Error, ErrorExtensions};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{fmt::Debug, sync::Arc};
+
+/// Checks the answer to a captcha widget. Implementations are picked by
+/// `CAPTCHA_PROVIDER`.
+#[async_trait]
+pub trait CaptchaVerifier: Debug + Send + Sync {
+    /// Whether challenges are shown at all; when not, callers skip them.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    async fn verify(&self, token: &str, remote_ip: Option<&str>) -> Result<bool, String>;
+}
+
+/// No captcha configured: nobody is challenged.
+#[derive(Debug)]
+pub struct NoCaptcha;
+
+#[async_trait]
+impl CaptchaVerifier for NoCaptcha {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    async fn verify(&self, _token: &str, _remote_ip: Option<&str>) -> Result<bool, String> {
+        Ok(true)
+    }
+}
+
+/// Any service speaking the reCAPTCHA `siteverify` protocol, which hCaptcha
+/// and Cloudflare Turnstile share.
+#[derive(Debug)]
+pub struct SiteVerifyCaptcha {
+    verify_url: String,
+    secret: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+#[async_trait]
+impl CaptchaVerifier for SiteVerifyCaptcha {
+    async fn verify(&self, token: &str, remote_ip: Option<&str>) -> Result<bool, String> {
+        let mut form = vec![("secret", self.secret.as_str()), ("response", token)];
+        if let Some(remote_ip) = remote_ip {
+            form.push(("remoteip", remote_ip));
+        }
+
+        let response: SiteVerifyResponse = self
+            .client
+            .post(&self.verify_url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Captcha verification failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid captcha response: {}", e))?;
+
+        Ok(response.success)
+    }
+}
+
+pub fn from_env() -> Arc<dyn CaptchaVerifier> {
+    let provider = dotenvy::var("CAPTCHA_PROVIDER").unwrap_or_else(|_| "none".to_string());
+    match provider.as_str() {
+        "none" => Arc::new(NoCaptcha),
+        "siteverify" => Arc::new(SiteVerifyCaptcha {
+            verify_url: dotenvy::var("CAPTCHA_VERIFY_URL")
+                .expect("CAPTCHA_VERIFY_URL environment variable not found"),
+            secret: dotenvy::var("CAPTCHA_SECRET")
+                .expect("CAPTCHA_SECRET environment variable not found"),
+            client: reqwest::Client::new(),
+        }),
+        other => panic!("Unknown CAPTCHA_PROVIDER: {}", other),
+    }
+}
+
+/// Tells the client to show the captcha widget and retry with its token.
+pub fn captcha_required() -> Error {
+    Error::new("Please complete the captcha").extend_with(|_, extensions| {
+        extensions.set("code", "CAPTCHA_REQUIRED");
+    })
+}
@@ -0,0 +1,177 @@
+use crate::{
+    captcha::captcha_required,
+    rate_limit::{self, AuthOperation},
+    user_id_from_token, ClientIp, Context,
+};
+
+use actix_web::Result;
+use async_graphql::{dataloader::DataLoader, Object, SimpleObject};
+use chrono::Utc;
+use entity::{
+    advert::{self, AdvertStatus, Entity as Advert},
+    contact_reveal::{self, Entity as ContactReveal},
+    loader::{UserAccess, UserAccessLoader},
+    user::{self, Entity as User},
+};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, EntityTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+#[derive(SimpleObject)]
+#[graphql(name = "ContactRevealCount")]
+pub struct ContactRevealCount {
+    advert_id: i32,
+    /// Distinct users who revealed the phone number.
+    reveals: i64,
+}
+
+async fn find_user(my_ctx: &Context, user_id: i32) -> Result<user::Model, async_graphql::Error> {
+    User::find_by_id(user_id)
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Wrong token"))
+}
+
+#[derive(Default)]
+pub struct ContactQuery;
+
+#[Object]
+impl ContactQuery {
+    /// How many people revealed the caller's phone number, per advert.
+    async fn my_contact_reveals(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<Vec<ContactRevealCount>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let counts: Vec<(i32, i64)> = ContactReveal::find()
+            .select_only()
+            .column(contact_reveal::Column::AdvertId)
+            .column_as(Expr::col(contact_reveal::Column::Id).count(), "reveals")
+            .filter(contact_reveal::Column::SellerId.eq(user_id))
+            .group_by(contact_reveal::Column::AdvertId)
+            .order_by(contact_reveal::Column::AdvertId, Order::Desc)
+            .into_tuple()
+            .all(&my_ctx.db)
+            .await?;
+
+        Ok(counts
+            .into_iter()
+            .map(|(advert_id, reveals)| ContactRevealCount { advert_id, reveals })
+            .collect())
+    }
+}
+
+#[derive(Default)]
+pub struct ContactMutation;
+
+#[Object]
+impl ContactMutation {
+    /// The seller's phone number for a verified, logged-in user. Throttled per
+    /// user; past a daily threshold it also wants a `captchaToken`.
+    async fn reveal_contact(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+        captcha_token: Option<String>,
+    ) -> Result<String, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let viewer = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+        if viewer.banned {
+            return Err(async_graphql::Error::new("You are banned"));
+        }
+        if !viewer.email_verified && !viewer.phone_verified {
+            return Err(async_graphql::Error::new(
+                "Verify your email or phone number to see contact details",
+            ));
+        }
+
+        let advert = Advert::find_by_id(advert_id)
+            .filter(advert::Column::Status.is_in([AdvertStatus::Active, AdvertStatus::Reserved]))
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Advert not found"))?;
+        let seller = find_user(my_ctx, advert.user_id).await?;
+        let phone = seller
+            .phone
+            .clone()
+            .ok_or_else(|| async_graphql::Error::new("The seller has no phone number"))?;
+
+        if seller.id == viewer.id {
+            return Ok(phone);
+        }
+        if !seller.show_phone {
+            let access = ctx
+                .data::<DataLoader<UserAccessLoader>>()?
+                .load_one(seller.id)
+                .await?
+                .unwrap_or(UserAccess::Public);
+            if access == UserAccess::Public {
+                return Err(async_graphql::Error::new(
+                    "The seller shares their number in chat only",
+                ));
+            }
+        }
+
+        // Showing a number again gives nothing new away.
+        let revealed_before = ContactReveal::find()
+            .filter(contact_reveal::Column::AdvertId.eq(advert.id))
+            .filter(contact_reveal::Column::ViewerId.eq(viewer.id))
+            .one(&my_ctx.db)
+            .await?
+            .is_some();
+        if revealed_before {
+            return Ok(phone);
+        }
+
+        rate_limit::check(
+            ctx,
+            AuthOperation::RevealContact,
+            Some(&viewer.id.to_string()),
+        )
+        .await?;
+
+        if my_ctx.captcha.enabled() {
+            let since = Utc::now().naive_utc() - chrono::Duration::days(1);
+            let recent = ContactReveal::find()
+                .filter(contact_reveal::Column::ViewerId.eq(viewer.id))
+                .filter(contact_reveal::Column::CreatedAt.gt(since))
+                .count(&my_ctx.db)
+                .await?;
+            if recent >= my_ctx.rate_limits.reveal_captcha_threshold {
+                let token = captcha_token.ok_or_else(captcha_required)?;
+                let ip = ctx.data_opt::<ClientIp>().map(|ClientIp(ip)| ip.as_str());
+                let solved = my_ctx
+                    .captcha
+                    .verify(&token, ip)
+                    .await
+                    .map_err(async_graphql::Error::new)?;
+                if !solved {
+                    return Err(captcha_required());
+                }
+            }
+        }
+
+        ContactReveal::insert(contact_reveal::ActiveModel {
+            advert_id: Set(advert.id),
+            seller_id: Set(seller.id),
+            viewer_id: Set(viewer.id),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                contact_reveal::Column::AdvertId,
+                contact_reveal::Column::ViewerId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(&my_ctx.db)
+        .await?;
+
+        Ok(phone)
+    }
+}
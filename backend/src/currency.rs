@@ -0,0 +1,153 @@
+use std::{collections::HashMap, str::FromStr};
+
+use chrono::Utc;
+use entity::{
+    exchange_rate::{self, Entity as ExchangeRate},
+    money::Money,
+};
+use sea_orm::{
+    prelude::Decimal, sea_query::OnConflict, ActiveModelTrait, DatabaseConnection, DbErr,
+    EntityTrait, Set, TransactionTrait,
+};
+
+pub fn normalize_code(code: &str) -> Result<String, async_graphql::Error> {
+    let code = code.trim().to_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(async_graphql::Error::new(format!(
+            "Invalid currency code: {}",
+            code
+        )));
+    }
+    Ok(code)
+}
+
+pub async fn load_rates(db: &DatabaseConnection) -> Result<HashMap<String, Decimal>, DbErr> {
+    let rates = ExchangeRate::find().all(db).await?;
+    Ok(rates
+        .into_iter()
+        .map(|rate| (rate.currency, rate.rate))
+        .collect())
+}
+
+/// Converts through the base currency; `None` if either side has no rate.
+pub fn convert(
+    amount: Money,
+    from: &str,
+    to: &str,
+    rates: &HashMap<String, Decimal>,
+) -> Option<Money> {
+    if from == to {
+        return Some(amount);
+    }
+    let from_rate = rates.get(from)?;
+    let to_rate = rates.get(to)?;
+    let converted = amount.0.checked_div(*from_rate)?.checked_mul(*to_rate)?;
+    Some(Money(converted.round_dp(2)))
+}
+
+pub async fn upsert_rate(
+    db: &DatabaseConnection,
+    currency: String,
+    rate: Decimal,
+) -> Result<exchange_rate::Model, DbErr> {
+    let model = exchange_rate::ActiveModel {
+        currency: Set(currency.clone()),
+        rate: Set(rate),
+        updated_at: Set(Utc::now().naive_utc()),
+    };
+
+    ExchangeRate::insert(model)
+        .on_conflict(
+            OnConflict::column(exchange_rate::Column::Currency)
+                .update_columns([
+                    exchange_rate::Column::Rate,
+                    exchange_rate::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+    ExchangeRate::find_by_id(currency)
+        .one(db)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound("exchange rate".to_string()))
+}
+
+/// Makes `base` the currency all rates are relative to. When it changes, rates
+/// stored against the old base are rebased through the new base's rate, or
+/// dropped if the new base had none, so no stale row keeps converting.
+pub async fn set_base_currency(db: &DatabaseConnection, base: &str) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    let rates = ExchangeRate::find().all(&txn).await?;
+    let base_rate = rates
+        .iter()
+        .find(|rate| rate.currency == base)
+        .map(|rate| rate.rate);
+    let now = Utc::now().naive_utc();
+
+    match base_rate {
+        Some(base_rate) if base_rate == Decimal::ONE => {}
+        Some(base_rate) => {
+            for rate in rates {
+                exchange_rate::ActiveModel {
+                    rate: Set(rate.rate / base_rate),
+                    updated_at: Set(now),
+                    ..rate.into()
+                }
+                .update(&txn)
+                .await?;
+            }
+        }
+        None => {
+            let dropped = ExchangeRate::delete_many().exec(&txn).await?.rows_affected;
+            if dropped > 0 {
+                eprintln!(
+                    "Base currency changed to {}: dropped {} rates stored against the old base",
+                    base, dropped
+                );
+            }
+            exchange_rate::ActiveModel {
+                currency: Set(base.to_string()),
+                rate: Set(Decimal::ONE),
+                updated_at: Set(now),
+            }
+            .insert(&txn)
+            .await?;
+        }
+    }
+
+    txn.commit().await
+}
+
+/// Imports `CODE,RATE` lines (blank lines and `#` comments are skipped).
+pub async fn import_file(db: &DatabaseConnection, path: &str) -> Result<usize, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut imported = 0;
+
+    for (line_no, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (code, rate) = line
+            .split_once(',')
+            .ok_or_else(|| format!("{}:{}: expected CODE,RATE", path, line_no + 1))?;
+        let code =
+            normalize_code(code).map_err(|e| format!("{}:{}: {}", path, line_no + 1, e.message))?;
+        let rate = Decimal::from_str(rate.trim())
+            .map_err(|e| format!("{}:{}: {}", path, line_no + 1, e))?;
+        if rate <= Decimal::ZERO {
+            return Err(format!("{}:{}: rate must be positive", path, line_no + 1));
+        }
+
+        upsert_rate(db, code, rate)
+            .await
+            .map_err(|e| e.to_string())?;
+        imported += 1;
+    }
+
+    Ok(imported)
+}
@@ -0,0 +1,297 @@
+use crate::{user_id_from_token, Context};
+use std::collections::HashMap;
+
+use actix_web::Result;
+use async_graphql::{Object, SimpleObject};
+use chrono::Utc;
+use entity::{
+    advert::{self, AdvertStatus, Entity as Advert},
+    favorite_collection::{self, Entity as FavoriteCollection},
+    favorites::{self, Entity as Favorites},
+};
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, ModelTrait, Order, QueryFilter, QueryOrder,
+    QueryTrait, Set,
+};
+
+const SHARE_TOKEN_LENGTH: usize = 32;
+
+/// Read-only view of a shared collection: no owner details, no notes.
+#[derive(SimpleObject)]
+#[graphql(name = "SharedFavoriteCollection")]
+pub struct SharedFavoriteCollection {
+    name: String,
+    adverts: Vec<advert::Model>,
+}
+
+async fn owned_collection(
+    my_ctx: &Context,
+    user_id: i32,
+    collection_id: i32,
+) -> Result<favorite_collection::Model, async_graphql::Error> {
+    FavoriteCollection::find_by_id(collection_id)
+        .one(&my_ctx.db)
+        .await?
+        .filter(|collection| collection.user_id == user_id)
+        .ok_or_else(|| async_graphql::Error::new("Collection not found"))
+}
+
+async fn owned_favorite(
+    my_ctx: &Context,
+    user_id: i32,
+    advert_id: i32,
+) -> Result<favorites::Model, async_graphql::Error> {
+    Favorites::find()
+        .filter(favorites::Column::UserId.eq(user_id))
+        .filter(favorites::Column::AdvertId.eq(advert_id))
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Wrong favorite"))
+}
+
+fn new_share_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SHARE_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Default)]
+pub struct FavoriteQuery;
+
+#[Object]
+impl FavoriteQuery {
+    async fn favorite_collections(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<Vec<favorite_collection::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let collections = FavoriteCollection::find()
+            .filter(favorite_collection::Column::UserId.eq(user_id))
+            .order_by(favorite_collection::Column::CreatedAt, Order::Asc)
+            .all(&my_ctx.db)
+            .await?;
+
+        Ok(collections)
+    }
+
+    /// The caller's favorites with their notes and adverts, optionally for one collection.
+    async fn favorite_entries(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        collection_id: Option<i32>,
+    ) -> Result<Vec<favorites::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let favorites = Favorites::find()
+            .filter(favorites::Column::UserId.eq(user_id))
+            .apply_if(collection_id, |query, collection_id| {
+                query.filter(favorites::Column::CollectionId.eq(collection_id))
+            })
+            .order_by(favorites::Column::CreatedAt, Order::Desc)
+            .find_also_related(Advert)
+            .all(&my_ctx.db)
+            .await?;
+
+        Ok(favorites
+            .into_iter()
+            .map(|(mut favorite, advert)| {
+                favorite.advert = advert;
+                favorite
+            })
+            .collect())
+    }
+
+    /// Public, read-only access to a collection through its share link.
+    async fn shared_favorite_collection(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        share_token: String,
+    ) -> Result<SharedFavoriteCollection, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let collection = FavoriteCollection::find()
+            .filter(favorite_collection::Column::ShareToken.eq(share_token))
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Collection not found"))?;
+
+        let favorites = collection
+            .find_related(Favorites)
+            .order_by(favorites::Column::CreatedAt, Order::Desc)
+            .all(&my_ctx.db)
+            .await?;
+
+        let order: HashMap<i32, usize> = favorites
+            .iter()
+            .enumerate()
+            .map(|(index, favorite)| (favorite.advert_id, index))
+            .collect();
+
+        let mut adverts = Advert::find()
+            .filter(advert::Column::Id.is_in(order.keys().copied()))
+            .filter(advert::Column::Status.ne(AdvertStatus::Draft))
+            .all(&my_ctx.db)
+            .await?;
+        adverts.sort_by_key(|advert| order.get(&advert.id).copied());
+
+        Ok(SharedFavoriteCollection {
+            name: collection.name,
+            adverts,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct FavoriteMutation;
+
+#[Object]
+impl FavoriteMutation {
+    async fn create_favorite_collection(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 100))] name: String,
+    ) -> Result<favorite_collection::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let collection = favorite_collection::ActiveModel {
+            user_id: Set(user_id),
+            name: Set(name.trim().to_string()),
+            share_token: Set(None),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        let collection: favorite_collection::Model = collection.insert(&my_ctx.db).await?;
+
+        Ok(collection)
+    }
+
+    async fn rename_favorite_collection(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        collection_id: i32,
+        #[graphql(validator(min_length = 1, max_length = 100))] name: String,
+    ) -> Result<favorite_collection::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        let collection = owned_collection(my_ctx, user_id, collection_id).await?;
+
+        let renamed = favorite_collection::ActiveModel {
+            name: Set(name.trim().to_string()),
+            ..collection.into()
+        };
+
+        let collection: favorite_collection::Model = renamed.update(&my_ctx.db).await?;
+
+        Ok(collection)
+    }
+
+    /// Deletes the collection; its favorites stay, just uncollected.
+    async fn delete_favorite_collection(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        collection_id: i32,
+    ) -> Result<favorite_collection::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        let collection = owned_collection(my_ctx, user_id, collection_id).await?;
+
+        collection.clone().delete(&my_ctx.db).await?;
+
+        Ok(collection)
+    }
+
+    async fn move_favorite(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+        collection_id: Option<i32>,
+    ) -> Result<favorites::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        if let Some(collection_id) = collection_id {
+            owned_collection(my_ctx, user_id, collection_id).await?;
+        }
+        let favorite = owned_favorite(my_ctx, user_id, advert_id).await?;
+
+        let moved = favorites::ActiveModel {
+            collection_id: Set(collection_id),
+            ..favorite.into()
+        };
+
+        let favorite: favorites::Model = moved.update(&my_ctx.db).await?;
+
+        Ok(favorite)
+    }
+
+    async fn set_favorite_note(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+        #[graphql(validator(max_length = 1000))] note: Option<String>,
+    ) -> Result<favorites::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        let favorite = owned_favorite(my_ctx, user_id, advert_id).await?;
+
+        let note = note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty());
+
+        let noted = favorites::ActiveModel {
+            note: Set(note),
+            ..favorite.into()
+        };
+
+        let favorite: favorites::Model = noted.update(&my_ctx.db).await?;
+
+        Ok(favorite)
+    }
+
+    /// Creates a share link token for the collection, or rotates the existing one.
+    async fn share_favorite_collection(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        collection_id: i32,
+    ) -> Result<favorite_collection::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        let collection = owned_collection(my_ctx, user_id, collection_id).await?;
+
+        let shared = favorite_collection::ActiveModel {
+            share_token: Set(Some(new_share_token())),
+            ..collection.into()
+        };
+
+        let collection: favorite_collection::Model = shared.update(&my_ctx.db).await?;
+
+        Ok(collection)
+    }
+
+    async fn unshare_favorite_collection(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        collection_id: i32,
+    ) -> Result<favorite_collection::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        let collection = owned_collection(my_ctx, user_id, collection_id).await?;
+
+        let unshared = favorite_collection::ActiveModel {
+            share_token: Set(None),
+            ..collection.into()
+        };
+
+        let collection: favorite_collection::Model = unshared.update(&my_ctx.db).await?;
+
+        Ok(collection)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{jobs::redacted_payload, user_id_from_token, Context};
+
+use actix_web::Result;
+use async_graphql::Object;
+use chrono::Utc;
+use entity::{
+    job::{self, Entity as Job, JobStatus},
+    user::{self, Entity as User, Role},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect,
+    QueryTrait, Set,
+};
+
+async fn require_admin(ctx: &async_graphql::Context<'_>) -> Result<(), async_graphql::Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    let user_id = user_id_from_token(ctx)?;
+
+    let caller: Option<user::Model> = User::find_by_id(user_id).one(&my_ctx.db).await?;
+    match caller {
+        Some(caller) if caller.role == Role::Admin => Ok(()),
+        Some(_) => Err(async_graphql::Error::new(
+            "You are not authorized to manage jobs",
+        )),
+        None => Err(async_graphql::Error::new("Wrong token")),
+    }
+}
+
+/// Payloads can hold addresses and message bodies, so admins only see their shape.
+fn redacted(job: job::Model) -> job::Model {
+    job::Model {
+        payload: redacted_payload(&job.payload),
+        ..job
+    }
+}
+
+#[derive(Default)]
+pub struct JobQuery;
+
+#[Object]
+impl JobQuery {
+    /// Queued jobs, newest first. Filter on `DEAD` for the dead-letter list.
+    async fn jobs(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        status: Option<JobStatus>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<job::Model>, async_graphql::Error> {
+        require_admin(ctx).await?;
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let jobs = Job::find()
+            .apply_if(status, |query, status| {
+                query.filter(job::Column::Status.eq(status))
+            })
+            .order_by(job::Column::CreatedAt, Order::Desc)
+            .offset(offset.max(0) as u64)
+            .limit(limit.clamp(0, 100) as u64)
+            .all(&my_ctx.db)
+            .await?
+            .into_iter()
+            .map(redacted)
+            .collect();
+
+        Ok(jobs)
+    }
+}
+
+#[derive(Default)]
+pub struct JobMutation;
+
+#[Object]
+impl JobMutation {
+    /// Puts a dead (or stuck) job back in the queue with a fresh set of attempts.
+    async fn retry_job(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        job_id: i32,
+    ) -> Result<job::Model, async_graphql::Error> {
+        require_admin(ctx).await?;
+        let my_ctx = ctx.data::<Context>().unwrap();
+
+        let job = Job::find_by_id(job_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Job not found"))?;
+
+        if job.status == JobStatus::Done {
+            return Err(async_graphql::Error::new("Job already completed"));
+        }
+
+        let now = Utc::now().naive_utc();
+        let retried = job::ActiveModel {
+            status: Set(JobStatus::Pending),
+            attempts: Set(0),
+            run_at: Set(now),
+            locked_at: Set(None),
+            updated_at: Set(now),
+            ..job.into()
+        };
+
+        let job: job::Model = retried.update(&my_ctx.db).await?;
+
+        Ok(redacted(job))
+    }
+}
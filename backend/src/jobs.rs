@@ -0,0 +1,341 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use deadpool_redis::Pool;
+use entity::job::{self, Entity as Job, JobStatus};
+use hmac::Hmac;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+    EntityTrait, QueryFilter, Set, Statement,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::{
+    account::anonymise_user,
+    account_emails::{self, Email},
+    mail::send_email,
+};
+
+const MAX_ATTEMPTS: i32 = 5;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const BASE_BACKOFF_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 60 * 60;
+/// A job still `running` after this long belongs to a worker that died.
+const STALE_LOCK_MINUTES: i64 = 10;
+/// Finished jobs are deleted after this long; their payloads hold addresses.
+const DONE_RETENTION_DAYS: i64 = 7;
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Work that runs outside the request. Stored as JSON, so renaming a variant
+/// or field orphans jobs already in the queue.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Task {
+    SendEmail {
+        to: String,
+        subject: String,
+        text: String,
+        html: String,
+    },
+    /// Account emails with a signed link. The link is signed when the job runs,
+    /// so the payload only has what goes into the token.
+    SendVerificationEmail {
+        to: String,
+        recipient: String,
+        exp: usize,
+    },
+    SendPasswordReset {
+        to: String,
+        token_id: String,
+        exp: usize,
+    },
+    SendEmailChangeConfirmation {
+        user_id: i32,
+        new_email: String,
+        change_id: String,
+        exp: usize,
+    },
+    SendEmailChangeNotice {
+        to: String,
+        user_id: i32,
+        new_email: String,
+        change_id: String,
+        exp: usize,
+    },
+    /// Carries out a requested account deletion once its grace period is over.
+    AnonymiseUser { user_id: i32 },
+}
+
+impl Task {
+    fn kind(&self) -> &'static str {
+        match self {
+            Task::SendEmail { .. } => "send_email",
+            Task::SendVerificationEmail { .. } => "send_verification_email",
+            Task::SendPasswordReset { .. } => "send_password_reset",
+            Task::SendEmailChangeConfirmation { .. } => "send_email_change_confirmation",
+            Task::SendEmailChangeNotice { .. } => "send_email_change_notice",
+            Task::AnonymiseUser { .. } => "anonymise_user",
+        }
+    }
+}
+
+pub async fn enqueue<C: ConnectionTrait>(db: &C, task: Task) -> Result<job::Model, DbErr> {
+    enqueue_at(db, task, Utc::now().naive_utc()).await
+}
+
+/// Like `enqueue`, but the job won't run before `run_at`.
+pub async fn enqueue_at<C: ConnectionTrait>(
+    db: &C,
+    task: Task,
+    run_at: NaiveDateTime,
+) -> Result<job::Model, DbErr> {
+    let now = Utc::now().naive_utc();
+    let payload = serde_json::to_value(&task).map_err(|e| DbErr::Custom(e.to_string()))?;
+
+    job::ActiveModel {
+        kind: Set(task.kind().to_string()),
+        payload: Set(payload),
+        status: Set(JobStatus::Pending),
+        attempts: Set(0),
+        max_attempts: Set(MAX_ATTEMPTS),
+        run_at: Set(run_at),
+        locked_at: Set(None),
+        last_error: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// The payload as shown to admins: its type and user, with everything else,
+/// such as addresses and message bodies, blanked out.
+pub fn redacted_payload(payload: &Value) -> Value {
+    match payload {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| match key.as_str() {
+                    "type" | "user_id" => (key.clone(), value.clone()),
+                    _ => (key.clone(), Value::from("[redacted]")),
+                })
+                .collect(),
+        ),
+        _ => Value::from("[redacted]"),
+    }
+}
+
+pub fn spawn_worker(
+    db: DatabaseConnection,
+    redis_pool: Pool,
+    mailersend_token: String,
+    email_key: Hmac<Sha256>,
+) {
+    tokio::spawn(async move {
+        let mut last_purge: Option<tokio::time::Instant> = None;
+        loop {
+            match work_one(&db, &redis_pool, &mailersend_token, &email_key).await {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(err) => eprintln!("Job worker error: {}", err),
+            }
+
+            if last_purge.is_none_or(|purged| purged.elapsed() >= PURGE_INTERVAL) {
+                last_purge = Some(tokio::time::Instant::now());
+                if let Err(err) = purge_done(&db).await {
+                    eprintln!("Failed to purge finished jobs: {}", err);
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+async fn purge_done(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let cutoff = Utc::now().naive_utc() - chrono::Duration::days(DONE_RETENTION_DAYS);
+    let deleted = Job::delete_many()
+        .filter(job::Column::Status.eq(JobStatus::Done))
+        .filter(job::Column::UpdatedAt.lt(cutoff))
+        .exec(db)
+        .await?;
+    Ok(deleted.rows_affected)
+}
+
+/// Claims and runs the next due job. Returns whether there was one.
+async fn work_one(
+    db: &DatabaseConnection,
+    redis_pool: &Pool,
+    mailersend_token: &str,
+    email_key: &Hmac<Sha256>,
+) -> Result<bool, DbErr> {
+    let claimed = Job::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"UPDATE job SET
+                status = 'running',
+                attempts = attempts + 1,
+                locked_at = timezone('utc', now()),
+                updated_at = timezone('utc', now())
+            WHERE id = (
+                SELECT id FROM job
+                WHERE (status = 'pending' AND run_at <= timezone('utc', now()))
+                   OR (status = 'running' AND locked_at < timezone('utc', now()) - INTERVAL '1 minute' * $1)
+                ORDER BY run_at
+                FOR UPDATE SKIP LOCKED
+                LIMIT 1
+            )
+            RETURNING *"#,
+            [STALE_LOCK_MINUTES.into()],
+        ))
+        .one(db)
+        .await?;
+
+    let Some(claimed) = claimed else {
+        return Ok(false);
+    };
+
+    let result = match serde_json::from_value::<Task>(claimed.payload.clone()) {
+        Ok(task) => run(db, redis_pool, task, mailersend_token, email_key).await,
+        Err(err) => Err(format!("Unreadable payload: {}", err)),
+    };
+
+    let now = Utc::now().naive_utc();
+    let attempts = claimed.attempts;
+    let max_attempts = claimed.max_attempts;
+    let mut finished: job::ActiveModel = claimed.into();
+    finished.locked_at = Set(None);
+    finished.updated_at = Set(now);
+
+    match result {
+        Ok(()) => {
+            finished.status = Set(JobStatus::Done);
+            finished.last_error = Set(None);
+        }
+        Err(err) if attempts >= max_attempts => {
+            finished.status = Set(JobStatus::Dead);
+            finished.last_error = Set(Some(err));
+        }
+        Err(err) => {
+            finished.status = Set(JobStatus::Pending);
+            finished.run_at = Set(now + chrono::Duration::seconds(backoff_seconds(attempts)));
+            finished.last_error = Set(Some(err));
+        }
+    }
+
+    finished.update(db).await?;
+
+    Ok(true)
+}
+
+/// Exponential backoff: 30s, 60s, 120s, ... capped at an hour.
+fn backoff_seconds(attempts: i32) -> i64 {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    (BASE_BACKOFF_SECONDS * 2i64.pow(exponent)).min(MAX_BACKOFF_SECONDS)
+}
+
+async fn run(
+    db: &DatabaseConnection,
+    redis_pool: &Pool,
+    task: Task,
+    mailersend_token: &str,
+    email_key: &Hmac<Sha256>,
+) -> Result<(), String> {
+    let (to, email) = match task {
+        Task::SendEmail {
+            to,
+            subject,
+            text,
+            html,
+        } => return send_email(mailersend_token, &to, &subject, text, html).await,
+        Task::AnonymiseUser { user_id } => {
+            return anonymise_user(db, redis_pool, user_id)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        Task::SendVerificationEmail { to, recipient, exp } => {
+            let email = account_emails::verification(email_key, &to, &recipient, exp)?;
+            (to, email)
+        }
+        Task::SendPasswordReset { to, token_id, exp } => {
+            let email = account_emails::password_reset(email_key, &to, &token_id, exp)?;
+            (to, email)
+        }
+        Task::SendEmailChangeConfirmation {
+            user_id,
+            new_email,
+            change_id,
+            exp,
+        } => {
+            let email = account_emails::email_change_confirmation(
+                email_key, user_id, &new_email, &change_id, exp,
+            )?;
+            (new_email, email)
+        }
+        Task::SendEmailChangeNotice {
+            to,
+            user_id,
+            new_email,
+            change_id,
+            exp,
+        } => {
+            let email = account_emails::email_change_notice(
+                email_key, user_id, &new_email, &change_id, exp,
+            )?;
+            (to, email)
+        }
+    };
+
+    let Email {
+        subject,
+        text,
+        html,
+    } = email;
+    send_email(mailersend_token, &to, &subject, text, html).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn account_email_payloads_hold_no_token() {
+        let payload = serde_json::to_value(Task::SendPasswordReset {
+            to: "user@example.com".to_string(),
+            token_id: "token-id".to_string(),
+            exp: 1,
+        })
+        .unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "type": "send_password_reset",
+                "to": "user@example.com",
+                "token_id": "token-id",
+                "exp": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn redacts_everything_but_type_and_user() {
+        let payload = json!({
+            "type": "send_email",
+            "user_id": 3,
+            "to": "user@example.com",
+            "html": "<a href=\"https://ad-ee.tech/reset/secret\">here</a>",
+        });
+        assert_eq!(
+            redacted_payload(&payload),
+            json!({
+                "type": "send_email",
+                "user_id": 3,
+                "to": "[redacted]",
+                "html": "[redacted]",
+            })
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use serde_json::json;
+
+/// Escapes user-provided text for an HTML email body or attribute value.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub async fn send_email(
+    mailersend_token: &str,
+    to: &str,
+    subject: &str,
+    text: String,
+    html: String,
+) -> Result<(), String> {
+    let payload = json!({
+        "from": { "email": "info@ad-ee.tech", "name": "Adee" },
+        "to": [{ "email": to }],
+        "subject": subject,
+        "text": text,
+        "html": html
+    });
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post("https://api.mailersend.com/v1/email")
+        .bearer_auth(mailersend_token)
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {}", e))?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to send email: {}",
+            resp.text().await.unwrap_or_default()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_html;
+
+    #[test]
+    fn escapes_markup_and_quotes() {
+        assert_eq!(
+            escape_html("<a href=\"x\" onclick='y'>Tom & Jerry</a>"),
+            "&lt;a href=&quot;x&quot; onclick=&#39;y&#39;&gt;Tom &amp; Jerry&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain text"), "plain text");
+    }
+}
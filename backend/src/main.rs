@@ -1,5 +1,41 @@
+mod account;
+mod account_emails;
+mod account_queries;
+mod advert_expiry;
 mod advert_queries;
+mod advert_stats;
+mod advert_stats_queries;
+mod analytics;
+mod analytics_queries;
+mod captcha;
+mod contact_queries;
+mod currency;
+mod favorite_queries;
+mod job_queries;
+mod jobs;
+mod mail;
+mod notification_queries;
+mod notifications;
+mod oidc;
+#[cfg(any(test, feature = "oidc-mock"))]
+mod oidc_mock;
+mod oidc_queries;
+mod password_policy;
+mod passwords;
+mod payments;
+mod phone;
+mod phone_queries;
+mod price_drop;
+mod profile_queries;
+mod promotion;
+mod promotion_queries;
+mod rate_limit;
+mod reputation;
+mod sms;
+mod two_factor;
+mod two_factor_queries;
 mod user_queries;
+mod web_push;
 
 use actix_cors::Cors;
 use actix_web::HttpRequest;
@@ -9,34 +45,59 @@ use actix_web::{
     http::{
         self,
         header::{self, HeaderMap},
+        StatusCode,
     },
-    web, App, HttpResponse, HttpServer, Result,
+    web, App, CustomizeResponder, HttpResponse, HttpServer, Responder, Result,
 };
 use advert_queries::{AdvertMutation, AdvertQuery};
+use advert_stats_queries::AdvertStatsQuery;
+use analytics_queries::AnalyticsQuery;
 use async_graphql::Error;
 use async_graphql::{
-    http::GraphiQLSource, EmptySubscription, MergedObject, Object, Schema, SimpleObject,
+    dataloader::DataLoader, http::GraphiQLSource, Data, MergedObject, Object, Schema, SimpleObject,
 };
-use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
 use deadpool_redis::{Config, Pool, Runtime};
 use dotenvy::dotenv;
 use entity::{
-    advert::{self, Entity as Advert},
-    user::{self, Entity as User},
+    loader::{
+        FavoriteLoader, PriceHistoryLoader, PromotionLoader, ReviewLoader, SpecificationsLoader,
+        UserAccessLoader, UserLoader,
+    },
+    notification,
 };
+use account_queries::{AccountMutation, AccountQuery};
+use captcha::CaptchaVerifier;
+use contact_queries::{ContactMutation, ContactQuery};
+use favorite_queries::{FavoriteMutation, FavoriteQuery};
 use hmac::{Hmac, Mac};
+use job_queries::{JobMutation, JobQuery};
 use jwt::VerifyWithKey;
-use migration::{sea_orm::EntityTrait, Migrator, MigratorTrait};
-use sea_orm::{
-    ColumnTrait, Database, DatabaseConnection, PaginatorTrait, QueryFilter,
-};
+use migration::{Migrator, MigratorTrait};
+use notification_queries::{NotificationMutation, NotificationQuery, NotificationSubscription};
+use oidc::OidcConfig;
+use oidc_queries::{OidcMutation, OidcQuery};
+use password_policy::PasswordPolicy;
+use passwords::Passwords;
+use phone_queries::PhoneMutation;
+use payments::PaymentProvider;
+use profile_queries::{ProfileMutation, ProfileQuery};
+use promotion_queries::{PromotionMutation, PromotionQuery};
+use rate_limit::RateLimits;
+use sea_orm::{Database, DatabaseConnection};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha2::Sha256;
+use sms::SmsProvider;
 use std::{
     collections::BTreeMap,
+    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::broadcast;
+use two_factor_queries::{TwoFactorMutation, TwoFactorQuery};
 use user_queries::{UserMutation, UserQuery};
+use web_push::WebPush;
 
 pub fn verify_access_token(
     access_token: String,
@@ -74,7 +135,30 @@ pub fn verify_access_token(
     Ok(claims)
 }
 
-#[derive(SimpleObject)]
+/// The logged-in user's id from the request's access token.
+pub fn user_id_from_token(ctx: &async_graphql::Context<'_>) -> Result<i32, Error> {
+    let my_ctx = ctx.data::<Context>()?;
+
+    let access_token = match ctx.data_opt::<Token>().map(|token| token.0.clone()) {
+        Some(token) => token,
+        None => return Err(Error::new("you are not logged in")),
+    };
+
+    let claims = verify_access_token(access_token, &my_ctx.access_key)?;
+
+    match claims.get("id") {
+        Some(Value::String(id)) => id
+            .parse()
+            .map_err(|_| Error::new("Invalid user ID in token: failed to parse string")),
+        Some(Value::Number(id)) => id
+            .as_i64()
+            .map(|id| id as i32)
+            .ok_or_else(|| Error::new("Invalid user ID in token: missing id")),
+        _ => Err(Error::new("Invalid user ID in token: missing id")),
+    }
+}
+
+#[derive(SimpleObject, Serialize, Deserialize)]
 pub struct Statistics {
     pub user_count: u64,
     pub advert_count: u64,
@@ -83,7 +167,8 @@ pub struct Statistics {
 }
 
 
-#[derive(Debug)]
+/// Shared by every request; built once in `main` and cloned per worker.
+#[derive(Clone, Debug)]
 pub struct Context {
     pub db: DatabaseConnection,
     pub redis_pool: Pool,
@@ -91,27 +176,27 @@ pub struct Context {
     pub refresh_key: Hmac<Sha256>,
     pub mailersend_token: String,
     pub email_key: Hmac<Sha256>,
-}
-
-impl Context {
-    pub fn new(
-        db: DatabaseConnection,
-        redis_pool: Pool,
-        access_key: Hmac<Sha256>,
-        refresh_key: Hmac<Sha256>,
-        mailersend_token: String,
-        email_key: Hmac<Sha256>,
-    ) -> Self {
-        Self {
-            db,
-            redis_pool,
-            // stripe_secret,
-            access_key,
-            refresh_key,
-            mailersend_token,
-            email_key,
-        }
-    }
+    pub advert_lifetime_days: i64,
+    pub base_currency: String,
+    pub review_window_days: i64,
+    pub notifications: broadcast::Sender<notification::Model>,
+    pub web_push: Option<Arc<WebPush>>,
+    /// Lets push subscriptions point at the local `/push-mock/` route.
+    pub push_mock_endpoint: bool,
+    pub rate_limits: RateLimits,
+    pub require_staff_two_factor: bool,
+    /// Take the client address from `X-Real-IP`, which the reverse proxy
+    /// overwrites, instead of the connection's peer address.
+    pub trust_real_ip_header: bool,
+    pub oidc: OidcConfig,
+    pub sms: Arc<dyn SmsProvider>,
+    /// Calling code assumed for phone numbers entered without one.
+    pub phone_country_code: String,
+    pub password_policy: PasswordPolicy,
+    pub passwords: Passwords,
+    pub account_deletion_grace_days: i64,
+    pub captcha: Arc<dyn CaptchaVerifier>,
+    pub payments: Arc<dyn PaymentProvider>,
 }
 
 #[derive(Default)]
@@ -125,76 +210,194 @@ impl QueryRoot {
     ) -> Result<Statistics, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
 
-        // let mut conn = my_ctx.redis_pool.get().await.unwrap();
-        // cmd("SET")
-        // .arg(&["deadpool/test_key", "42"])
-        // .query_async::<()>(&mut conn)
-        // .await.unwrap();
-
-        let users = User::find();
-
-        let user_count = users.clone().count(&my_ctx.db).await?;
-
-        let adverts = Advert::find();
-
-        let advert_count = adverts.clone().count(&my_ctx.db).await?;
-
-        let today: Option<sea_orm::prelude::DateTime> =
-            chrono::Utc::now().naive_utc().date().and_hms_opt(0, 0, 0);
-
-        let today_advert_count = adverts
-            .filter(advert::Column::CreatedAt.gt(today))
-            .count(&my_ctx.db)
-            .await?;
-
-        let today_user_count = users
-            .filter(user::Column::CreatedAt.gt(today))
-            .count(&my_ctx.db)
-            .await?;
-
-        Ok(Statistics {
-            user_count,
-            advert_count,
-            today_user_count,
-            today_advert_count,
-        })
+        analytics::statistics(my_ctx).await
     }
 }
 
 #[derive(Debug)]
 pub struct Token(pub String);
 
+/// The caller's address as seen through the reverse proxy, used for rate limiting.
+#[derive(Debug)]
+pub struct ClientIp(pub String);
+
+/// Client-supplied `Forwarded` and `X-Forwarded-For` headers are never read, so
+/// the address can't be made up to get around rate limits.
+fn client_ip(req: &HttpRequest, trust_real_ip_header: bool) -> Option<ClientIp> {
+    let ip = if trust_real_ip_header {
+        req.headers()
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .map(|ip| ip.trim().to_string())
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    };
+    ip.filter(|ip| !ip.is_empty()).map(ClientIp)
+}
+
 fn get_token_from_headers(headers: &HeaderMap) -> Option<Token> {
     headers
         .get("authorization")
         .and_then(|value| value.to_str().map(|s| Token(s.to_string())).ok())
 }
 
+fn viewer_id(token: &Token, access_key: &Hmac<Sha256>) -> Option<i32> {
+    let claims = verify_access_token(token.0.clone(), access_key).ok()?;
+    match claims.get("id")? {
+        Value::String(id) => id.parse().ok(),
+        Value::Number(id) => id.as_i64().map(|id| id as i32),
+        _ => None,
+    }
+}
+
 async fn index(
-    schema: web::Data<Schema<Query, Mutation, EmptySubscription>>,
+    schema: web::Data<Schema<Query, Mutation, NotificationSubscription>>,
+    context: web::Data<Context>,
     req: HttpRequest,
     gql_request: GraphQLRequest,
-) -> GraphQLResponse {
+) -> CustomizeResponder<GraphQLResponse> {
     let mut request = gql_request.into_inner();
 
-    if let Some(token) = get_token_from_headers(req.headers()) {
+    let token = get_token_from_headers(req.headers());
+    let viewer_id = token
+        .as_ref()
+        .and_then(|token| viewer_id(token, &context.access_key));
+
+    // Loaders cache per request, so they are built here rather than on the schema.
+    let db = &context.db;
+    request = request
+        .data(DataLoader::new(
+            SpecificationsLoader(db.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(UserLoader(db.clone()), tokio::spawn))
+        .data(DataLoader::new(ReviewLoader(db.clone()), tokio::spawn))
+        .data(DataLoader::new(PromotionLoader(db.clone()), tokio::spawn))
+        .data(DataLoader::new(PriceHistoryLoader(db.clone()), tokio::spawn))
+        .data(DataLoader::new(
+            FavoriteLoader {
+                db: db.clone(),
+                viewer_id,
+            },
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            UserAccessLoader {
+                db: db.clone(),
+                viewer_id,
+            },
+            tokio::spawn,
+        ));
+
+    if let Some(token) = token {
         request = request.data(token);
     }
-    schema.execute(request).await.into()
+    if let Some(ip) = client_ip(&req, context.trust_real_ip_header) {
+        request = request.data(ip);
+    }
+
+    let response = schema.execute(request).await;
+    let throttled = response.errors.iter().any(|error| {
+        error
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code"))
+            .is_some_and(|code| *code == async_graphql::Value::from("TOO_MANY_REQUESTS"))
+    });
+
+    let status = if throttled {
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        StatusCode::OK
+    };
+    GraphQLResponse::from(response).customize().with_status(status)
 }
 
 
+async fn index_ws(
+    schema: web::Data<Schema<Query, Mutation, NotificationSubscription>>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse> {
+    GraphQLSubscription::new(Schema::clone(&*schema))
+        .on_connection_init(on_connection_init)
+        .start(&req, payload)
+}
+
+/// Websocket clients pass their access token in the `connection_init` payload.
+async fn on_connection_init(payload: Value) -> async_graphql::Result<Data> {
+    let mut data = Data::default();
+    if let Some(token) = payload
+        .get("Authorization")
+        .or_else(|| payload.get("authorization"))
+        .and_then(|value| value.as_str())
+    {
+        data.insert(Token(token.to_string()));
+    }
+    Ok(data)
+}
+
+/// Stands in for a browser push service in development: register a
+/// subscription whose endpoint points here and pushes are logged instead.
+async fn push_mock(path: web::Path<String>, req: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let authorization = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    println!(
+        "Mock push to {}: {} encrypted bytes, vapid: {}",
+        path.into_inner(),
+        body.len(),
+        authorization.starts_with("vapid t=")
+    );
+    HttpResponse::Created().finish()
+}
+
 async fn index_graphiql() -> Result<HttpResponse> {
     Ok(HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
-        .body(GraphiQLSource::build().endpoint("/").finish()))
+        .body(
+            GraphiQLSource::build()
+                .endpoint("/")
+                .subscription_endpoint("/")
+                .finish(),
+        ))
 }
 
 #[derive(MergedObject, Default)]
-struct Query(UserQuery, QueryRoot, AdvertQuery);
+struct Query(
+    UserQuery,
+    QueryRoot,
+    AdvertQuery,
+    FavoriteQuery,
+    NotificationQuery,
+    JobQuery,
+    TwoFactorQuery,
+    OidcQuery,
+    AccountQuery,
+    ProfileQuery,
+    ContactQuery,
+    AdvertStatsQuery,
+    AnalyticsQuery,
+    PromotionQuery,
+);
 
 #[derive(MergedObject, Default)]
-struct Mutation(UserMutation, AdvertMutation);
+struct Mutation(
+    UserMutation,
+    AdvertMutation,
+    FavoriteMutation,
+    NotificationMutation,
+    JobMutation,
+    TwoFactorMutation,
+    OidcMutation,
+    PhoneMutation,
+    AccountMutation,
+    ProfileMutation,
+    ContactMutation,
+    PromotionMutation,
+);
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -212,6 +415,25 @@ async fn main() -> std::io::Result<()> {
     let mailersend_token = dotenvy::var("MAILERSEND_TOKEN")
         .expect("MAILERSEND_TOKEN environment variable not found");
     let email_key = dotenvy::var("EMAIL_SECRET").expect("EMAIL_SECRET environment variable not found");
+    let advert_lifetime_days = dotenvy::var("ADVERT_LIFETIME_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<i64>()
+        .expect("ADVERT_LIFETIME_DAYS is not a number");
+    let advert_expiry_notice_days = dotenvy::var("ADVERT_EXPIRY_NOTICE_DAYS")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse::<i64>()
+        .expect("ADVERT_EXPIRY_NOTICE_DAYS is not a number");
+    let account_deletion_grace_days = dotenvy::var("ACCOUNT_DELETION_GRACE_DAYS")
+        .unwrap_or_else(|_| "14".to_string())
+        .parse::<i64>()
+        .expect("ACCOUNT_DELETION_GRACE_DAYS is not a number");
+    let base_currency = dotenvy::var("BASE_CURRENCY")
+        .map(|code| code.trim().to_uppercase())
+        .unwrap_or_else(|_| "EUR".to_string());
+    let review_window_days = dotenvy::var("REVIEW_WINDOW_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<i64>()
+        .expect("REVIEW_WINDOW_DAYS is not a number");
     // tracing_subscriber::fmt()
     //     .with_max_level(tracing::Level::DEBUG)
     //     .with_test_writer()
@@ -222,6 +444,20 @@ async fn main() -> std::io::Result<()> {
 
     Migrator::up(&db, None).await.expect("Migration error");
 
+    currency::set_base_currency(&db, &base_currency)
+        .await
+        .expect("Failed to store base currency rate");
+
+    if let Some(path) = dotenvy::var("EXCHANGE_RATES_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())
+    {
+        let imported = currency::import_file(&db, &path)
+            .await
+            .expect("Failed to import exchange rates");
+        println!("Imported {} exchange rates from {}", imported, path);
+    }
+
     println!("GraphiQL IDE: http://{}:{}/", ip, port);
 
     let access_key: Hmac<Sha256> = Hmac::new_from_slice(access_secret.as_bytes()).unwrap();
@@ -230,28 +466,86 @@ async fn main() -> std::io::Result<()> {
     let cfg = Config::from_url(redis_url);
     let pool = cfg.create_pool(Some(Runtime::Tokio1)).unwrap();
 
+    let web_push = dotenvy::var("VAPID_PRIVATE_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            let subject = dotenvy::var("VAPID_SUBJECT")
+                .unwrap_or_else(|_| "mailto:support@ad-ee.tech".to_string());
+            Arc::new(WebPush::new(&key, subject).expect("Invalid VAPID_PRIVATE_KEY"))
+        });
+    let rate_limits = RateLimits::from_env();
+    let require_staff_two_factor = dotenvy::var("REQUIRE_STAFF_2FA")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+    let trust_real_ip_header = dotenvy::var("TRUST_X_REAL_IP")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+    let push_mock_endpoint = dotenvy::var("PUSH_MOCK_ENDPOINT")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+    let oidc_config = OidcConfig::from_env();
+    let sms_provider = sms::from_env();
+    let captcha = captcha::from_env();
+    let payments = payments::from_env();
+    let password_policy = PasswordPolicy::from_env();
+    let passwords = Passwords::from_env();
+    let phone_country_code = dotenvy::var("DEFAULT_PHONE_COUNTRY_CODE")
+        .map(|code| code.trim_start_matches('+').to_string())
+        .unwrap_or_else(|_| "371".to_string());
+    #[cfg(feature = "oidc-mock")]
+    let oidc_mock_endpoint = dotenvy::var("OIDC_MOCK_ENDPOINT")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+
+    let (notifications, _) = broadcast::channel(256);
+    notifications::spawn_listener(db.clone(), notifications.clone(), web_push.clone());
+
+    jobs::spawn_worker(
+        db.clone(),
+        pool.clone(),
+        mailersend_token.clone(),
+        email_key.clone(),
+    );
+    advert_expiry::spawn(db.clone(), advert_expiry_notice_days);
+    advert_stats::spawn_flusher(db.clone(), pool.clone());
+
+    let context = Context {
+        db: db.clone(),
+        redis_pool: pool.clone(),
+        access_key,
+        refresh_key,
+        mailersend_token,
+        email_key,
+        advert_lifetime_days,
+        base_currency,
+        review_window_days,
+        notifications,
+        web_push,
+        push_mock_endpoint,
+        rate_limits,
+        require_staff_two_factor,
+        trust_real_ip_header,
+        oidc: oidc_config,
+        sms: sms_provider,
+        phone_country_code,
+        password_policy,
+        passwords,
+        account_deletion_grace_days,
+        captcha,
+        payments,
+    };
+
     HttpServer::new(move || {
-        let schema = Schema::build(Query::default(), Mutation::default(), EmptySubscription)
-            .data(Context::new(
-                db.clone(),
-                pool.clone(),
-                // stripe.clone(),
-                access_key.clone(),
-                refresh_key.clone(),
-                mailersend_token.clone(),
-                email_key.clone(   )
-            ))
-            .finish();
-
-        let context_data = web::Data::new(Context::new(
-            db.clone(),
-            pool.clone(),
-            // stripe.clone(),
-            access_key.clone(),
-            refresh_key.clone(),
-            mailersend_token.clone(),
-            email_key.clone(),
-        ));
+        let schema = Schema::build(
+            Query::default(),
+            Mutation::default(),
+            NotificationSubscription,
+        )
+        .data(context.clone())
+        .finish();
+
+        let context_data = web::Data::new(context.clone());
 
         let cors = Cors::default()
             .allow_any_origin()
@@ -280,7 +574,22 @@ async fn main() -> std::io::Result<()> {
                 }
             })
             .service(web::resource("/").guard(guard::Post()).to(index))
+            .service(
+                web::resource("/")
+                    .guard(guard::Get())
+                    .guard(guard::Header("upgrade", "websocket"))
+                    .to(index_ws),
+            )
             .service(web::resource("/").guard(guard::Get()).to(index_graphiql))
+            .configure(|cfg| {
+                if push_mock_endpoint {
+                    cfg.route("/push-mock/{id}", web::post().to(push_mock));
+                }
+                #[cfg(feature = "oidc-mock")]
+                if oidc_mock_endpoint {
+                    oidc_mock::configure(cfg);
+                }
+            })
     })
     .bind((ip, port))?
     .run()
@@ -0,0 +1,301 @@
+use crate::{user_id_from_token, web_push::check_endpoint, Context};
+
+use actix_web::Result;
+use async_graphql::{Object, SimpleObject, Subscription};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use entity::{
+    notification::{self, Entity as Notification, NotificationKind},
+    notification_preference::{self, Entity as NotificationPreference},
+    push_subscription::{self, Entity as PushSubscription},
+};
+use futures_util::Stream;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, EntityTrait, ModelTrait, Order, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait, Set,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+#[derive(SimpleObject)]
+#[graphql(name = "NotificationPage")]
+pub struct NotificationPage {
+    items: Vec<notification::Model>,
+    total: u64,
+    unread_count: u64,
+}
+
+#[derive(Default)]
+pub struct NotificationQuery;
+
+#[Object]
+impl NotificationQuery {
+    async fn notifications(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        offset: i32,
+        limit: i32,
+        #[graphql(default = false)] unread_only: bool,
+    ) -> Result<NotificationPage, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let query = Notification::find()
+            .filter(notification::Column::UserId.eq(user_id))
+            .apply_if(unread_only.then_some(()), |query, _| {
+                query.filter(notification::Column::ReadAt.is_null())
+            });
+
+        let total = query.clone().count(&my_ctx.db).await?;
+
+        let items = query
+            .order_by(notification::Column::CreatedAt, Order::Desc)
+            .offset(offset.max(0) as u64)
+            .limit(limit.clamp(0, 100) as u64)
+            .all(&my_ctx.db)
+            .await?;
+
+        let unread_count = Notification::find()
+            .filter(notification::Column::UserId.eq(user_id))
+            .filter(notification::Column::ReadAt.is_null())
+            .count(&my_ctx.db)
+            .await?;
+
+        Ok(NotificationPage {
+            items,
+            total,
+            unread_count,
+        })
+    }
+
+    /// Preferences for every kind, falling back to all channels on. Email is
+    /// always off for kinds that are never emailed.
+    async fn notification_preferences(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<Vec<notification_preference::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let stored = NotificationPreference::find()
+            .filter(notification_preference::Column::UserId.eq(user_id))
+            .all(&my_ctx.db)
+            .await?;
+
+        let preferences = [
+            NotificationKind::NewMessage,
+            NotificationKind::DealOffer,
+            NotificationKind::ReviewReceived,
+            NotificationKind::PriceDrop,
+            NotificationKind::AdvertExpiring,
+        ]
+        .into_iter()
+        .map(|kind| {
+            let preference = stored
+                .iter()
+                .find(|preference| preference.kind == kind)
+                .cloned()
+                .unwrap_or(notification_preference::Model {
+                    user_id,
+                    kind,
+                    in_app: true,
+                    email: true,
+                });
+            notification_preference::Model {
+                email: preference.email && kind.emailed(),
+                ..preference
+            }
+        })
+        .collect();
+
+        Ok(preferences)
+    }
+
+    /// Application server key for `PushManager.subscribe()`; null when Web Push
+    /// isn't configured.
+    async fn vapid_public_key(&self, ctx: &async_graphql::Context<'_>) -> Option<String> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        my_ctx
+            .web_push
+            .as_ref()
+            .map(|web_push| web_push.public_key().to_string())
+    }
+}
+
+#[derive(Default)]
+pub struct NotificationMutation;
+
+#[Object]
+impl NotificationMutation {
+    /// Marks the given notifications, or all of them when `ids` is omitted, as
+    /// read. Returns how many were updated.
+    async fn mark_notifications_read(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        ids: Option<Vec<i32>>,
+    ) -> Result<u64, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let result = Notification::update_many()
+            .col_expr(
+                notification::Column::ReadAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(notification::Column::UserId.eq(user_id))
+            .filter(notification::Column::ReadAt.is_null())
+            .apply_if(ids, |query, ids| {
+                query.filter(notification::Column::Id.is_in(ids))
+            })
+            .exec(&my_ctx.db)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+
+    async fn set_notification_preference(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        kind: NotificationKind,
+        in_app: bool,
+        email: bool,
+    ) -> Result<notification_preference::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        if email && !kind.emailed() {
+            return Err(async_graphql::Error::new(
+                "Chat notifications can't be sent by email",
+            ));
+        }
+
+        let preference = notification_preference::Model {
+            user_id,
+            kind,
+            in_app,
+            email,
+        };
+
+        NotificationPreference::insert(notification_preference::ActiveModel::from(
+            preference.clone(),
+        ))
+        .on_conflict(
+            OnConflict::columns([
+                notification_preference::Column::UserId,
+                notification_preference::Column::Kind,
+            ])
+            .update_columns([
+                notification_preference::Column::InApp,
+                notification_preference::Column::Email,
+            ])
+            .to_owned(),
+        )
+        .exec_without_returning(&my_ctx.db)
+        .await?;
+
+        Ok(preference)
+    }
+
+    /// Stores a browser push subscription. Re-registering an endpoint moves it
+    /// to the caller and refreshes its keys.
+    async fn register_push_subscription(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        endpoint: String,
+        p256dh: String,
+        auth: String,
+    ) -> Result<push_subscription::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        check_endpoint(&endpoint, my_ctx.push_mock_endpoint).map_err(async_graphql::Error::new)?;
+        let p256dh = p256dh.trim_end_matches('=').to_string();
+        let auth = auth.trim_end_matches('=').to_string();
+        let key_len = URL_SAFE_NO_PAD.decode(&p256dh).ok().map(|key| key.len());
+        let auth_len = URL_SAFE_NO_PAD.decode(&auth).ok().map(|auth| auth.len());
+        if key_len != Some(65) || auth_len != Some(16) {
+            return Err(async_graphql::Error::new("Invalid push subscription keys"));
+        }
+
+        let subscription = push_subscription::ActiveModel {
+            user_id: Set(user_id),
+            endpoint: Set(endpoint.clone()),
+            p256dh: Set(p256dh),
+            auth: Set(auth),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        };
+
+        PushSubscription::insert(subscription)
+            .on_conflict(
+                OnConflict::column(push_subscription::Column::Endpoint)
+                    .update_columns([
+                        push_subscription::Column::UserId,
+                        push_subscription::Column::P256dh,
+                        push_subscription::Column::Auth,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(&my_ctx.db)
+            .await?;
+
+        PushSubscription::find()
+            .filter(push_subscription::Column::Endpoint.eq(endpoint))
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Push subscription not found"))
+    }
+
+    async fn unregister_push_subscription(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        endpoint: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let subscription = PushSubscription::find()
+            .filter(push_subscription::Column::Endpoint.eq(endpoint))
+            .filter(push_subscription::Column::UserId.eq(user_id))
+            .one(&my_ctx.db)
+            .await?;
+
+        match subscription {
+            Some(subscription) => {
+                subscription.delete(&my_ctx.db).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NotificationSubscription;
+
+#[Subscription]
+impl NotificationSubscription {
+    /// New in-app notifications for the connected user.
+    async fn notification_received(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<impl Stream<Item = notification::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        let receiver = my_ctx.notifications.subscribe();
+
+        Ok(futures_util::stream::unfold(
+            receiver,
+            move |mut receiver| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(notification) if notification.user_id == user_id => {
+                            return Some((notification, receiver))
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+}
@@ -0,0 +1,157 @@
+use chrono::Utc;
+use entity::{
+    notification::{self, Entity as Notification, NotificationKind},
+    notification_preference::{self, Entity as NotificationPreference},
+    user::Entity as User,
+};
+use sea_orm::{
+    sqlx::postgres::PgListener, ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, Set,
+};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use crate::{
+    jobs::{enqueue, Task},
+    mail::escape_html,
+    web_push::{push_notification, WebPush},
+};
+
+/// Postgres channel the `notification` insert trigger publishes new ids on.
+const CHANNEL: &str = "notification";
+
+pub struct NewNotification {
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+}
+
+/// The plain text and HTML email for a notification. Bodies quote user input
+/// such as advert titles, so they are escaped for the HTML part.
+pub fn email_content(body: &str, link: Option<&str>) -> (String, String) {
+    match link {
+        Some(link) => (
+            format!("{} {}", body, link),
+            format!(
+                "<p>{}</p><p><a href=\"{}\">Open</a></p>",
+                escape_html(body),
+                escape_html(link)
+            ),
+        ),
+        None => (body.to_string(), format!("<p>{}</p>", escape_html(body))),
+    }
+}
+
+/// Delivers a notification on every channel the user has enabled for its kind.
+/// In-app notifications reach subscribers through the insert trigger, so this
+/// only has to write the row; email goes through the job queue. The chat
+/// service inserts its `NewMessage` and `DealOffer` rows itself, so those are
+/// never emailed.
+pub async fn notify(db: &DatabaseConnection, new: NewNotification) -> Result<(), DbErr> {
+    let preference = NotificationPreference::find()
+        .filter(notification_preference::Column::UserId.eq(new.user_id))
+        .filter(notification_preference::Column::Kind.eq(new.kind))
+        .one(db)
+        .await?;
+    let (in_app, email) = preference
+        .map(|preference| (preference.in_app, preference.email))
+        .unwrap_or((true, true));
+
+    if in_app {
+        notification::ActiveModel {
+            user_id: Set(new.user_id),
+            kind: Set(new.kind),
+            title: Set(new.title.clone()),
+            body: Set(new.body.clone()),
+            link: Set(new.link.clone()),
+            read_at: Set(None),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    if email {
+        let address = User::find_by_id(new.user_id)
+            .one(db)
+            .await?
+            .and_then(|user| user.email);
+
+        if let Some(address) = address {
+            let (text, html) = email_content(&new.body, new.link.as_deref());
+
+            enqueue(
+                db,
+                Task::SendEmail {
+                    to: address,
+                    subject: new.title,
+                    text,
+                    html,
+                },
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Forwards every inserted notification to the subscription stream and, when
+/// configured, to the user's Web Push subscriptions. Reconnects if the LISTEN
+/// connection drops.
+pub fn spawn_listener(
+    db: DatabaseConnection,
+    sender: broadcast::Sender<notification::Model>,
+    web_push: Option<Arc<WebPush>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = listen(&db, &sender, web_push.as_ref()).await {
+                eprintln!("Notification listener failed: {}", err);
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        }
+    });
+}
+
+async fn listen(
+    db: &DatabaseConnection,
+    sender: &broadcast::Sender<notification::Model>,
+    web_push: Option<&Arc<WebPush>>,
+) -> Result<(), String> {
+    let mut listener = PgListener::connect_with(db.get_postgres_connection_pool())
+        .await
+        .map_err(|e| e.to_string())?;
+    listener.listen(CHANNEL).await.map_err(|e| e.to_string())?;
+
+    loop {
+        let event = listener.recv().await.map_err(|e| e.to_string())?;
+        let Ok(id) = event.payload().parse::<i32>() else {
+            continue;
+        };
+
+        let notification = Notification::find_by_id(id)
+            .one(db)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if let Some(notification) = notification {
+            if let Some(web_push) = web_push {
+                let db = db.clone();
+                let web_push = web_push.clone();
+                let pushed = notification.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = push_notification(&db, &web_push, &pushed).await {
+                        eprintln!("Failed to push notification: {}", err);
+                    }
+                });
+            }
+
+            // No receivers just means nobody is subscribed right now.
+            let _ = sender.send(notification);
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use rsa::{
+    pkcs1v15::{Signature, VerifyingKey},
+    signature::Verifier,
+    BigUint, RsaPublicKey,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCOPES: &str = "openid email profile";
+
+#[derive(Clone, Debug)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    pub providers: Vec<OidcProvider>,
+    /// Where providers send the browser back to; the frontend passes the
+    /// `state` and `code` it receives there on to `finishOidcLogin`.
+    pub redirect_uri: String,
+}
+
+impl OidcConfig {
+    /// `OIDC_PROVIDERS` lists provider names; each one reads
+    /// `OIDC_<NAME>_ISSUER`, `OIDC_<NAME>_CLIENT_ID` and optionally
+    /// `OIDC_<NAME>_CLIENT_SECRET`.
+    pub fn from_env() -> Self {
+        let providers = dotenvy::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let var = |suffix: &str| format!("OIDC_{}_{}", name.to_uppercase(), suffix);
+                let required = |suffix: &str| {
+                    dotenvy::var(var(suffix)).unwrap_or_else(|_| {
+                        panic!("{} environment variable not found", var(suffix))
+                    })
+                };
+                OidcProvider {
+                    name: name.to_lowercase(),
+                    issuer: required("ISSUER").trim_end_matches('/').to_string(),
+                    client_id: required("CLIENT_ID"),
+                    client_secret: dotenvy::var(var("CLIENT_SECRET"))
+                        .ok()
+                        .filter(|secret| !secret.is_empty()),
+                }
+            })
+            .collect();
+
+        Self {
+            providers,
+            redirect_uri: dotenvy::var("OIDC_REDIRECT_URI")
+                .unwrap_or_else(|_| "https://ad-ee.tech/auth/callback".to_string()),
+        }
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&OidcProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name == name.to_lowercase())
+    }
+}
+
+/// Kept in Redis under the `state` parameter between the redirect to the
+/// provider and the callback.
+#[derive(Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub provider: String,
+    pub verifier: String,
+    pub nonce: String,
+    /// Set when a logged-in user is linking a provider rather than logging in.
+    pub link_user_id: Option<i32>,
+}
+
+/// The verified claims of an ID token that we care about.
+#[derive(Debug)]
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+/// What a verified provider login leads to.
+#[derive(Debug, PartialEq)]
+pub enum LoginOutcome {
+    /// The identity is linked to this user already.
+    LogIn(i32),
+    /// Nobody has this identity or email yet.
+    SignUp,
+    /// An account already uses the email. The identity is never linked to it
+    /// here; its owner has to log in and link the provider themselves.
+    LinkRequired,
+}
+
+/// `linked_user_id` owns the provider identity; `email_user_id` has the same
+/// email address.
+pub fn login_outcome(linked_user_id: Option<i32>, email_user_id: Option<i32>) -> LoginOutcome {
+    match (linked_user_id, email_user_id) {
+        (Some(user_id), _) => LoginOutcome::LogIn(user_id),
+        (None, Some(_)) => LoginOutcome::LinkRequired,
+        (None, None) => LoginOutcome::SignUp,
+    }
+}
+
+/// Parses the pending login stored for a callback's `state` and checks it was
+/// started by the same flow: a plain login (`link_user_id` of `None`) or
+/// linking for that user.
+pub fn check_pending(
+    stored: Option<&str>,
+    link_user_id: Option<i32>,
+) -> Result<PendingLogin, String> {
+    let pending: PendingLogin = stored
+        .and_then(|pending| serde_json::from_str(pending).ok())
+        .ok_or("Login expired, please try again")?;
+    if pending.link_user_id != link_user_id {
+        return Err("Login expired, please try again".to_string());
+    }
+    Ok(pending)
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+pub fn random_token(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// The S256 PKCE code challenge for a verifier.
+pub(crate) fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub(crate) fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn discover(client: &reqwest::Client, issuer: &str) -> Result<Discovery, String> {
+    client
+        .get(format!("{}/.well-known/openid-configuration", issuer))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Discovery failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid discovery document: {}", e))
+}
+
+pub async fn authorization_url(
+    provider: &OidcProvider,
+    redirect_uri: &str,
+    state: &str,
+    pending: &PendingLogin,
+) -> Result<String, String> {
+    let client = reqwest::Client::new();
+    let discovery = discover(&client, &provider.issuer).await?;
+
+    let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)
+        .map_err(|e| format!("Invalid authorization endpoint: {}", e))?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", SCOPES)
+        .append_pair("state", state)
+        .append_pair("nonce", &pending.nonce)
+        .append_pair("code_challenge", &pkce_challenge(&pending.verifier))
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(url.to_string())
+}
+
+/// Redeems the authorization code and returns the identity from the verified ID token.
+pub async fn authenticate(
+    provider: &OidcProvider,
+    redirect_uri: &str,
+    code: &str,
+    pending: &PendingLogin,
+) -> Result<Identity, String> {
+    let client = reqwest::Client::new();
+    let discovery = discover(&client, &provider.issuer).await?;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", pending.verifier.as_str()),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+
+    let tokens: TokenResponse = client
+        .post(&discovery.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Token exchange failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid token response: {}", e))?;
+
+    verify_id_token(
+        &client,
+        provider,
+        &discovery,
+        &tokens.id_token,
+        &pending.nonce,
+    )
+    .await
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(segment.trim_end_matches('='))
+        .map_err(|_| "Malformed ID token".to_string())
+}
+
+fn decode_json(segment: &str) -> Result<Value, String> {
+    serde_json::from_slice(&decode_segment(segment)?).map_err(|_| "Malformed ID token".to_string())
+}
+
+async fn verify_id_token(
+    client: &reqwest::Client,
+    provider: &OidcProvider,
+    discovery: &Discovery,
+    id_token: &str,
+    nonce: &str,
+) -> Result<Identity, String> {
+    let mut parts = id_token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("Malformed ID token".to_string());
+    };
+    let message = format!("{}.{}", header, payload);
+    let signature = decode_segment(signature)?;
+    let header = decode_json(header)?;
+
+    match header.get("alg").and_then(Value::as_str) {
+        Some("RS256") => {
+            let kid = header.get("kid").and_then(Value::as_str);
+            let jwks: Value = client
+                .get(&discovery.jwks_uri)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to fetch signing keys: {}", e))?
+                .json()
+                .await
+                .map_err(|e| format!("Invalid signing keys: {}", e))?;
+            let key = jwks["keys"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|key| key["kty"] == "RSA" && (kid.is_none() || key["kid"].as_str() == kid))
+                .ok_or("No matching signing key")?;
+
+            let component = |name: &str| -> Result<BigUint, String> {
+                let bytes = decode_segment(key[name].as_str().unwrap_or_default())?;
+                Ok(BigUint::from_bytes_be(&bytes))
+            };
+            let public_key = RsaPublicKey::new(component("n")?, component("e")?)
+                .map_err(|e| format!("Invalid signing key: {}", e))?;
+            let signature = Signature::try_from(signature.as_slice())
+                .map_err(|_| "Malformed ID token signature")?;
+            VerifyingKey::<Sha256>::new(public_key)
+                .verify(message.as_bytes(), &signature)
+                .map_err(|_| "Invalid ID token signature")?;
+        }
+        // Symmetric tokens are signed with the client secret.
+        Some("HS256") => {
+            let secret = provider
+                .client_secret
+                .as_deref()
+                .ok_or("HS256 ID tokens need a client secret")?;
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(message.as_bytes());
+            mac.verify_slice(&signature)
+                .map_err(|_| "Invalid ID token signature")?;
+        }
+        _ => return Err("Unsupported ID token algorithm".to_string()),
+    }
+
+    let claims = decode_json(payload)?;
+
+    let audience_matches = match &claims["aud"] {
+        Value::String(audience) => *audience == provider.client_id,
+        Value::Array(audiences) => audiences.iter().any(|a| *a == provider.client_id.as_str()),
+        _ => false,
+    };
+    if claims["iss"].as_str() != Some(discovery.issuer.as_str())
+        || !audience_matches
+        || claims["exp"].as_u64().unwrap_or(0) < now_seconds()
+        || claims["nonce"].as_str() != Some(nonce)
+    {
+        return Err("ID token failed validation".to_string());
+    }
+
+    let text = |name: &str| claims[name].as_str().map(str::to_string);
+    Ok(Identity {
+        subject: text("sub").ok_or("ID token has no subject")?,
+        email: text("email").map(|email| email.to_lowercase()),
+        // Some providers send this as a string.
+        email_verified: claims["email_verified"] == true || claims["email_verified"] == "true",
+        given_name: text("given_name"),
+        family_name: text("family_name"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpServer};
+
+    const REDIRECT_URI: &str = "http://localhost/callback";
+
+    /// Serves the mock issuer on a free local port.
+    fn mock_provider() -> OidcProvider {
+        let server = HttpServer::new(|| App::new().configure(crate::oidc_mock::configure))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let port = server.addrs()[0].port();
+        actix_web::rt::spawn(server.run());
+
+        OidcProvider {
+            name: "mock".to_string(),
+            issuer: format!("http://127.0.0.1:{}/oidc-mock", port),
+            client_id: "client".to_string(),
+            client_secret: Some("secret".to_string()),
+        }
+    }
+
+    fn pending(link_user_id: Option<i32>) -> PendingLogin {
+        PendingLogin {
+            provider: "mock".to_string(),
+            verifier: random_token(64),
+            nonce: random_token(32),
+            link_user_id,
+        }
+    }
+
+    /// Runs the browser side of a login as `email` and returns the `code` and
+    /// `state` the callback receives.
+    async fn authorize(
+        provider: &OidcProvider,
+        pending: &PendingLogin,
+        email: &str,
+    ) -> (String, String) {
+        let url = authorization_url(provider, REDIRECT_URI, "the-state", pending)
+            .await
+            .unwrap();
+        let mut url = reqwest::Url::parse(&url).unwrap();
+        url.query_pairs_mut().append_pair("login_hint", email);
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let response = client.get(url).send().await.unwrap();
+        let location = response.headers()[reqwest::header::LOCATION]
+            .to_str()
+            .unwrap();
+        let callback = reqwest::Url::parse(location).unwrap();
+        assert!(location.starts_with(REDIRECT_URI));
+
+        let param = |name: &str| {
+            callback
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .unwrap()
+        };
+        (param("code"), param("state"))
+    }
+
+    #[actix_web::test]
+    async fn new_user_signs_up() {
+        let provider = mock_provider();
+        let pending = pending(None);
+        let (code, state) = authorize(&provider, &pending, "new@example.com").await;
+        assert_eq!(state, "the-state");
+
+        let identity = authenticate(&provider, REDIRECT_URI, &code, &pending)
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, "mock|new@example.com");
+        assert_eq!(identity.email.as_deref(), Some("new@example.com"));
+        assert!(identity.email_verified);
+        assert_eq!(login_outcome(None, None), LoginOutcome::SignUp);
+    }
+
+    #[actix_web::test]
+    async fn existing_account_links_only_when_logged_in() {
+        let provider = mock_provider();
+
+        // Logging in with the email of an existing account must not take it over.
+        let login = pending(None);
+        let (code, _) = authorize(&provider, &login, "owner@example.com").await;
+        authenticate(&provider, REDIRECT_URI, &code, &login)
+            .await
+            .unwrap();
+        assert_eq!(login_outcome(None, Some(7)), LoginOutcome::LinkRequired);
+
+        // The owner links it from their account, after which it logs them in.
+        let link = pending(Some(7));
+        let stored = serde_json::to_string(&link).unwrap();
+        let link = check_pending(Some(&stored), Some(7)).unwrap();
+        let (code, _) = authorize(&provider, &link, "owner@example.com").await;
+        let identity = authenticate(&provider, REDIRECT_URI, &code, &link)
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, "mock|owner@example.com");
+        assert_eq!(login_outcome(Some(7), Some(7)), LoginOutcome::LogIn(7));
+    }
+
+    #[test]
+    fn rejects_unknown_or_mismatched_state() {
+        assert!(check_pending(None, None).is_err());
+        assert!(check_pending(Some("not json"), None).is_err());
+
+        let login = serde_json::to_string(&pending(None)).unwrap();
+        let link = serde_json::to_string(&pending(Some(7))).unwrap();
+        assert!(check_pending(Some(&login), None).is_ok());
+        assert!(check_pending(Some(&login), Some(7)).is_err());
+        assert!(check_pending(Some(&link), None).is_err());
+        assert!(check_pending(Some(&link), Some(8)).is_err());
+    }
+
+    #[actix_web::test]
+    async fn rejects_mismatched_nonce() {
+        let provider = mock_provider();
+        let started = pending(None);
+        let (code, _) = authorize(&provider, &started, "user@example.com").await;
+
+        let other = PendingLogin {
+            nonce: random_token(32),
+            ..started
+        };
+        let error = authenticate(&provider, REDIRECT_URI, &code, &other)
+            .await
+            .unwrap_err();
+        assert_eq!(error, "ID token failed validation");
+    }
+
+    #[actix_web::test]
+    async fn rejects_wrong_code_verifier() {
+        let provider = mock_provider();
+        let started = pending(None);
+        let (code, _) = authorize(&provider, &started, "user@example.com").await;
+
+        let other = PendingLogin {
+            verifier: random_token(64),
+            ..started
+        };
+        assert!(authenticate(&provider, REDIRECT_URI, &code, &other)
+            .await
+            .is_err());
+    }
+}
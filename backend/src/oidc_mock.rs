@@ -0,0 +1,135 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+
+use crate::oidc::{now_seconds, pkce_challenge};
+
+fn mock_issuer(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}/oidc-mock", info.scheme(), info.host())
+}
+
+async fn mock_discovery(req: HttpRequest) -> HttpResponse {
+    let issuer = mock_issuer(&req);
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "jwks_uri": format!("{}/jwks", issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["HS256"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+/// Logs in whoever is named in `login_hint` without asking, and redirects
+/// straight back. The code just carries the request.
+async fn mock_authorize(query: web::Query<HashMap<String, String>>) -> HttpResponse {
+    let (Some(redirect_uri), Some(state), Some(challenge)) = (
+        query.get("redirect_uri"),
+        query.get("state"),
+        query.get("code_challenge"),
+    ) else {
+        return HttpResponse::BadRequest().body("invalid_request");
+    };
+
+    let code = URL_SAFE_NO_PAD.encode(
+        json!({
+            "email": query.get("login_hint").map(String::as_str).unwrap_or("mock.user@example.com"),
+            "nonce": query.get("nonce"),
+            "client_id": query.get("client_id"),
+            "code_challenge": challenge,
+        })
+        .to_string(),
+    );
+
+    let Ok(mut location) = reqwest::Url::parse(redirect_uri) else {
+        return HttpResponse::BadRequest().body("invalid_request");
+    };
+    location
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", state);
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location.to_string()))
+        .finish()
+}
+
+async fn mock_token(req: HttpRequest, form: web::Form<HashMap<String, String>>) -> HttpResponse {
+    let request: Option<Value> = form
+        .get("code")
+        .and_then(|code| URL_SAFE_NO_PAD.decode(code).ok())
+        .and_then(|code| serde_json::from_slice(&code).ok());
+    let (Some(request), Some(verifier), Some(secret)) = (
+        request,
+        form.get("code_verifier"),
+        form.get("client_secret"),
+    ) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_request" }));
+    };
+
+    if request["code_challenge"].as_str() != Some(pkce_challenge(verifier).as_str())
+        || request["client_id"].as_str() != form.get("client_id").map(String::as_str)
+    {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+
+    let email = request["email"].as_str().unwrap_or_default();
+    let now = now_seconds();
+    let claims = json!({
+        "iss": mock_issuer(&req),
+        "sub": format!("mock|{}", email),
+        "aud": request["client_id"],
+        "iat": now,
+        "exp": now + 300,
+        "nonce": request["nonce"],
+        "email": email,
+        "email_verified": true,
+    });
+
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(json!({ "alg": "HS256", "typ": "JWT" }).to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    let id_token = format!(
+        "{}.{}",
+        message,
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    );
+
+    HttpResponse::Ok().json(json!({
+        "access_token": "mock",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+}
+
+async fn mock_jwks() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "keys": [] }))
+}
+
+/// A throwaway issuer under `/oidc-mock` for local development and tests:
+/// configure a provider with this issuer and any client id/secret to log in as
+/// `login_hint`. Only built with the `oidc-mock` feature, since it vouches for
+/// any email.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/oidc-mock")
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(mock_discovery),
+            )
+            .route("/authorize", web::get().to(mock_authorize))
+            .route("/token", web::post().to(mock_token))
+            .route("/jwks", web::get().to(mock_jwks)),
+    );
+}
@@ -0,0 +1,301 @@
+use crate::{
+    oidc::{
+        authenticate, authorization_url, check_pending, login_outcome, random_token, Identity,
+        LoginOutcome, PendingLogin,
+    },
+    rate_limit::{self, AuthOperation},
+    user_id_from_token,
+    user_queries::{complete_login, LoginResponse},
+    Context,
+};
+
+use actix_web::Result;
+use async_graphql::{Object, SimpleObject};
+use chrono::Utc;
+use deadpool_redis::redis::cmd;
+use entity::{
+    user::{self, Entity as User},
+    user_identity::{self, Entity as UserIdentity},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, ModelTrait, Order, PaginatorTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+
+/// How long the user has to come back from the provider.
+const PENDING_LOGIN_SECONDS: u64 = 10 * 60;
+
+#[derive(SimpleObject)]
+#[graphql(name = "OidcAuthorization")]
+pub struct OidcAuthorization {
+    /// Send the browser here.
+    url: String,
+    state: String,
+}
+
+async fn start(
+    my_ctx: &Context,
+    provider: &str,
+    link_user_id: Option<i32>,
+) -> Result<OidcAuthorization, async_graphql::Error> {
+    let provider = my_ctx
+        .oidc
+        .provider(provider)
+        .ok_or_else(|| async_graphql::Error::new("Unknown login provider"))?;
+
+    let state = random_token(32);
+    let pending = PendingLogin {
+        provider: provider.name.clone(),
+        verifier: random_token(64),
+        nonce: random_token(32),
+        link_user_id,
+    };
+
+    let url = authorization_url(provider, &my_ctx.oidc.redirect_uri, &state, &pending)
+        .await
+        .map_err(async_graphql::Error::new)?;
+
+    let mut conn = my_ctx.redis_pool.get().await?;
+    cmd("SET")
+        .arg(format!("oidc_state:{}", state))
+        .arg(serde_json::to_string(&pending)?)
+        .arg("EX")
+        .arg(PENDING_LOGIN_SECONDS)
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(OidcAuthorization { url, state })
+}
+
+/// Consumes the pending login for `state` and verifies the callback `code`.
+/// `link_user_id` is the user linking a provider, `None` for a login.
+async fn finish(
+    my_ctx: &Context,
+    state: &str,
+    code: &str,
+    link_user_id: Option<i32>,
+) -> Result<(PendingLogin, Identity), async_graphql::Error> {
+    let mut conn = my_ctx.redis_pool.get().await?;
+    let pending: Option<String> = cmd("GETDEL")
+        .arg(format!("oidc_state:{}", state))
+        .query_async(&mut conn)
+        .await?;
+    let pending =
+        check_pending(pending.as_deref(), link_user_id).map_err(async_graphql::Error::new)?;
+
+    let provider = my_ctx
+        .oidc
+        .provider(&pending.provider)
+        .ok_or_else(|| async_graphql::Error::new("Unknown login provider"))?;
+
+    let identity = authenticate(provider, &my_ctx.oidc.redirect_uri, code, &pending)
+        .await
+        .map_err(async_graphql::Error::new)?;
+
+    Ok((pending, identity))
+}
+
+fn new_identity(user_id: i32, provider: &str, identity: &Identity) -> user_identity::ActiveModel {
+    user_identity::ActiveModel {
+        user_id: Set(user_id),
+        provider: Set(provider.to_string()),
+        subject: Set(identity.subject.clone()),
+        email: Set(identity.email.clone()),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    }
+}
+
+async fn find_identity(
+    my_ctx: &Context,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<user_identity::Model>, async_graphql::Error> {
+    Ok(UserIdentity::find()
+        .filter(user_identity::Column::Provider.eq(provider))
+        .filter(user_identity::Column::Subject.eq(subject))
+        .one(&my_ctx.db)
+        .await?)
+}
+
+#[derive(Default)]
+pub struct OidcQuery;
+
+#[Object]
+impl OidcQuery {
+    /// Names of the configured login providers.
+    async fn oidc_providers(&self, ctx: &async_graphql::Context<'_>) -> Vec<String> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        my_ctx
+            .oidc
+            .providers
+            .iter()
+            .map(|provider| provider.name.clone())
+            .collect()
+    }
+
+    async fn my_identities(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<Vec<user_identity::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let identities = UserIdentity::find()
+            .filter(user_identity::Column::UserId.eq(user_id))
+            .order_by(user_identity::Column::CreatedAt, Order::Asc)
+            .all(&my_ctx.db)
+            .await?;
+
+        Ok(identities)
+    }
+}
+
+#[derive(Default)]
+pub struct OidcMutation;
+
+#[Object]
+impl OidcMutation {
+    /// First step of logging in with a provider (authorization code + PKCE).
+    async fn start_oidc_login(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        provider: String,
+    ) -> Result<OidcAuthorization, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        rate_limit::check(ctx, AuthOperation::Login, None).await?;
+
+        start(my_ctx, &provider, None).await
+    }
+
+    /// Completes a provider login with the `state` and `code` from the callback.
+    /// A known identity logs its user in and an unknown one signs up. If an
+    /// account already has the email, its owner has to log in and link the
+    /// provider with `startOidcLink`.
+    async fn finish_oidc_login(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        state: String,
+        code: String,
+    ) -> Result<LoginResponse, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        rate_limit::check(ctx, AuthOperation::Login, None).await?;
+
+        let (pending, identity) = finish(my_ctx, &state, &code, None).await?;
+
+        let linked = find_identity(my_ctx, &pending.provider, &identity.subject).await?;
+        let existing = match &identity.email {
+            Some(email) => User::find_by_email(email.clone()).one(&my_ctx.db).await?,
+            None => None,
+        };
+
+        let user = match login_outcome(
+            linked.map(|linked| linked.user_id),
+            existing.map(|user| user.id),
+        ) {
+            LoginOutcome::LogIn(user_id) => User::find_by_id(user_id)
+                .one(&my_ctx.db)
+                .await?
+                .ok_or_else(|| async_graphql::Error::new("No user found"))?,
+            LoginOutcome::LinkRequired => {
+                return Err(async_graphql::Error::new(
+                    "An account with this email exists; log in with your password and link the provider",
+                ));
+            }
+            LoginOutcome::SignUp => {
+                let now = Utc::now().naive_utc();
+                let txn = my_ctx.db.begin().await?;
+                let user: user::Model = user::ActiveModel {
+                    name: Set(identity.given_name.clone()),
+                    surname: Set(identity.family_name.clone()),
+                    email: Set(identity.email.clone()),
+                    email_verified: Set(identity.email.is_some() && identity.email_verified),
+                    password_hash: Set(None),
+                    created_at: Set(now),
+                    updated_at: Set(now),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?;
+                new_identity(user.id, &pending.provider, &identity)
+                    .insert(&txn)
+                    .await?;
+                txn.commit().await?;
+                user
+            }
+        };
+        if user.banned {
+            return Err(async_graphql::Error::new("User is banned"));
+        }
+
+        complete_login(ctx, user).await
+    }
+
+    /// Like `startOidcLogin`, but links the provider to the logged-in account.
+    async fn start_oidc_link(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        provider: String,
+    ) -> Result<OidcAuthorization, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        start(my_ctx, &provider, Some(user_id)).await
+    }
+
+    async fn finish_oidc_link(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        state: String,
+        code: String,
+    ) -> Result<user_identity::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let (pending, identity) = finish(my_ctx, &state, &code, Some(user_id)).await?;
+
+        match find_identity(my_ctx, &pending.provider, &identity.subject).await? {
+            Some(linked) if linked.user_id == user_id => Ok(linked),
+            Some(_) => Err(async_graphql::Error::new(
+                "This login is already linked to another account",
+            )),
+            None => Ok(new_identity(user_id, &pending.provider, &identity)
+                .insert(&my_ctx.db)
+                .await?),
+        }
+    }
+
+    /// Removes a linked provider, unless it is the only way left to log in.
+    async fn unlink_identity(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        identity_id: i32,
+    ) -> Result<bool, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+
+        let identity = UserIdentity::find_by_id(identity_id)
+            .one(&my_ctx.db)
+            .await?
+            .filter(|identity| identity.user_id == user_id)
+            .ok_or_else(|| async_graphql::Error::new("Identity not found"))?;
+
+        let user = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Wrong token"))?;
+        let identity_count = UserIdentity::find()
+            .filter(user_identity::Column::UserId.eq(user_id))
+            .count(&my_ctx.db)
+            .await?;
+        if user.password_hash.is_none() && identity_count <= 1 {
+            return Err(async_graphql::Error::new(
+                "Set a password before removing your only login provider",
+            ));
+        }
+
+        identity.delete(&my_ctx.db).await?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,171 @@
+use async_graphql::Error;
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+
+/// Rules every new password has to pass, wherever it is set.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Directory of breached-password range files in the Have I Been Pwned
+    /// k-anonymity format: one file per 5-character SHA-1 prefix (`ABCDE.txt`)
+    /// holding `SUFFIX:COUNT` lines.
+    pub breached_passwords_dir: Option<PathBuf>,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            min_length: dotenvy::var("PASSWORD_MIN_LENGTH")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse()
+                .expect("PASSWORD_MIN_LENGTH is not a number"),
+            max_length: 128,
+            breached_passwords_dir: dotenvy::var("BREACHED_PASSWORDS_DIR")
+                .ok()
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        }
+    }
+
+    /// Rejects passwords that are too short or long, derived from the email,
+    /// or known from breaches.
+    pub async fn check(&self, password: &str, email: Option<&str>) -> Result<(), Error> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(Error::new(format!(
+                "Password must be at least {} characters",
+                self.min_length
+            )));
+        }
+        if length > self.max_length {
+            return Err(Error::new(format!(
+                "Password must be at most {} characters",
+                self.max_length
+            )));
+        }
+
+        if let Some(email) = email {
+            if resembles_email(password, email) {
+                return Err(Error::new("Password is too similar to your email"));
+            }
+        }
+
+        if self.is_breached(password).await? {
+            return Err(Error::new(
+                "This password has appeared in a data breach, choose another one",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn is_breached(&self, password: &str) -> Result<bool, Error> {
+        let Some(dir) = &self.breached_passwords_dir else {
+            return Ok(false);
+        };
+
+        let hash: String = Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let (prefix, suffix) = hash.split_at(5);
+
+        let range = match tokio::fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+            Ok(range) => range,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(Error::new(err.to_string())),
+        };
+
+        Ok(range.lines().any(|line| {
+            line.split(':')
+                .next()
+                .is_some_and(|candidate| candidate.trim().eq_ignore_ascii_case(suffix))
+        }))
+    }
+}
+
+fn resembles_email(password: &str, email: &str) -> bool {
+    let password = password.to_lowercase();
+    let email = email.to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+
+    password == email
+        || email.contains(&password)
+        || (local_part.chars().count() >= 4 && password.contains(local_part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(breached_passwords_dir: Option<PathBuf>) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 128,
+            breached_passwords_dir,
+        }
+    }
+
+    #[test]
+    fn resembles_email_matches_the_address_and_its_local_part() {
+        assert!(resembles_email(
+            "Jane.Doe@Example.com",
+            "jane.doe@example.com"
+        ));
+        assert!(resembles_email("example.com", "jane.doe@example.com"));
+        assert!(resembles_email("jane.doe2024", "jane.doe@example.com"));
+        assert!(!resembles_email(
+            "correct horse battery",
+            "jane.doe@example.com"
+        ));
+        // Short local parts are too common to reject on.
+        assert!(!resembles_email("bobsleigh-team", "bob@example.com"));
+    }
+
+    #[tokio::test]
+    async fn breached_passwords_are_looked_up_by_hash_prefix() {
+        let dir = std::env::temp_dir().join(format!("breached-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8.
+        std::fs::write(
+            dir.join("5BAA6.txt"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n1e4c9b93f3f0682250b6cf8331b7ee68fd8:9545824\r\n",
+        )
+        .unwrap();
+        let policy = policy(Some(dir.clone()));
+
+        assert!(policy.is_breached("password").await.unwrap());
+        // No range file for its prefix.
+        assert!(!policy.is_breached("correct horse battery").await.unwrap());
+        assert!(policy
+            .check("password", None)
+            .await
+            .unwrap_err()
+            .message
+            .contains("data breach"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_breach_data_lets_passwords_through() {
+        let unconfigured = policy(None);
+        assert!(!unconfigured.is_breached("password").await.unwrap());
+        assert!(unconfigured.check("password", None).await.is_ok());
+
+        let empty = policy(Some(std::env::temp_dir().join("no-breach-data")));
+        assert!(!empty.is_breached("password").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn check_rejects_length_and_email_lookalikes() {
+        let policy = policy(None);
+        assert!(policy.check("short", None).await.is_err());
+        assert!(policy.check(&"x".repeat(129), None).await.is_err());
+        assert!(policy
+            .check("jane.doe@example.com", Some("jane.doe@example.com"))
+            .await
+            .is_err());
+    }
+}
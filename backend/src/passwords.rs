@@ -0,0 +1,112 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use async_graphql::Error;
+
+/// Outcome of checking a password against a stored hash.
+#[derive(PartialEq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// Correct, but hashed with other parameters than the current ones.
+    ValidNeedsRehash,
+    /// The stored hash can't be parsed; only a reset will fix the account.
+    Corrupt,
+}
+
+/// Argon2id hashing with parameters from the environment. Hashing is CPU
+/// heavy by design, so it runs on the blocking pool.
+#[derive(Clone, Debug)]
+pub struct Passwords {
+    params: Params,
+}
+
+impl Passwords {
+    /// Defaults to the argon2 crate's defaults (19 MiB, 2 passes, 1 lane).
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u32| -> u32 {
+            dotenvy::var(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{} is not a number", name))
+                })
+                .unwrap_or(default)
+        };
+
+        let params = Params::new(
+            var("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST),
+            var("ARGON2_ITERATIONS", Params::DEFAULT_T_COST),
+            var("ARGON2_PARALLELISM", Params::DEFAULT_P_COST),
+            None,
+        )
+        .expect("Invalid Argon2 parameters");
+
+        Self { params }
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    pub async fn hash(&self, password: String) -> Result<String, Error> {
+        let argon2 = self.argon2();
+
+        tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            argon2
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| Error::new(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::new(e.to_string()))?
+    }
+
+    pub async fn verify(&self, password: String, stored: String) -> Result<Verification, Error> {
+        let argon2 = self.argon2();
+        let params = self.params.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let Ok(hash) = PasswordHash::new(&stored) else {
+                return Verification::Corrupt;
+            };
+            // Verification takes its parameters from the hash itself.
+            if argon2.verify_password(password.as_bytes(), &hash).is_err() {
+                return Verification::Invalid;
+            }
+
+            let current = hash.algorithm == Algorithm::Argon2id.ident()
+                && hash.version == Some(Version::V0x13.into())
+                && Params::try_from(&hash).is_ok_and(|stored| {
+                    stored.m_cost() == params.m_cost()
+                        && stored.t_cost() == params.t_cost()
+                        && stored.p_cost() == params.p_cost()
+                });
+            if current {
+                Verification::Valid
+            } else {
+                Verification::ValidNeedsRehash
+            }
+        })
+        .await
+        .map_err(|e| Error::new(e.to_string()))
+    }
+
+    /// Verifies a password for an action that needs re-confirming it, where a
+    /// corrupt or missing hash simply means the password is wrong.
+    pub async fn confirm(&self, password: &str, stored: Option<&str>) -> Result<bool, Error> {
+        let Some(stored) = stored else {
+            return Ok(false);
+        };
+
+        let verification = self
+            .verify(password.to_string(), stored.to_string())
+            .await?;
+        Ok(matches!(
+            verification,
+            Verification::Valid | Verification::ValidNeedsRehash
+        ))
+    }
+}
@@ -0,0 +1,51 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use entity::money::Money;
+use std::{fmt::Debug, sync::Arc};
+
+/// Takes payments for paid features. Implementations are picked by
+/// `PAYMENT_PROVIDER`.
+#[async_trait]
+pub trait PaymentProvider: Debug + Send + Sync {
+    /// Charges `user_id` and returns the provider's reference for the payment.
+    async fn charge(
+        &self,
+        user_id: i32,
+        amount: Money,
+        currency: &str,
+        description: &str,
+    ) -> Result<String, String>;
+}
+
+/// Development stand-in that accepts every payment without charging anyone.
+#[derive(Debug)]
+pub struct StubPaymentProvider;
+
+#[async_trait]
+impl PaymentProvider for StubPaymentProvider {
+    async fn charge(
+        &self,
+        user_id: i32,
+        amount: Money,
+        currency: &str,
+        description: &str,
+    ) -> Result<String, String> {
+        eprintln!(
+            "Payment of {} {} by user {}: {}",
+            amount, currency, user_id, description
+        );
+        Ok(format!(
+            "stub-{}-{}",
+            user_id,
+            Utc::now().timestamp_millis()
+        ))
+    }
+}
+
+pub fn from_env() -> Arc<dyn PaymentProvider> {
+    let provider = dotenvy::var("PAYMENT_PROVIDER").unwrap_or_else(|_| "stub".to_string());
+    match provider.as_str() {
+        "stub" => Arc::new(StubPaymentProvider),
+        other => panic!("Unknown PAYMENT_PROVIDER: {}", other),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::Context;
+
+use async_graphql::Error;
+use deadpool_redis::redis::cmd;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+const CODE_TTL_SECONDS: u64 = 10 * 60;
+const MAX_CODE_ATTEMPTS: i64 = 5;
+
+/// Normalises a number to E.164 (`+` and up to 15 digits). Numbers without an
+/// international prefix are taken to be in `default_country_code`.
+pub fn normalize_phone(input: &str, default_country_code: &str) -> Result<String, Error> {
+    let compact: String = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '(' | ')' | '.'))
+        .collect();
+
+    let international = if let Some(rest) = compact.strip_prefix('+') {
+        rest.to_string()
+    } else if let Some(rest) = compact.strip_prefix("00") {
+        rest.to_string()
+    } else {
+        format!("{}{}", default_country_code, compact)
+    };
+
+    let valid = (8..=15).contains(&international.len())
+        && international.chars().all(|c| c.is_ascii_digit())
+        && !international.starts_with('0');
+    if !valid {
+        return Err(Error::new("Invalid phone number"));
+    }
+
+    Ok(format!("+{}", international))
+}
+
+/// What a one-time code sent to a phone proves.
+#[derive(Clone, Copy)]
+pub enum CodePurpose {
+    /// The user owns the number on their profile.
+    Verify,
+    Login,
+}
+
+impl CodePurpose {
+    fn key(&self, phone: &str) -> String {
+        match self {
+            CodePurpose::Verify => format!("phone_code:verify:{}", phone),
+            CodePurpose::Login => format!("phone_code:login:{}", phone),
+        }
+    }
+}
+
+fn hash_code(code: &str) -> String {
+    Sha256::digest(code.trim().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Texts a fresh six-digit code to `phone`, replacing any earlier one.
+pub async fn send_code(my_ctx: &Context, phone: &str, purpose: CodePurpose) -> Result<(), Error> {
+    let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+    let key = purpose.key(phone);
+
+    let mut conn = my_ctx.redis_pool.get().await?;
+    cmd("DEL").arg(&key).query_async::<()>(&mut conn).await?;
+    cmd("HSET")
+        .arg(&key)
+        .arg("hash")
+        .arg(hash_code(&code))
+        .arg("attempts")
+        .arg(0)
+        .query_async::<()>(&mut conn)
+        .await?;
+    cmd("EXPIRE")
+        .arg(&key)
+        .arg(CODE_TTL_SECONDS)
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    my_ctx
+        .sms
+        .send(phone, &format!("Your Adee code is {}", code))
+        .await
+        .map_err(Error::new)
+}
+
+/// Checks a code and burns it on success. Too many wrong guesses burn it too.
+pub async fn check_code(
+    my_ctx: &Context,
+    phone: &str,
+    purpose: CodePurpose,
+    code: &str,
+) -> Result<bool, Error> {
+    let key = purpose.key(phone);
+    let mut conn = my_ctx.redis_pool.get().await?;
+
+    let stored: Option<String> = cmd("HGET")
+        .arg(&key)
+        .arg("hash")
+        .query_async(&mut conn)
+        .await?;
+    let Some(stored) = stored else {
+        return Ok(false);
+    };
+
+    let attempts: i64 = cmd("HINCRBY")
+        .arg(&key)
+        .arg("attempts")
+        .arg(1)
+        .query_async(&mut conn)
+        .await?;
+    let matches = attempts <= MAX_CODE_ATTEMPTS && stored == hash_code(code);
+
+    if matches || attempts >= MAX_CODE_ATTEMPTS {
+        cmd("DEL").arg(&key).query_async::<()>(&mut conn).await?;
+    }
+
+    Ok(matches)
+}
@@ -0,0 +1,142 @@
+use crate::{
+    phone::{check_code, normalize_phone, send_code, CodePurpose},
+    rate_limit::{self, AuthOperation},
+    user_id_from_token,
+    user_queries::{complete_login, LoginResponse},
+    Context,
+};
+
+use actix_web::Result;
+use async_graphql::Object;
+use chrono::Utc;
+use entity::user::{self, Entity as User};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set, SqlErr};
+
+async fn find_user(my_ctx: &Context, user_id: i32) -> Result<user::Model, async_graphql::Error> {
+    User::find_by_id(user_id)
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Wrong token"))
+}
+
+#[derive(Default)]
+pub struct PhoneMutation;
+
+#[Object]
+impl PhoneMutation {
+    /// Texts a code to the phone number on the caller's profile.
+    async fn request_phone_verification(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<bool, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let phone = user
+            .phone
+            .ok_or_else(|| async_graphql::Error::new("Add a phone number first"))?;
+        if user.phone_verified {
+            return Err(async_graphql::Error::new("Phone number already verified"));
+        }
+        let phone = normalize_phone(&phone, &my_ctx.phone_country_code)?;
+        rate_limit::check(ctx, AuthOperation::PhoneCode, Some(&phone)).await?;
+
+        send_code(my_ctx, &phone, CodePurpose::Verify).await?;
+
+        Ok(true)
+    }
+
+    async fn verify_phone(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        code: String,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let phone = user
+            .phone
+            .as_deref()
+            .ok_or_else(|| async_graphql::Error::new("Add a phone number first"))
+            .and_then(|phone| normalize_phone(phone, &my_ctx.phone_country_code))?;
+        if !check_code(my_ctx, &phone, CodePurpose::Verify, &code).await? {
+            return Err(async_graphql::Error::new("Wrong or expired code"));
+        }
+
+        let taken = User::find_by_phone(phone.clone())
+            .filter(user::Column::PhoneVerified.eq(true))
+            .filter(user::Column::Id.ne(user.id))
+            .one(&my_ctx.db)
+            .await?;
+        if taken.is_some() {
+            return Err(async_graphql::Error::new(
+                "This phone number is verified on another account",
+            ));
+        }
+
+        // The unique index still catches another account verifying it meanwhile.
+        let user: user::Model = user::ActiveModel {
+            phone: Set(Some(phone)),
+            phone_verified: Set(true),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await
+        .map_err(|err| match err.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => {
+                async_graphql::Error::new("This phone number is verified on another account")
+            }
+            _ => err.into(),
+        })?;
+
+        Ok(user)
+    }
+
+    /// Texts a login code if the number is verified on an account. Always
+    /// succeeds so it can't be used to find out which numbers are registered.
+    async fn request_phone_login_code(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        phone: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let phone = normalize_phone(&phone, &my_ctx.phone_country_code)?;
+        rate_limit::check(ctx, AuthOperation::PhoneCode, Some(&phone)).await?;
+
+        let user = User::find_by_phone(phone.clone())
+            .filter(user::Column::PhoneVerified.eq(true))
+            .one(&my_ctx.db)
+            .await?;
+        if user.is_some() {
+            send_code(my_ctx, &phone, CodePurpose::Login).await?;
+        }
+
+        Ok(true)
+    }
+
+    async fn login_with_phone(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        phone: String,
+        code: String,
+    ) -> Result<LoginResponse, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let phone = normalize_phone(&phone, &my_ctx.phone_country_code)?;
+        rate_limit::check(ctx, AuthOperation::Login, Some(&phone)).await?;
+
+        if !check_code(my_ctx, &phone, CodePurpose::Login, &code).await? {
+            return Err(async_graphql::Error::new("Wrong or expired code"));
+        }
+
+        // "idx-user-verified_phone" allows a verified number on one account only,
+        // so this can't pick between several.
+        let user = User::find_by_phone(phone)
+            .filter(user::Column::PhoneVerified.eq(true))
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Wrong or expired code"))?;
+
+        complete_login(ctx, user).await
+    }
+}
@@ -0,0 +1,68 @@
+use entity::{
+    advert,
+    favorites::{self, Entity as Favorites},
+    money::Money,
+    notification::NotificationKind,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use crate::notifications::{notify, NewNotification};
+
+fn body(title: &str, old_price: Money, new_price: Money, currency: &str) -> String {
+    format!(
+        "\"{}\" dropped from {} {} to {} {}.",
+        title, old_price, currency, new_price, currency
+    )
+}
+
+pub async fn notify_favorites(
+    db: &DatabaseConnection,
+    advert: &advert::Model,
+    old_price: Money,
+) -> Result<(), sea_orm::DbErr> {
+    let watchers = Favorites::find()
+        .filter(favorites::Column::AdvertId.eq(advert.id))
+        .filter(favorites::Column::UserId.ne(advert.user_id))
+        .all(db)
+        .await?;
+
+    let link = format!("https://ad-ee.tech/advert/{}", advert.id);
+
+    for watcher in watchers {
+        notify(
+            db,
+            NewNotification {
+                user_id: watcher.user_id,
+                kind: NotificationKind::PriceDrop,
+                title: "Price drop on a favorite advert".to_string(),
+                body: body(&advert.title, old_price, advert.price, &advert.currency),
+                link: Some(link.clone()),
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::email_content;
+    use sea_orm::prelude::Decimal;
+
+    #[test]
+    fn title_is_escaped_in_the_email() {
+        let body = body(
+            "<img src=x onerror=alert(1)>",
+            Money(Decimal::new(100, 0)),
+            Money(Decimal::new(80, 0)),
+            "EUR",
+        );
+        let (text, html) = email_content(&body, Some("https://ad-ee.tech/advert/1"));
+
+        assert!(text.starts_with("\"<img src=x onerror=alert(1)>\" dropped"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("&quot;&lt;img src=x onerror=alert(1)&gt;&quot; dropped"));
+    }
+}
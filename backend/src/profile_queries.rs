@@ -0,0 +1,277 @@
+use crate::{user_id_from_token, Context};
+
+use actix_web::Result;
+use async_graphql::Object;
+use chrono::Utc;
+use entity::{
+    advert::{self, AdvertStatus, Entity as Advert},
+    user::{self, AccountType, Entity as User, Role},
+};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, Order, QueryFilter, QueryOrder, Set};
+
+/// Profiles without a custom slug are reachable as `user-{id}`, so custom
+/// slugs may not start with this.
+const DEFAULT_SLUG_PREFIX: &str = "user-";
+const RESERVED_SLUGS: &[&str] = &["admin", "me", "settings", "search", "advert", "adverts"];
+
+/// Lowercases `input` and checks it is 3–40 letters, digits and inner hyphens.
+fn normalize_slug(input: &str) -> Result<String, async_graphql::Error> {
+    let slug = input.trim().to_lowercase();
+
+    let valid_chars = slug
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !(3..=40).contains(&slug.len())
+        || !valid_chars
+        || slug.starts_with('-')
+        || slug.ends_with('-')
+        || slug.contains("--")
+    {
+        return Err(async_graphql::Error::new(
+            "Use 3 to 40 letters, digits or single hyphens",
+        ));
+    }
+    if slug.starts_with(DEFAULT_SLUG_PREFIX) || RESERVED_SLUGS.contains(&slug.as_str()) {
+        return Err(async_graphql::Error::new("This address is reserved"));
+    }
+
+    Ok(slug)
+}
+
+/// Uppercase without spaces or dots, e.g. "lv 4000 3241 234" -> "LV40003241234".
+fn normalize_vat_number(input: &str) -> Result<String, async_graphql::Error> {
+    let vat: String = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '.' && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+
+    let valid = vat.len() >= 4
+        && vat.len() <= 15
+        && vat.is_ascii()
+        && vat[..2].chars().all(|c| c.is_ascii_alphabetic())
+        && vat[2..].chars().all(|c| c.is_ascii_alphanumeric());
+    if !valid {
+        return Err(async_graphql::Error::new(
+            "VAT number must be a country code followed by up to 13 letters or digits",
+        ));
+    }
+
+    Ok(vat)
+}
+
+/// `None` keeps the current value, an empty string clears it.
+fn updated(value: Option<String>, current: &Option<String>) -> Option<String> {
+    match value {
+        Some(value) => {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        }
+        None => current.clone(),
+    }
+}
+
+async fn find_user(my_ctx: &Context, user_id: i32) -> Result<user::Model, async_graphql::Error> {
+    User::find_by_id(user_id)
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Wrong token"))
+}
+
+#[derive(Default)]
+pub struct ProfileQuery;
+
+#[Object]
+impl ProfileQuery {
+    /// A seller's public profile with their live adverts, by custom slug or
+    /// `user-{id}`.
+    async fn seller_profile(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        slug: String,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let slug = slug.trim().to_lowercase();
+
+        let user = match slug
+            .strip_prefix(DEFAULT_SLUG_PREFIX)
+            .and_then(|id| id.parse::<i32>().ok())
+        {
+            Some(id) => User::find_by_id(id).one(&my_ctx.db).await?,
+            None => User::find_by_slug(slug).one(&my_ctx.db).await?,
+        };
+        let mut user = user
+            .filter(|user| user.deleted_at.is_none() && !user.banned)
+            .ok_or_else(|| async_graphql::Error::new("No user found"))?;
+
+        user.adverts = Advert::find()
+            .filter(advert::Column::UserId.eq(user.id))
+            .filter(advert::Column::Status.is_in([AdvertStatus::Active, AdvertStatus::Reserved]))
+            .order_by(advert::Column::CreatedAt, Order::Desc)
+            .all(&my_ctx.db)
+            .await?;
+
+        Ok(user)
+    }
+}
+
+#[derive(Default)]
+pub struct ProfileMutation;
+
+#[Object]
+impl ProfileMutation {
+    /// Switches between a private and a business account and sets the business
+    /// details; omitted fields are kept, empty ones cleared. Changing the
+    /// company, registration or VAT number of a verified business removes the
+    /// badge until an admin checks it again.
+    #[allow(clippy::too_many_arguments)]
+    async fn update_business_profile(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        account_type: AccountType,
+        company_name: Option<String>,
+        registration_number: Option<String>,
+        vat_number: Option<String>,
+        address: Option<String>,
+        opening_hours: Option<String>,
+        logo_url: Option<String>,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let company_name = updated(company_name, &user.company_name);
+        let registration_number = updated(registration_number, &user.registration_number);
+        let vat_number = match updated(vat_number, &user.vat_number) {
+            Some(vat_number) => Some(normalize_vat_number(&vat_number)?),
+            None => None,
+        };
+        let address = updated(address, &user.address);
+        let opening_hours = updated(opening_hours, &user.opening_hours);
+        let logo_url = updated(logo_url, &user.logo_url);
+
+        if account_type == AccountType::Business
+            && (company_name.is_none() || registration_number.is_none())
+        {
+            return Err(async_graphql::Error::new(
+                "A business account needs a company name and registration number",
+            ));
+        }
+
+        let details_changed = account_type != user.account_type
+            || company_name != user.company_name
+            || registration_number != user.registration_number
+            || vat_number != user.vat_number;
+
+        let mut active_user: user::ActiveModel = user.clone().into();
+        active_user.account_type = Set(account_type);
+        active_user.company_name = Set(company_name);
+        active_user.registration_number = Set(registration_number);
+        active_user.vat_number = Set(vat_number);
+        active_user.address = Set(address);
+        active_user.opening_hours = Set(opening_hours);
+        active_user.logo_url = Set(logo_url);
+        if details_changed && user.business_verified {
+            active_user.business_verified = Set(false);
+            active_user.business_verified_at = Set(None);
+        }
+        active_user.updated_at = Set(Utc::now().naive_utc());
+
+        Ok(active_user.update(&my_ctx.db).await?)
+    }
+
+    /// Sets the caller's public profile address; an empty slug goes back to
+    /// `user-{id}`.
+    async fn set_profile_slug(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        slug: String,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let slug = match slug.trim() {
+            "" => None,
+            slug => Some(normalize_slug(slug)?),
+        };
+
+        if let Some(slug) = &slug {
+            let taken = User::find_by_slug(slug.clone())
+                .filter(user::Column::Id.ne(user.id))
+                .one(&my_ctx.db)
+                .await?;
+            if taken.is_some() {
+                return Err(async_graphql::Error::new("This address is taken"));
+            }
+        }
+
+        let user: user::Model = user::ActiveModel {
+            slug: Set(slug),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Whether any verified user may reveal the caller's phone number, which
+    /// is the default. Otherwise only the other side of an open chat or a deal
+    /// sees it.
+    async fn set_show_phone(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        show_phone: bool,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let user: user::Model = user::ActiveModel {
+            show_phone: Set(show_phone),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Grants or removes the verified-business badge. Admins only.
+    async fn set_business_verified(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        user_id: i32,
+        verified: bool,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let caller = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+        if caller.role != Role::Admin {
+            return Err(async_graphql::Error::new(
+                "You are not authorized to verify businesses",
+            ));
+        }
+
+        let target = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
+        if verified && target.account_type != AccountType::Business {
+            return Err(async_graphql::Error::new(
+                "Only business accounts can be verified",
+            ));
+        }
+
+        let now = Utc::now().naive_utc();
+        let target: user::Model = user::ActiveModel {
+            business_verified: Set(verified),
+            business_verified_at: Set(verified.then_some(now)),
+            updated_at: Set(now),
+            ..target.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        Ok(target)
+    }
+}
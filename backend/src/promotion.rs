@@ -0,0 +1,184 @@
+use chrono::Utc;
+use entity::{
+    advert::{self, AdvertStatus},
+    money::Money,
+    promotion::{self, Entity as Promotion, PromotionKind},
+};
+use sea_orm::{
+    prelude::Decimal, ColumnTrait, Condition, DatabaseConnection, DbErr, JoinType, Order,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+
+/// Promoted adverts take the first of every `SLOT_INTERVAL` positions in a
+/// listing: 0, 5, 10, and so on, for as long as there are any.
+pub const SLOT_INTERVAL: usize = 5;
+
+/// What a day of each promotion costs, in the base currency.
+pub fn daily_price(kind: PromotionKind) -> Money {
+    match kind {
+        PromotionKind::Bump => Money(Decimal::new(99, 2)),
+        PromotionKind::Featured => Money(Decimal::new(199, 2)),
+        PromotionKind::Highlight => Money(Decimal::new(49, 2)),
+    }
+}
+
+/// Active adverts with a running bump, plus those featured in `category` when
+/// browsing one. The most recently started promotion comes first.
+pub async fn promoted_ids(
+    db: &DatabaseConnection,
+    category: Option<&str>,
+) -> Result<Vec<i32>, DbErr> {
+    let mut kinds = Condition::any().add(promotion::Column::Kind.eq(PromotionKind::Bump));
+    if let Some(category) = category {
+        kinds = kinds.add(
+            Condition::all()
+                .add(promotion::Column::Kind.eq(PromotionKind::Featured))
+                .add(advert::Column::Category.eq(category)),
+        );
+    }
+
+    let advert_ids: Vec<i32> = Promotion::find_active(Utc::now().naive_utc())
+        .select_only()
+        .column(promotion::Column::AdvertId)
+        .join(JoinType::InnerJoin, promotion::Relation::Advert.def())
+        .filter(advert::Column::Status.eq(AdvertStatus::Active))
+        .filter(kinds)
+        .order_by(promotion::Column::StartsAt, Order::Desc)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut promoted: Vec<i32> = Vec::with_capacity(advert_ids.len());
+    for advert_id in advert_ids {
+        if !promoted.contains(&advert_id) {
+            promoted.push(advert_id);
+        }
+    }
+    Ok(promoted)
+}
+
+/// How many of `promoted` adverts get a promoted slot before `position`.
+pub fn slots_before(position: usize, promoted: usize) -> usize {
+    position.div_ceil(SLOT_INTERVAL).min(promoted)
+}
+
+/// How many promoted and organic adverts come before `position` in a listing
+/// of `promoted` and `organic` adverts laid out by `interleave`. Once the
+/// organic adverts run out, the promoted ones take every position.
+pub fn shown_before(position: usize, promoted: usize, organic: usize) -> (usize, usize) {
+    let promoted_shown =
+        promoted.min(slots_before(position, promoted).max(position.saturating_sub(organic)));
+    let organic_shown = organic.min(position - promoted_shown);
+    (promoted_shown, organic_shown)
+}
+
+/// Positions `offset..offset + limit` of a listing. `promoted` starts with the
+/// advert for the first slot at or after `offset`, and `organic` right after
+/// the organic adverts already shown before it. Once either runs out the
+/// other fills the rest.
+pub fn interleave<T>(offset: usize, limit: usize, promoted: Vec<T>, organic: Vec<T>) -> Vec<T> {
+    let mut promoted = promoted.into_iter();
+    let mut organic = organic.into_iter();
+
+    let mut page = Vec::new();
+    for position in offset..offset.saturating_add(limit) {
+        let next = if position % SLOT_INTERVAL == 0 {
+            promoted.next().or_else(|| organic.next())
+        } else {
+            organic.next().or_else(|| promoted.next())
+        };
+        match next {
+            Some(item) => page.push(item),
+            None => break,
+        }
+    }
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The whole listing, as 'P'/'O' per position, paged `limit` at a time the
+    /// way the resolvers do it.
+    fn paged(promoted: usize, organic: usize, limit: usize) -> String {
+        let mut listing = String::new();
+        let mut offset = 0;
+        loop {
+            let (promoted_from, organic_from) = shown_before(offset, promoted, organic);
+            let (promoted_to, organic_to) = shown_before(offset + limit, promoted, organic);
+            let page = interleave(
+                offset,
+                limit,
+                vec!['P'; promoted_to - promoted_from],
+                vec!['O'; organic_to - organic_from],
+            );
+            if page.is_empty() {
+                return listing;
+            }
+            listing.extend(page);
+            offset += limit;
+        }
+    }
+
+    #[test]
+    fn slots_before_counts_started_slots() {
+        assert_eq!(slots_before(0, 10), 0);
+        assert_eq!(slots_before(1, 10), 1);
+        assert_eq!(slots_before(5, 10), 1);
+        assert_eq!(slots_before(6, 10), 2);
+        assert_eq!(slots_before(23, 10), 5);
+        assert_eq!(slots_before(23, 3), 3);
+    }
+
+    #[test]
+    fn shown_before_with_enough_of_both() {
+        assert_eq!(shown_before(0, 4, 20), (0, 0));
+        assert_eq!(shown_before(3, 4, 20), (1, 2));
+        assert_eq!(shown_before(12, 4, 20), (3, 9));
+    }
+
+    #[test]
+    fn shown_before_with_few_promoted() {
+        assert_eq!(shown_before(12, 1, 20), (1, 11));
+        assert_eq!(shown_before(30, 1, 20), (1, 20));
+    }
+
+    #[test]
+    fn shown_before_with_few_organic() {
+        assert_eq!(shown_before(4, 6, 2), (2, 2));
+        assert_eq!(shown_before(7, 6, 2), (5, 2));
+        assert_eq!(shown_before(20, 6, 2), (6, 2));
+    }
+
+    #[test]
+    fn interleaves_from_an_offset_off_the_slot_boundary() {
+        let page = interleave(3, 5, vec!['P', 'P'], vec!['O', 'O', 'O', 'O']);
+        assert_eq!(page, vec!['O', 'O', 'P', 'O', 'O']);
+    }
+
+    #[test]
+    fn interleave_falls_back_when_either_runs_out() {
+        assert_eq!(
+            interleave(0, 10, vec!['P'; 6], vec!['O'; 2]),
+            vec!['P', 'O', 'O', 'P', 'P', 'P', 'P', 'P']
+        );
+        assert_eq!(
+            interleave(0, 8, vec!['P'], vec!['O'; 10]),
+            vec!['P', 'O', 'O', 'O', 'O', 'O', 'O', 'O']
+        );
+    }
+
+    #[test]
+    fn paging_shows_every_advert_once() {
+        for (promoted, organic) in [(6, 2), (2, 30), (0, 7), (9, 0), (4, 13)] {
+            for limit in [1, 3, 5, 7, 10] {
+                let listing = paged(promoted, organic, limit);
+                assert_eq!(listing, paged(promoted, organic, promoted + organic));
+                assert_eq!(listing.matches('P').count(), promoted);
+                assert_eq!(listing.matches('O').count(), organic);
+            }
+        }
+        assert_eq!(paged(6, 2, 3), "POOPPPPP");
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{promotion::daily_price, user_id_from_token, Context};
+
+use actix_web::Result;
+use async_graphql::Object;
+use chrono::{Duration, NaiveDateTime, Utc};
+use entity::{
+    advert::{self, AdvertStatus, Entity as Advert},
+    money::Money,
+    promotion::{self, Entity as Promotion, PromotionKind},
+    user::{self, Entity as User, Role},
+};
+use sea_orm::{
+    prelude::Decimal, ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, Order,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+
+const MAX_PROMOTION_DAYS: i32 = 30;
+
+async fn find_user(my_ctx: &Context, user_id: i32) -> Result<user::Model, async_graphql::Error> {
+    User::find_by_id(user_id)
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Wrong token"))
+}
+
+async fn find_active_advert(
+    my_ctx: &Context,
+    advert_id: i32,
+) -> Result<advert::Model, async_graphql::Error> {
+    Advert::find_by_id(advert_id)
+        .filter(advert::Column::Status.eq(AdvertStatus::Active))
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Advert not found"))
+}
+
+fn check_days(days: i32) -> Result<(), async_graphql::Error> {
+    if !(1..=MAX_PROMOTION_DAYS).contains(&days) {
+        return Err(async_graphql::Error::new(format!(
+            "A promotion runs for 1 to {} days",
+            MAX_PROMOTION_DAYS
+        )));
+    }
+    Ok(())
+}
+
+/// When a new promotion of `kind` should start: now, or when the one already
+/// running ends, so buying again extends it.
+async fn next_start(
+    db: &impl ConnectionTrait,
+    advert_id: i32,
+    kind: PromotionKind,
+) -> Result<NaiveDateTime, async_graphql::Error> {
+    let now = Utc::now().naive_utc();
+    let latest = Promotion::find()
+        .filter(promotion::Column::AdvertId.eq(advert_id))
+        .filter(promotion::Column::Kind.eq(kind))
+        .filter(promotion::Column::EndsAt.gt(now))
+        .order_by(promotion::Column::EndsAt, Order::Desc)
+        .one(db)
+        .await?;
+
+    Ok(latest.map_or(now, |promotion| promotion.ends_at.max(now)))
+}
+
+#[derive(Default)]
+pub struct PromotionQuery;
+
+#[Object]
+impl PromotionQuery {
+    /// Past, running and upcoming promotions of one of the caller's adverts.
+    async fn advert_promotions(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+    ) -> Result<Vec<promotion::Model>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let caller = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let advert = Advert::find_by_id(advert_id)
+            .one(&my_ctx.db)
+            .await?
+            .filter(|advert| advert.user_id == caller.id || caller.role == Role::Admin)
+            .ok_or_else(|| async_graphql::Error::new("Advert not found"))?;
+
+        Ok(Promotion::find()
+            .filter(promotion::Column::AdvertId.eq(advert.id))
+            .order_by(promotion::Column::StartsAt, Order::Desc)
+            .all(&my_ctx.db)
+            .await?)
+    }
+}
+
+#[derive(Default)]
+pub struct PromotionMutation;
+
+#[Object]
+impl PromotionMutation {
+    /// Pays for promoting one of the caller's active adverts for `days` days.
+    /// A promotion of the same kind that is still running is extended.
+    async fn buy_promotion(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+        kind: PromotionKind,
+        days: i32,
+    ) -> Result<promotion::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+        if user.banned {
+            return Err(async_graphql::Error::new("You are banned"));
+        }
+        check_days(days)?;
+
+        let advert = find_active_advert(my_ctx, advert_id).await?;
+        if advert.user_id != user.id {
+            return Err(async_graphql::Error::new("Advert not found"));
+        }
+
+        // The promotion is only committed once the charge went through, and a
+        // charge is only made for a promotion that could be saved.
+        let txn = my_ctx.db.begin().await?;
+        let starts_at = next_start(&txn, advert.id, kind).await?;
+        let promotion = promotion::ActiveModel {
+            advert_id: Set(advert.id),
+            kind: Set(kind),
+            starts_at: Set(starts_at),
+            ends_at: Set(starts_at + Duration::days(days as i64)),
+            granted_by: Set(None),
+            payment_reference: Set(None),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        let amount = Money(daily_price(kind).0 * Decimal::from(days));
+        let payment_reference = my_ctx
+            .payments
+            .charge(
+                user.id,
+                amount,
+                &my_ctx.base_currency,
+                &format!("{:?} for \"{}\", {} days", kind, advert.title, days),
+            )
+            .await
+            .map_err(async_graphql::Error::new)?;
+
+        let saved = async {
+            let promotion: promotion::Model = promotion::ActiveModel {
+                payment_reference: Set(Some(payment_reference.clone())),
+                ..promotion.into()
+            }
+            .update(&txn)
+            .await?;
+            txn.commit().await?;
+            Ok::<_, DbErr>(promotion)
+        }
+        .await;
+        if let Err(err) = &saved {
+            eprintln!(
+                "Promotion for advert {} was paid ({}) but not saved: {}",
+                advert.id, payment_reference, err
+            );
+        }
+
+        Ok(saved?)
+    }
+
+    /// Promotes any active advert free of charge, from `startsAt` (now by
+    /// default) for `days` days. Admins only.
+    async fn grant_promotion(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        advert_id: i32,
+        kind: PromotionKind,
+        days: i32,
+        starts_at: Option<NaiveDateTime>,
+    ) -> Result<promotion::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let caller = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+        if caller.role != Role::Admin {
+            return Err(async_graphql::Error::new(
+                "You are not authorized to grant promotions",
+            ));
+        }
+        check_days(days)?;
+
+        let advert = find_active_advert(my_ctx, advert_id).await?;
+        let starts_at = match starts_at {
+            Some(starts_at) => starts_at,
+            None => next_start(&my_ctx.db, advert.id, kind).await?,
+        };
+
+        let promotion = promotion::ActiveModel {
+            advert_id: Set(advert.id),
+            kind: Set(kind),
+            starts_at: Set(starts_at),
+            ends_at: Set(starts_at + Duration::days(days as i64)),
+            granted_by: Set(Some(caller.id)),
+            payment_reference: Set(None),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&my_ctx.db)
+        .await?;
+
+        Ok(promotion)
+    }
+}
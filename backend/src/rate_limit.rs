@@ -0,0 +1,279 @@
+use crate::{ClientIp, Context};
+
+use async_graphql::{Error, ErrorExtensions};
+use deadpool_redis::redis::cmd;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Failed logins are counted over this window before the counter resets.
+const FAILURE_WINDOW_SECONDS: u64 = 24 * 60 * 60;
+const MAX_LOCKOUT_SECONDS: u64 = 24 * 60 * 60;
+
+/// Refills the bucket for the time elapsed since the last call, then takes a
+/// token if one is available. Returns `{allowed, milliseconds until a token}`.
+const TOKEN_BUCKET: &str = r#"
+local capacity = tonumber(ARGV[1])
+local refill_ms = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) / refill_ms)
+local allowed = 0
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+else
+    wait = math.ceil((1 - tokens) * refill_ms)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity * refill_ms))
+return {allowed, wait}
+"#;
+
+#[derive(Clone, Copy, Debug)]
+pub enum AuthOperation {
+    Login,
+    Register,
+    ForgotPassword,
+    ResendEmail,
+    TwoFactor,
+    PhoneCode,
+    RevealContact,
+}
+
+impl AuthOperation {
+    fn key(&self) -> &'static str {
+        match self {
+            AuthOperation::Login => "login",
+            AuthOperation::Register => "register",
+            AuthOperation::ForgotPassword => "forgot_password",
+            AuthOperation::ResendEmail => "resend_email",
+            AuthOperation::TwoFactor => "two_factor",
+            AuthOperation::PhoneCode => "phone_code",
+            AuthOperation::RevealContact => "reveal_contact",
+        }
+    }
+}
+
+/// A bucket holding `capacity` attempts that refills completely over `period_seconds`.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub capacity: u32,
+    pub period_seconds: u64,
+}
+
+impl Limit {
+    /// Parses `capacity/seconds`, e.g. `10/300`.
+    fn parse(spec: &str) -> Option<Limit> {
+        let (capacity, period_seconds) = spec.trim().split_once('/')?;
+        let limit = Limit {
+            capacity: capacity.trim().parse().ok()?,
+            period_seconds: period_seconds.trim().parse().ok()?,
+        };
+        (limit.capacity > 0 && limit.period_seconds > 0).then_some(limit)
+    }
+
+    fn from_env(name: &str, default: &str) -> Limit {
+        let spec = dotenvy::var(name).unwrap_or_else(|_| default.to_string());
+        Limit::parse(&spec).unwrap_or_else(|| panic!("{} must look like capacity/seconds", name))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    pub login: Limit,
+    pub register: Limit,
+    pub forgot_password: Limit,
+    pub resend_email: Limit,
+    pub two_factor: Limit,
+    pub phone_code: Limit,
+    pub reveal_contact: Limit,
+    /// Failed logins allowed before the account gets locked.
+    pub lockout_threshold: u32,
+    /// First lockout length; doubles with every further failure.
+    pub lockout_base_seconds: u64,
+    /// Contact reveals a user may make in a day before having to solve a captcha.
+    pub reveal_captcha_threshold: u64,
+}
+
+impl RateLimits {
+    pub fn from_env() -> Self {
+        Self {
+            login: Limit::from_env("RATE_LIMIT_LOGIN", "10/300"),
+            register: Limit::from_env("RATE_LIMIT_REGISTER", "5/3600"),
+            forgot_password: Limit::from_env("RATE_LIMIT_FORGOT_PASSWORD", "3/900"),
+            resend_email: Limit::from_env("RATE_LIMIT_RESEND_EMAIL", "3/900"),
+            two_factor: Limit::from_env("RATE_LIMIT_TWO_FACTOR", "5/300"),
+            phone_code: Limit::from_env("RATE_LIMIT_PHONE_CODE", "3/900"),
+            reveal_contact: Limit::from_env("RATE_LIMIT_REVEAL_CONTACT", "30/3600"),
+            lockout_threshold: dotenvy::var("LOGIN_LOCKOUT_THRESHOLD")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .expect("LOGIN_LOCKOUT_THRESHOLD is not a number"),
+            lockout_base_seconds: dotenvy::var("LOGIN_LOCKOUT_BASE_SECONDS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .expect("LOGIN_LOCKOUT_BASE_SECONDS is not a number"),
+            reveal_captcha_threshold: dotenvy::var("REVEAL_CAPTCHA_THRESHOLD")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .expect("REVEAL_CAPTCHA_THRESHOLD is not a number"),
+        }
+    }
+
+    fn limit(&self, operation: AuthOperation) -> Limit {
+        match operation {
+            AuthOperation::Login => self.login,
+            AuthOperation::Register => self.register,
+            AuthOperation::ForgotPassword => self.forgot_password,
+            AuthOperation::ResendEmail => self.resend_email,
+            AuthOperation::TwoFactor => self.two_factor,
+            AuthOperation::PhoneCode => self.phone_code,
+            AuthOperation::RevealContact => self.reveal_contact,
+        }
+    }
+}
+
+/// The error returned to throttled clients. `index` turns it into an HTTP 429.
+pub fn too_many_requests(message: &str, retry_after: u64) -> Error {
+    Error::new(format!("{}, try again in {} seconds", message, retry_after)).extend_with(
+        |_, extensions| {
+            extensions.set("code", "TOO_MANY_REQUESTS");
+            extensions.set("status", 429);
+            extensions.set("retryAfter", retry_after);
+        },
+    )
+}
+
+fn reject(ctx: &async_graphql::Context<'_>, message: &str, retry_after: u64) -> Error {
+    ctx.append_http_header("Retry-After", retry_after.to_string());
+    too_many_requests(message, retry_after)
+}
+
+fn account_key(account: &str) -> String {
+    account.trim().to_lowercase()
+}
+
+/// Takes a token from the caller's IP bucket and, when given, the account's
+/// bucket for `operation`.
+pub async fn check(
+    ctx: &async_graphql::Context<'_>,
+    operation: AuthOperation,
+    account: Option<&str>,
+) -> Result<(), Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    let limit = my_ctx.rate_limits.limit(operation);
+
+    let mut keys = Vec::new();
+    if let Some(ClientIp(ip)) = ctx.data_opt::<ClientIp>() {
+        keys.push(format!("rate_limit:{}:ip:{}", operation.key(), ip));
+    }
+    if let Some(account) = account {
+        keys.push(format!(
+            "rate_limit:{}:account:{}",
+            operation.key(),
+            account_key(account)
+        ));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let refill_ms = (limit.period_seconds * 1000 / limit.capacity as u64).max(1);
+    let mut conn = my_ctx.redis_pool.get().await?;
+
+    for key in keys {
+        let (allowed, wait_ms): (i64, i64) = cmd("EVAL")
+            .arg(TOKEN_BUCKET)
+            .arg(1)
+            .arg(key)
+            .arg(limit.capacity)
+            .arg(refill_ms)
+            .arg(now)
+            .query_async(&mut conn)
+            .await?;
+
+        if allowed == 0 {
+            let retry_after = (wait_ms.max(0) as u64).div_ceil(1000).max(1);
+            return Err(reject(ctx, "Too many requests", retry_after));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails while the account is locked out after repeated failed logins.
+pub async fn check_login_lock(
+    ctx: &async_graphql::Context<'_>,
+    account: &str,
+) -> Result<(), Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    let mut conn = my_ctx.redis_pool.get().await?;
+
+    let ttl: i64 = cmd("TTL")
+        .arg(format!("login_lock:{}", account_key(account)))
+        .query_async(&mut conn)
+        .await?;
+
+    if ttl > 0 {
+        return Err(reject(ctx, "Too many failed login attempts", ttl as u64));
+    }
+
+    Ok(())
+}
+
+/// Counts a failed login and, past the threshold, locks the account for a
+/// period that doubles with every further failure.
+pub async fn record_login_failure(
+    ctx: &async_graphql::Context<'_>,
+    account: &str,
+) -> Result<(), Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    let limits = &my_ctx.rate_limits;
+    let account = account_key(account);
+    let mut conn = my_ctx.redis_pool.get().await?;
+
+    let failures_key = format!("login_failures:{}", account);
+    let failures: u32 = cmd("INCR")
+        .arg(&failures_key)
+        .query_async(&mut conn)
+        .await?;
+    cmd("EXPIRE")
+        .arg(&failures_key)
+        .arg(FAILURE_WINDOW_SECONDS)
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    if failures >= limits.lockout_threshold {
+        let exponent = (failures - limits.lockout_threshold).min(16);
+        let lockout = (limits.lockout_base_seconds * 2u64.pow(exponent)).min(MAX_LOCKOUT_SECONDS);
+        cmd("SET")
+            .arg(format!("login_lock:{}", account))
+            .arg(1)
+            .arg("EX")
+            .arg(lockout.max(1))
+            .query_async::<()>(&mut conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn clear_login_failures(
+    ctx: &async_graphql::Context<'_>,
+    account: &str,
+) -> Result<(), Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    let account = account_key(account);
+    let mut conn = my_ctx.redis_pool.get().await?;
+
+    cmd("DEL")
+        .arg(format!("login_failures:{}", account))
+        .arg(format!("login_lock:{}", account))
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use sea_orm::{ConnectionTrait, DbBackend, DbErr, Statement};
+
+/// Recomputes a user's rating aggregates from the reviews written about them:
+/// `rating_avg` / `rating_count` from buyers rating them as a seller, and
+/// `buyer_rating_avg` / `buyer_rating_count` from sellers rating them as a
+/// buyer. Hidden reviews don't count. Every resolver reads these columns
+/// instead of aggregating reviews itself, so call this whenever reviews about
+/// a user change.
+pub async fn refresh_user_ratings<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"UPDATE "user" SET
+            rating_avg = COALESCE(stats.seller_avg, 0),
+            rating_count = stats.seller_count,
+            buyer_rating_avg = COALESCE(stats.buyer_avg, 0),
+            buyer_rating_count = stats.buyer_count
+        FROM (
+            SELECT
+                (AVG(r.rating) FILTER (WHERE r.role = 'as_buyer'))::real AS seller_avg,
+                (COUNT(r.id) FILTER (WHERE r.role = 'as_buyer'))::int AS seller_count,
+                (AVG(r.rating) FILTER (WHERE r.role = 'as_seller'))::real AS buyer_avg,
+                (COUNT(r.id) FILTER (WHERE r.role = 'as_seller'))::int AS buyer_count
+            FROM reviews r
+            WHERE r.subject_id = $1 AND NOT r.hidden
+        ) AS stats
+        WHERE "user".id = $1"#,
+        [user_id.into()],
+    ))
+    .await?;
+
+    Ok(())
+}
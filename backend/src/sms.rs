@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use std::{fmt::Debug, sync::Arc};
+
+/// Delivers text messages. Implementations are picked by `SMS_PROVIDER`.
+#[async_trait]
+pub trait SmsProvider: Debug + Send + Sync {
+    /// `to` is an E.164 number.
+    async fn send(&self, to: &str, body: &str) -> Result<(), String>;
+}
+
+/// Development stand-in that prints messages instead of sending them.
+#[derive(Debug)]
+pub struct LogSmsProvider;
+
+#[async_trait]
+impl SmsProvider for LogSmsProvider {
+    async fn send(&self, to: &str, body: &str) -> Result<(), String> {
+        println!("SMS to {}: {}", to, body);
+        Ok(())
+    }
+}
+
+pub fn from_env() -> Arc<dyn SmsProvider> {
+    let provider = dotenvy::var("SMS_PROVIDER").unwrap_or_else(|_| "log".to_string());
+    match provider.as_str() {
+        "log" => Arc::new(LogSmsProvider),
+        other => panic!("Unknown SMS_PROVIDER: {}", other),
+    }
+}
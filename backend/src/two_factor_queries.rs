@@ -0,0 +1,332 @@
+use crate::{
+    rate_limit::{self, AuthOperation},
+    two_factor::{
+        generate_recovery_codes, generate_secret, hash_recovery_code, otpauth_uri,
+        verify_challenge, verify_code, ChallengePurpose,
+    },
+    user_id_from_token,
+    user_queries::{issue_tokens, LoginResponse},
+    Context,
+};
+
+use actix_web::Result;
+use async_graphql::{Object, SimpleObject};
+use chrono::Utc;
+use deadpool_redis::redis::cmd;
+use entity::{
+    recovery_code::{self, Entity as RecoveryCode},
+    user::{self, Entity as User, Role},
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+
+#[derive(SimpleObject)]
+#[graphql(name = "TwoFactorStatus")]
+pub struct TwoFactorStatus {
+    enabled: bool,
+    /// Whether this account may not turn 2FA off.
+    required: bool,
+    recovery_codes_left: u64,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "TwoFactorEnrolment")]
+pub struct TwoFactorEnrolment {
+    secret: String,
+    otpauth_uri: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "TwoFactorConfirmation")]
+pub struct TwoFactorConfirmation {
+    /// Shown once; only their hashes are kept.
+    recovery_codes: Vec<String>,
+    /// Session tokens when enrolment finished a login started with a challenge token.
+    login: Option<LoginResponse>,
+}
+
+fn is_required(my_ctx: &Context, user: &user::Model) -> bool {
+    my_ctx.require_staff_two_factor && user.role != Role::User
+}
+
+async fn find_user(my_ctx: &Context, user_id: i32) -> Result<user::Model, async_graphql::Error> {
+    User::find_by_id(user_id)
+        .one(&my_ctx.db)
+        .await?
+        .ok_or_else(|| async_graphql::Error::new("Wrong token"))
+}
+
+/// Enrolment is done either with a session or, for staff forced into 2FA, with
+/// the challenge token `login` handed out instead of one.
+fn enrolling_user_id(
+    ctx: &async_graphql::Context<'_>,
+    challenge_token: Option<&str>,
+) -> Result<i32, async_graphql::Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+    match challenge_token {
+        Some(token) => verify_challenge(&my_ctx.access_key, token, ChallengePurpose::Enrol),
+        None => user_id_from_token(ctx),
+    }
+}
+
+/// Accepts a current TOTP code, each time step only once, or an unused
+/// recovery code, which is then spent.
+async fn verify_second_factor(
+    my_ctx: &Context,
+    user: &user::Model,
+    code: &str,
+) -> Result<bool, async_graphql::Error> {
+    let Some(secret) = user.totp_secret.as_deref() else {
+        return Ok(false);
+    };
+
+    if let Some(step) = verify_code(secret, code) {
+        let mut conn = my_ctx.redis_pool.get().await?;
+        let fresh: Option<String> = cmd("SET")
+            .arg(format!("totp_used:{}:{}", user.id, step))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(120)
+            .query_async(&mut conn)
+            .await?;
+        return Ok(fresh.is_some());
+    }
+
+    let recovery_code = RecoveryCode::find()
+        .filter(recovery_code::Column::UserId.eq(user.id))
+        .filter(recovery_code::Column::CodeHash.eq(hash_recovery_code(code)))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .one(&my_ctx.db)
+        .await?;
+
+    match recovery_code {
+        Some(recovery_code) => {
+            let spent = RecoveryCode::update_many()
+                .col_expr(
+                    recovery_code::Column::UsedAt,
+                    Expr::value(Utc::now().naive_utc()),
+                )
+                .filter(recovery_code::Column::Id.eq(recovery_code.id))
+                .filter(recovery_code::Column::UsedAt.is_null())
+                .exec(&my_ctx.db)
+                .await?;
+            Ok(spent.rows_affected == 1)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Swaps the user's recovery codes for a fresh set and returns them in clear.
+async fn replace_recovery_codes<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Vec<String>, async_graphql::Error> {
+    RecoveryCode::delete_many()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let codes = generate_recovery_codes();
+    let now = Utc::now().naive_utc();
+    RecoveryCode::insert_many(codes.iter().map(|code| recovery_code::ActiveModel {
+        user_id: Set(user_id),
+        code_hash: Set(hash_recovery_code(code)),
+        used_at: Set(None),
+        created_at: Set(now),
+        ..Default::default()
+    }))
+    .exec(db)
+    .await?;
+
+    Ok(codes)
+}
+
+#[derive(Default)]
+pub struct TwoFactorQuery;
+
+#[Object]
+impl TwoFactorQuery {
+    async fn two_factor_status(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> Result<TwoFactorStatus, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user = find_user(my_ctx, user_id_from_token(ctx)?).await?;
+
+        let recovery_codes_left = RecoveryCode::find()
+            .filter(recovery_code::Column::UserId.eq(user.id))
+            .filter(recovery_code::Column::UsedAt.is_null())
+            .count(&my_ctx.db)
+            .await?;
+
+        Ok(TwoFactorStatus {
+            enabled: user.totp_enabled,
+            required: is_required(my_ctx, &user),
+            recovery_codes_left,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct TwoFactorMutation;
+
+#[Object]
+impl TwoFactorMutation {
+    /// Starts enrolment with a new secret. 2FA stays off until `confirmTwoFactor`.
+    async fn enrol_two_factor(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        challenge_token: Option<String>,
+    ) -> Result<TwoFactorEnrolment, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = enrolling_user_id(ctx, challenge_token.as_deref())?;
+        let user = find_user(my_ctx, user_id).await?;
+
+        if user.totp_enabled {
+            return Err(async_graphql::Error::new(
+                "Two-factor authentication is already on",
+            ));
+        }
+
+        let secret = generate_secret();
+        let otpauth_uri = otpauth_uri(user.email.as_deref().unwrap_or_default(), &secret);
+
+        user::ActiveModel {
+            totp_secret: Set(Some(secret.clone())),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        Ok(TwoFactorEnrolment {
+            secret,
+            otpauth_uri,
+        })
+    }
+
+    /// Turns 2FA on once the authenticator produces a valid code.
+    async fn confirm_two_factor(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        code: String,
+        challenge_token: Option<String>,
+    ) -> Result<TwoFactorConfirmation, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = enrolling_user_id(ctx, challenge_token.as_deref())?;
+        rate_limit::check(ctx, AuthOperation::TwoFactor, Some(&user_id.to_string())).await?;
+        let user = find_user(my_ctx, user_id).await?;
+
+        if user.totp_enabled {
+            return Err(async_graphql::Error::new(
+                "Two-factor authentication is already on",
+            ));
+        }
+        if user.totp_secret.is_none() {
+            return Err(async_graphql::Error::new("Start enrolment first"));
+        }
+        if !verify_second_factor(my_ctx, &user, &code).await? {
+            return Err(async_graphql::Error::new("Wrong code"));
+        }
+
+        let txn = my_ctx.db.begin().await?;
+        let user: user::Model = user::ActiveModel {
+            totp_enabled: Set(true),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&txn)
+        .await?;
+        let recovery_codes = replace_recovery_codes(&txn, user.id).await?;
+        txn.commit().await?;
+
+        let login = match challenge_token {
+            Some(_) => Some(issue_tokens(ctx, user).await?),
+            None => None,
+        };
+
+        Ok(TwoFactorConfirmation {
+            recovery_codes,
+            login,
+        })
+    }
+
+    /// Second step of `login` for accounts with 2FA on. Takes a TOTP or recovery code.
+    async fn verify_two_factor_login(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        challenge_token: String,
+        code: String,
+    ) -> Result<LoginResponse, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = verify_challenge(
+            &my_ctx.access_key,
+            &challenge_token,
+            ChallengePurpose::Login,
+        )?;
+        rate_limit::check(ctx, AuthOperation::TwoFactor, Some(&user_id.to_string())).await?;
+        let user = find_user(my_ctx, user_id).await?;
+
+        if !user.totp_enabled || !verify_second_factor(my_ctx, &user, &code).await? {
+            return Err(async_graphql::Error::new("Wrong code"));
+        }
+
+        issue_tokens(ctx, user).await
+    }
+
+    async fn regenerate_recovery_codes(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        code: String,
+    ) -> Result<Vec<String>, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        rate_limit::check(ctx, AuthOperation::TwoFactor, Some(&user_id.to_string())).await?;
+        let user = find_user(my_ctx, user_id).await?;
+
+        if !user.totp_enabled || !verify_second_factor(my_ctx, &user, &code).await? {
+            return Err(async_graphql::Error::new("Wrong code"));
+        }
+
+        replace_recovery_codes(&my_ctx.db, user.id).await
+    }
+
+    async fn disable_two_factor(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        code: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        rate_limit::check(ctx, AuthOperation::TwoFactor, Some(&user_id.to_string())).await?;
+        let user = find_user(my_ctx, user_id).await?;
+
+        if is_required(my_ctx, &user) {
+            return Err(async_graphql::Error::new(
+                "Two-factor authentication is required for your role",
+            ));
+        }
+        if !user.totp_enabled || !verify_second_factor(my_ctx, &user, &code).await? {
+            return Err(async_graphql::Error::new("Wrong code"));
+        }
+
+        let txn = my_ctx.db.begin().await?;
+        RecoveryCode::delete_many()
+            .filter(recovery_code::Column::UserId.eq(user.id))
+            .exec(&txn)
+            .await?;
+        user::ActiveModel {
+            totp_secret: Set(None),
+            totp_enabled: Set(false),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&txn)
+        .await?;
+        txn.commit().await?;
+
+        Ok(true)
+    }
+}
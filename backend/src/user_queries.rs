@@ -1,40 +1,155 @@
 use serde_json::Value;
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, HashSet},
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::{verify_access_token, Context, Token};
+use crate::{
+    jobs::{enqueue, Task},
+    passwords::Verification,
+    phone::normalize_phone,
+    rate_limit::{self, AuthOperation},
+    reputation::refresh_user_ratings,
+    two_factor::{issue_challenge, ChallengePurpose},
+    user_id_from_token, verify_access_token, Context, Token,
+};
 use actix_web::Result;
 use async_graphql::{Object, SimpleObject};
 use chrono::Utc;
 use deadpool_redis::redis::cmd;
 use entity::{
-    advert::{self, Entity as Advert},
+    advert::{self, AdvertStatus, Entity as Advert},
     chat::{self},
-    favorites::{self},
-    reviews::{self, Entity as Reviews},
-    user::{self, Entity as User},
+    reviews::{self, Entity as Reviews, ReviewRole},
+    user::{self, Entity as User, Role},
 };
 use jwt::SignWithKey;
 use jwt::VerifyWithKey;
 use sea_orm::ColumnTrait;
 use sea_orm::{ActiveModelTrait, EntityTrait, QueryFilter, Set};
 
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
-};
+use rand::{distributions::Alphanumeric, Rng};
 use serde_json::json;
 const ACCESS_EXPIRATION: usize = 100;
 const REFRESH_EXPIRATION: usize = 180;
+/// Hours a requested email change can be confirmed or cancelled.
+const EMAIL_CHANGE_EXPIRATION: usize = 24;
+const RESET_EXPIRATION_SECONDS: usize = 60 * 60;
 
 #[derive(SimpleObject)]
 #[graphql(name = "LoginResponse")]
 pub struct LoginResponse {
-    refresh_token: String,
-    access_token: String,
+    refresh_token: Option<String>,
+    access_token: Option<String>,
     user_id: i32,
+    /// Returned instead of the tokens when a second factor is needed: pass it to
+    /// `verifyTwoFactorLogin`, or to `enrolTwoFactor` when `twoFactorSetupRequired`.
+    challenge_token: Option<String>,
+    two_factor_setup_required: bool,
+}
+
+impl LoginResponse {
+    fn challenge(user_id: i32, challenge_token: String, two_factor_setup_required: bool) -> Self {
+        Self {
+            refresh_token: None,
+            access_token: None,
+            user_id,
+            challenge_token: Some(challenge_token),
+            two_factor_setup_required,
+        }
+    }
+}
+
+/// Finishes a login once the user has proven who they are: asks for the second
+/// factor when one is set up (or required), otherwise hands out the tokens.
+pub async fn complete_login(
+    ctx: &async_graphql::Context<'_>,
+    user: user::Model,
+) -> Result<LoginResponse, async_graphql::Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+
+    if user.totp_enabled {
+        return Ok(LoginResponse::challenge(
+            user.id,
+            issue_challenge(&my_ctx.access_key, user.id, ChallengePurpose::Login)?,
+            false,
+        ));
+    }
+
+    if my_ctx.require_staff_two_factor && user.role != Role::User {
+        return Ok(LoginResponse::challenge(
+            user.id,
+            issue_challenge(&my_ctx.access_key, user.id, ChallengePurpose::Enrol)?,
+            true,
+        ));
+    }
+
+    issue_tokens(ctx, user).await
+}
+
+/// Signs a fresh access/refresh token pair for `user`, stores the refresh token
+/// and sets the session cookies.
+pub async fn issue_tokens(
+    ctx: &async_graphql::Context<'_>,
+    user: user::Model,
+) -> Result<LoginResponse, async_graphql::Error> {
+    let my_ctx = ctx.data::<Context>().unwrap();
+
+    let mut refresh_claims: BTreeMap<&str, Value> = BTreeMap::new();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize;
+    let expiration = now + (ACCESS_EXPIRATION * 60);
+    let expiration2 = now + (REFRESH_EXPIRATION * 60);
+
+    let id = user.id.to_string();
+    // Accounts created through an identity provider may have no email.
+    let email = user.email.unwrap_or_default();
+
+    refresh_claims.insert("sub", json!("someone"));
+    refresh_claims.insert("id", json!(id));
+    refresh_claims.insert("email", json!(email));
+    refresh_claims.insert("exp", json!(expiration2));
+
+    let refresh_token = match refresh_claims.clone().sign_with_key(&my_ctx.refresh_key) {
+        Ok(token) => token,
+        Err(err) => return Err(async_graphql::Error::new(err.to_string())),
+    };
+
+    let mut access_claims: BTreeMap<&str, Value> = BTreeMap::new();
+    access_claims.insert("sub", json!("someone"));
+    access_claims.insert("id", json!(id));
+    access_claims.insert("email", json!(email));
+    access_claims.insert("exp", json!(expiration));
+    let access_token = match access_claims.sign_with_key(&my_ctx.access_key) {
+        Ok(token) => token,
+        Err(err) => return Err(async_graphql::Error::new(err.to_string())),
+    };
+
+    let mut conn = my_ctx.redis_pool.get().await.unwrap();
+    cmd("SET")
+        .arg(&[
+            user.id.to_string(),
+            refresh_token.clone(),
+            "EX".to_string(),
+            expiration2.to_string(),
+        ])
+        .query_async::<()>(&mut conn)
+        .await
+        .unwrap();
+
+    ctx.append_http_header("Set-Cookie", format!("refreshToken={}", refresh_token));
+    ctx.append_http_header("Set-Cookie", format!("accessToken={}", access_token));
+    ctx.append_http_header("Set-Cookie", format!("userId={}", user.id));
+
+    Ok(LoginResponse {
+        refresh_token: Some(refresh_token),
+        access_token: Some(access_token),
+        user_id: user.id,
+        challenge_token: None,
+        two_factor_setup_required: false,
+    })
 }
 
 #[derive(Default)]
@@ -54,107 +169,45 @@ impl UserQuery {
             .await?
             .ok_or_else(|| async_graphql::Error::new("No user found"))?;
 
-        let adverts_with_review = Advert::find()
-            .filter(advert::Column::UserId.eq(user.id))
-            .find_also_related(Reviews)
+        // Drafts and archived adverts are only listed for their owner.
+        let is_owner = user_id_from_token(ctx).ok() == Some(user.id);
+        let mut adverts = Advert::find().filter(advert::Column::UserId.eq(user.id));
+        if !is_owner {
+            adverts = adverts.filter(
+                advert::Column::Status.is_not_in([AdvertStatus::Draft, AdvertStatus::Archived]),
+            );
+        }
+        let adverts = adverts.all(&my_ctx.db).await?;
+
+        let buyer_reviewed: HashSet<i32> = Reviews::find()
+            .filter(reviews::Column::SubjectId.eq(user.id))
+            .filter(reviews::Column::Role.eq(ReviewRole::AsBuyer))
+            .filter(reviews::Column::Hidden.eq(false))
             .all(&my_ctx.db)
-            .await?;
+            .await?
+            .into_iter()
+            .map(|review| review.advert_id)
+            .collect();
 
-        let reviewer_ids: HashSet<i32> = adverts_with_review
+        let adverts_with_reviews: Vec<advert::Model> = adverts
             .iter()
-            .filter_map(|(_, review_opt)| review_opt.as_ref())
-            .map(|review| review.user_id)
+            .filter(|advert| buyer_reviewed.contains(&advert.id))
+            .cloned()
             .collect();
 
-        let reviewers = User::find()
-            .filter(user::Column::Id.is_in(reviewer_ids.clone()))
-            .all(&my_ctx.db)
-            .await?;
-
-        let reviewer_map: HashMap<i32, user::Model> =
-            reviewers.into_iter().map(|user| (user.id, user)).collect();
-
-        let mut favorite_advert_ids = HashSet::new();
-
-        if let Some(token) = ctx.data_opt::<Token>().map(|token| token.0.clone()) {
-            if let Ok(claims) = verify_access_token(token, &my_ctx.access_key) {
-                let current_user_id: i32 =
-                    if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
-                        id_str.parse().map_err(|_| {
-                            async_graphql::Error::new(
-                                "Invalid user ID in token: failed to parse string",
-                            )
-                        })?
-                    } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
-                        id_num as i32
-                    } else {
-                        return Err(async_graphql::Error::new(
-                            "Invalid user ID in token: missing id",
-                        ));
-                    };
-
-                let favorite_adverts = favorites::Entity::find()
-                    .filter(favorites::Column::UserId.eq(current_user_id))
-                    .all(&my_ctx.db)
-                    .await?;
-
-                favorite_advert_ids = favorite_adverts
-                    .into_iter()
-                    .map(|fav| fav.advert_id)
-                    .collect();
-            }
-        }
-
-        let mut user_rating: f32 = 0.0;
-
-        let mut adverts = Vec::new();
-        let mut adverts_with_reviews = Vec::new();
-
-        for (mut advert, mut review_opt) in adverts_with_review {
-            if let Some(review) = review_opt.as_mut() {
-                user_rating += review.rating as f32;
-                let reviewer = reviewer_map
-                    .get(&review.user_id)
-                    .cloned()
-                    .unwrap_or_else(|| user::Model::default());
-                review.user = reviewer;
-                advert.review = Some(review.clone());
-
-                adverts_with_reviews.push(advert.clone());
-            }
-
-            advert.is_favorited = favorite_advert_ids.contains(&advert.id);
-            adverts.push(advert);
-        }
-
-        let reviews_written_by_user = Reviews::find()
+        let reviewed_adverts: Vec<advert::Model> = Reviews::find()
             .filter(reviews::Column::UserId.eq(user.id))
             .find_also_related(Advert)
             .all(&my_ctx.db)
-            .await?;
-
-        let mut reviewed_adverts = Vec::new();
-
-        for (mut review, advert_opt) in reviews_written_by_user {
-            if let Some(mut advert) = advert_opt {
-                advert.is_favorited = favorite_advert_ids.contains(&advert.id);
-
-                review.user = user.clone();
-                advert.review = Some(review);
-
-                reviewed_adverts.push(advert);
-            }
-        }
+            .await?
+            .into_iter()
+            .filter_map(|(_, advert_opt)| advert_opt)
+            .collect();
 
         let mut user = user;
         user.adverts = adverts;
         user.adverts_with_reviews = adverts_with_reviews;
         user.reviewed_adverts = reviewed_adverts;
-        user.rating = if user_rating > 0.0 && !user.adverts_with_reviews.is_empty() {
-            user_rating / (user.adverts_with_reviews.len() as f32)
-        } else {
-            0.0
-        };
 
         Ok(user)
     }
@@ -194,108 +247,41 @@ impl UserQuery {
             .await?
             .ok_or_else(|| async_graphql::Error::new("No user found."))?;
 
-        let adverts_with_review = Advert::find()
+        let adverts = Advert::find()
             .filter(advert::Column::UserId.eq(user.id))
-            .find_also_related(Reviews)
             .all(&my_ctx.db)
             .await?;
 
-        let reviewer_ids: HashSet<i32> = adverts_with_review
-            .iter()
-            .filter_map(|(_, review_opt)| review_opt.as_ref())
-            .map(|review| review.user_id)
-            .collect();
-
-        let reviewers = User::find()
-            .filter(user::Column::Id.is_in(reviewer_ids.clone()))
+        let buyer_reviewed: HashSet<i32> = Reviews::find()
+            .filter(reviews::Column::SubjectId.eq(user.id))
+            .filter(reviews::Column::Role.eq(ReviewRole::AsBuyer))
+            .filter(reviews::Column::Hidden.eq(false))
             .all(&my_ctx.db)
-            .await?;
-
-        let reviewer_map: HashMap<i32, user::Model> =
-            reviewers.into_iter().map(|user| (user.id, user)).collect();
-
-        let mut favorite_advert_ids = HashSet::new();
-
-        if let Some(token) = ctx.data_opt::<Token>().map(|token| token.0.clone()) {
-            if let Ok(claims) = verify_access_token(token, &my_ctx.access_key) {
-                let current_user_id: i32 =
-                    if let Some(id_str) = claims.get("id").and_then(|v| v.as_str()) {
-                        id_str.parse().map_err(|_| {
-                            async_graphql::Error::new(
-                                "Invalid user ID in token: failed to parse string",
-                            )
-                        })?
-                    } else if let Some(id_num) = claims.get("id").and_then(|v| v.as_i64()) {
-                        id_num as i32
-                    } else {
-                        return Err(async_graphql::Error::new(
-                            "Invalid user ID in token: missing id",
-                        ));
-                    };
-
-                let favorite_adverts = favorites::Entity::find()
-                    .filter(favorites::Column::UserId.eq(current_user_id))
-                    .all(&my_ctx.db)
-                    .await?;
-
-                favorite_advert_ids = favorite_adverts
-                    .into_iter()
-                    .map(|fav| fav.advert_id)
-                    .collect();
-            }
-        }
-
-        let mut user_rating: f32 = 0.0;
-
-        let mut adverts = Vec::new();
-        let mut adverts_with_reviews = Vec::new();
-
-        for (mut advert, mut review_opt) in adverts_with_review {
-            if let Some(review) = review_opt.as_mut() {
-                user_rating += review.rating as f32;
-                let reviewer = reviewer_map
-                    .get(&review.user_id)
-                    .cloned()
-                    .unwrap_or_else(|| user::Model::default());
-                review.user = reviewer;
-                advert.review = Some(review.clone());
-
-                adverts_with_reviews.push(advert.clone());
-            }
+            .await?
+            .into_iter()
+            .map(|review| review.advert_id)
+            .collect();
 
-            advert.is_favorited = favorite_advert_ids.contains(&advert.id);
-            adverts.push(advert);
-        }
+        let adverts_with_reviews: Vec<advert::Model> = adverts
+            .iter()
+            .filter(|advert| buyer_reviewed.contains(&advert.id))
+            .cloned()
+            .collect();
 
-        let reviews_written_by_user = Reviews::find()
+        let reviewed_adverts: Vec<advert::Model> = Reviews::find()
             .filter(reviews::Column::UserId.eq(user.id))
             .find_also_related(Advert)
             .all(&my_ctx.db)
-            .await?;
-
-        let mut reviewed_adverts = Vec::new();
-
-        for (mut review, advert_opt) in reviews_written_by_user {
-            if let Some(mut advert) = advert_opt {
-                advert.is_favorited = favorite_advert_ids.contains(&advert.id);
-
-                review.user = user.clone();
-                advert.review = Some(review);
-
-                reviewed_adverts.push(advert);
-            }
-        }
+            .await?
+            .into_iter()
+            .filter_map(|(_, advert_opt)| advert_opt)
+            .collect();
 
         // Update the user model with the fetched data
         let mut user = user;
         user.adverts = adverts;
         user.adverts_with_reviews = adverts_with_reviews;
         user.reviewed_adverts = reviewed_adverts;
-        user.rating = if user_rating > 0.0 && !user.adverts_with_reviews.is_empty() {
-            user_rating / (user.adverts_with_reviews.len() as f32)
-        } else {
-            0.0
-        };
 
         Ok(user)
     }
@@ -320,6 +306,18 @@ impl UserQuery {
         if exp <= now {
             return Ok(false);
         }
+        // Used links are removed from Redis by resetPassword.
+        let Some(token_id) = claims.get("jti").and_then(|v| v.as_str()) else {
+            return Ok(false);
+        };
+        let mut conn = my_ctx.redis_pool.get().await?;
+        let unused: bool = cmd("EXISTS")
+            .arg(format!("password_reset:{}", token_id))
+            .query_async(&mut conn)
+            .await?;
+        if !unused {
+            return Ok(false);
+        }
         let email = claims
             .get("email")
             .and_then(|v| v.as_str())
@@ -341,24 +339,19 @@ impl UserMutation {
         &self,
         ctx: &async_graphql::Context<'_>,
         #[graphql(validator(email))] email: String,
-        #[graphql(validator(min_length = 8))] password: String,
+        password: String,
         surname: Option<String>,
         name: Option<String>,
         company_name: Option<String>,
     ) -> Result<user::Model, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-
-        let password_hash = match argon2.hash_password(password.as_bytes(), &salt) {
-            Ok(hash) => hash.to_string(),
-            Err(err) => {
-                return Err(async_graphql::Error::new(err.to_string()));
-            }
-        };
+        rate_limit::check(ctx, AuthOperation::Register, Some(&email)).await?;
+        my_ctx
+            .password_policy
+            .check(&password, Some(&email))
+            .await?;
 
-        let parsed_hash = PasswordHash::new(&password_hash)
-            .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+        let password_hash = my_ctx.passwords.hash(password).await?;
 
         let naive_date_time = Utc::now().naive_utc();
 
@@ -373,7 +366,7 @@ impl UserMutation {
             surname: Set(surname),
             company_name: Set(company_name),
             email: Set(Some(email.clone())),
-            password_hash: Set(Some(parsed_hash.to_string())),
+            password_hash: Set(Some(password_hash)),
             created_at: Set(naive_date_time),
             updated_at: Set(naive_date_time),
             ..Default::default()
@@ -386,55 +379,22 @@ impl UserMutation {
             .unwrap()
             .as_secs() as usize;
         let expiration = now + (ACCESS_EXPIRATION * 60);
-        let mut email_verif: BTreeMap<&str, Value> = BTreeMap::new();
-        email_verif.insert("sub", json!("someone"));
-        email_verif.insert(
-            "email",
-            json!(user.email.as_deref().unwrap_or("default_email")),
-        );
-        email_verif.insert("exp", json!(expiration));
-        let verification = match email_verif.sign_with_key(&my_ctx.email_key) {
-            Ok(token) => token,
-            Err(err) => return Err(async_graphql::Error::new(err.to_string())),
-        };
-
-        println!("{:?}", verification);
-
         let recipient = user
             .name
             .as_deref()
             .or(user.company_name.as_deref())
             .unwrap_or("User");
 
-        let payload = json!({
-            "from": { "email": "info@ad-ee.tech", "name": "Adee" },
-            "to": [{ "email": user.email }],
-            "subject": "You are awesome!",
-            "text": format!(
-                "Hi {}, here is your verification link: https://ad-ee.tech/verify_email/{}",
-                recipient, verification
-            ),
-            "html": format!(
-                "<p>Hi {},</p><p>Here is your verification link:</p><p><a href=\"https://ad-ee.tech/verify_email/{}\">Verify Email</a></p>",
-                recipient, verification
-            )
-        });
-
-        let client = reqwest::Client::new();
-        let resp = client
-            .post("https://api.mailersend.com/v1/email")
-            .bearer_auth(&my_ctx.mailersend_token)
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| async_graphql::Error::new(format!("Network error: {}", e)))?;
-
-        if resp.status().is_success() {
-            println!("Email sent successfully!");
-        } else {
-            println!("Failed to send email: {}", resp.text().await?);
-        }
-        return Ok(user);
+        enqueue(
+            &my_ctx.db,
+            Task::SendVerificationEmail {
+                to: user.email.clone().unwrap_or_default(),
+                recipient: recipient.to_string(),
+                exp: expiration,
+            },
+        )
+        .await?;
+        Ok(user)
     }
 
     async fn login(
@@ -444,91 +404,62 @@ impl UserMutation {
         password: String,
     ) -> Result<LoginResponse, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
+        rate_limit::check(ctx, AuthOperation::Login, Some(&email)).await?;
+        rate_limit::check_login_lock(ctx, &email).await?;
 
-        let user: Option<user::Model> = User::find_by_email(email).one(&my_ctx.db).await?;
+        let user: Option<user::Model> = User::find_by_email(email.clone()).one(&my_ctx.db).await?;
 
         let user = match user {
             Some(user) => user,
             None => {
+                rate_limit::record_login_failure(ctx, &email).await?;
                 return Err(async_graphql::Error::new(
                     "Wrong email or password".to_string(),
-                ))
+                ));
             }
         };
 
-        let argon2 = Argon2::default();
-
-        let response = if let Some(password_hash) = &user.password_hash {
-            argon2
-                .verify_password(
-                    password.as_bytes(),
-                    &PasswordHash::new(password_hash).unwrap(),
-                )
-                .is_ok()
-        } else {
-            false
-        };
-
-        if !response {
-            return Err(async_graphql::Error::new(
-                "Wrong email or password".to_string(),
-            ));
-        }
-
-        let mut refresh_claims: BTreeMap<&str, Value> = BTreeMap::new();
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as usize;
-        let expiration = now + (ACCESS_EXPIRATION * 60);
-        let expiration2 = now + (REFRESH_EXPIRATION * 60);
-
-        let id = user.id.to_string();
-        let email = user.email.expect("Email should not be None").to_string();
-
-        refresh_claims.insert("sub", json!("someone"));
-        refresh_claims.insert("id", json!(id));
-        refresh_claims.insert("email", json!(email));
-        refresh_claims.insert("exp", json!(expiration2));
-
-        let refresh_token = match refresh_claims.clone().sign_with_key(&my_ctx.refresh_key) {
-            Ok(token) => token,
-            Err(err) => return Err(async_graphql::Error::new(err.to_string())),
+        let verification = match user.password_hash.clone() {
+            Some(password_hash) => {
+                my_ctx
+                    .passwords
+                    .verify(password.clone(), password_hash)
+                    .await?
+            }
+            None => Verification::Invalid,
         };
 
-        let mut access_claims: BTreeMap<&str, Value> = BTreeMap::new();
-        access_claims.insert("sub", json!("someone"));
-        access_claims.insert("id", json!(id));
-        access_claims.insert("email", json!(email));
-        access_claims.insert("exp", json!(expiration));
-        let access_token = match access_claims.sign_with_key(&my_ctx.access_key) {
-            Ok(token) => token,
-            Err(err) => return Err(async_graphql::Error::new(err.to_string())),
+        let user = match verification {
+            Verification::Valid => user,
+            Verification::ValidNeedsRehash => {
+                let password_hash = my_ctx.passwords.hash(password).await?;
+                user::ActiveModel {
+                    password_hash: Set(Some(password_hash)),
+                    ..user.into()
+                }
+                .update(&my_ctx.db)
+                .await?
+            }
+            Verification::Corrupt => {
+                eprintln!("Unreadable password hash for user {}", user.id);
+                return Err(async_graphql::Error::new(
+                    "Your password can't be checked, please reset it".to_string(),
+                ));
+            }
+            Verification::Invalid => {
+                rate_limit::record_login_failure(ctx, &email).await?;
+                return Err(async_graphql::Error::new(
+                    "Wrong email or password".to_string(),
+                ));
+            }
         };
 
-        let mut conn = my_ctx.redis_pool.get().await.unwrap();
-        cmd("SET")
-            .arg(&[
-                user.id.to_string(),
-                refresh_token.clone(),
-                "EX".to_string(),
-                expiration2.to_string(),
-            ])
-            .query_async::<()>(&mut conn)
-            .await
-            .unwrap();
-
-        ctx.append_http_header("Set-Cookie", format!("refreshToken={}", refresh_token));
-        ctx.append_http_header("Set-Cookie", format!("accessToken={}", access_token));
-        ctx.append_http_header("Set-Cookie", format!("userId={}", user.id));
+        rate_limit::clear_login_failures(ctx, &email).await?;
 
-        Ok(LoginResponse {
-            refresh_token,
-            access_token,
-            user_id: user.id,
-        })
+        complete_login(ctx, user).await
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn edit(
         &self,
         ctx: &async_graphql::Context<'_>,
@@ -575,23 +506,16 @@ impl UserMutation {
             None => return Err(async_graphql::Error::new("Wrong token".to_string())),
         };
 
-        let argon2 = Argon2::default();
-
-        let response = if let Some(password_hash) = &user.password_hash {
-            argon2
-                .verify_password(
-                    password.as_bytes(),
-                    &PasswordHash::new(password_hash).unwrap(),
-                )
-                .is_ok()
-        } else {
-            false
-        };
+        let response = my_ctx
+            .passwords
+            .confirm(&password, user.password_hash.as_deref())
+            .await?;
 
         if !response {
             return Err(async_graphql::Error::new("Wrong password".to_string()));
         }
 
+        let current_phone = user.phone.clone();
         let mut active_user = user::ActiveModel {
             id: Set(user.id),
             ..user.into()
@@ -607,7 +531,14 @@ impl UserMutation {
             active_user.company_name = Set(Some(company_name));
         }
         if let Some(phone) = phone {
-            active_user.phone = Set(Some(phone));
+            let phone = match phone.trim() {
+                "" => None,
+                phone => Some(normalize_phone(phone, &my_ctx.phone_country_code)?),
+            };
+            if phone != current_phone {
+                active_user.phone = Set(phone);
+                active_user.phone_verified = Set(false);
+            }
         }
         if let Some(avatar_url) = avatar_url {
             active_user.avatar_url = Set(Some(avatar_url));
@@ -660,18 +591,18 @@ impl UserMutation {
         let user: Option<user::Model> = User::find_by_id(id).one(&my_ctx.db).await?;
 
         let user = match user {
-            Some(user) => user,
-            None => return Err(async_graphql::Error::new("Wrong token".to_string())),
+            Some(user) if user.deleted_at.is_none() => user,
+            _ => return Err(async_graphql::Error::new("Wrong token".to_string())),
         };
 
         let mut conn = my_ctx.redis_pool.get().await.unwrap();
-        let token: String = cmd("GET")
+        // Gone once the user logs out everywhere or the account is deleted.
+        let token: Option<String> = cmd("GET")
             .arg(&[user.id.to_string()])
             .query_async(&mut conn)
-            .await
-            .unwrap();
+            .await?;
 
-        if refresh_token != token {
+        if token.as_deref() != Some(refresh_token.as_str()) {
             return Err(async_graphql::Error::new("Wrong token".to_string()));
         }
 
@@ -684,7 +615,7 @@ impl UserMutation {
         let expiration2 = now + (REFRESH_EXPIRATION * 60); // 60 minutes from now
 
         let id = user.id.to_string();
-        let email = user.email.expect("Email should not be None").to_string();
+        let email = user.email.unwrap_or_default();
 
         refresh_claims.insert("sub", json!("someone"));
         refresh_claims.insert("id", json!(id));
@@ -717,11 +648,13 @@ impl UserMutation {
             .await
             .unwrap();
 
-        return Ok(LoginResponse {
-            refresh_token,
-            access_token,
+        Ok(LoginResponse {
+            refresh_token: Some(refresh_token),
+            access_token: Some(access_token),
             user_id: user.id,
-        });
+            challenge_token: None,
+            two_factor_setup_required: false,
+        })
     }
 
     async fn verify_email(
@@ -780,7 +713,7 @@ impl UserMutation {
         .update(&my_ctx.db)
         .await?;
 
-        return Ok("Email verified".to_string());
+        Ok("Email verified".to_string())
     }
 
     async fn resend_email(
@@ -814,6 +747,8 @@ impl UserMutation {
                 "Invalid user ID in token: missing id",
             ));
         };
+        rate_limit::check(ctx, AuthOperation::ResendEmail, Some(&id.to_string())).await?;
+
         let user: Option<user::Model> = User::find_by_id(id).one(&my_ctx.db).await?;
 
         let user = match user {
@@ -832,56 +767,23 @@ impl UserMutation {
             .unwrap()
             .as_secs() as usize;
         let expiration = now + (ACCESS_EXPIRATION * 60); // 1 minutes from now
-        let mut email_verif: BTreeMap<&str, Value> = BTreeMap::new();
-        email_verif.insert("sub", json!("someone"));
-        email_verif.insert(
-            "email",
-            json!(user.email.as_deref().unwrap_or("default_email")),
-        );
-        email_verif.insert("exp", json!(expiration));
-        let verification = match email_verif.sign_with_key(&my_ctx.email_key) {
-            Ok(token) => token,
-            Err(err) => return Err(async_graphql::Error::new(err.to_string())),
-        };
-
-        println!("{:?}", verification);
-
         let recipient = user
             .name
             .as_deref()
             .or(user.company_name.as_deref())
             .unwrap_or("User");
 
-        let payload = json!({
-            "from": { "email": "info@ad-ee.tech", "name": "Adee" },
-            "to": [{ "email": user.email }],
-            "subject": "You are awesome!",
-            "text": format!(
-                "Hi {}, here is your verification link: https://ad-ee.tech/verify_email/{}",
-                recipient, verification
-            ),
-            "html": format!(
-                "<p>Hi {},</p><p>Here is your verification link:</p><p><a href=\"https://ad-ee.tech/verify_email/{}\">Verify Email</a></p>",
-                recipient, verification
-            )
-        });
-
-        let client = reqwest::Client::new();
-        let resp = client
-            .post("https://api.mailersend.com/v1/email")
-            .bearer_auth(&my_ctx.mailersend_token)
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| async_graphql::Error::new(format!("Network error: {}", e)))?;
-
-        if resp.status().is_success() {
-            println!("Email sent successfully!");
-        } else {
-            println!("Failed to send email: {}", resp.text().await?);
-        }
+        enqueue(
+            &my_ctx.db,
+            Task::SendVerificationEmail {
+                to: user.email.clone().unwrap_or_default(),
+                recipient: recipient.to_string(),
+                exp: expiration,
+            },
+        )
+        .await?;
 
-        return Ok("Email sent".to_string());
+        Ok("Email sent".to_string())
     }
 
     async fn ban_user(
@@ -920,9 +822,7 @@ impl UserMutation {
 
         match caller {
             Some(u) => {
-                if u.role == user::Role::Admin {
-                    ()
-                } else {
+                if u.role != user::Role::Admin {
                     return Err(async_graphql::Error::new(
                         "You are not authorized to ban users".to_string(),
                     ));
@@ -944,6 +844,14 @@ impl UserMutation {
 
         let updated_user: user::Model = active_user.update(&my_ctx.db).await?;
 
+        let buyer_ids: HashSet<i32> = advert::Entity::find()
+            .filter(advert::Column::UserId.eq(user_id))
+            .all(&my_ctx.db)
+            .await?
+            .into_iter()
+            .filter_map(|advert| advert.sold_to)
+            .collect();
+
         let deletion_result = advert::Entity::delete_many()
             .filter(advert::Column::UserId.eq(user_id))
             .exec(&my_ctx.db)
@@ -951,6 +859,11 @@ impl UserMutation {
 
         println!("Deleted {:?} adverts", deletion_result);
 
+        refresh_user_ratings(&my_ctx.db, user_id).await?;
+        for buyer_id in buyer_ids {
+            refresh_user_ratings(&my_ctx.db, buyer_id).await?;
+        }
+
         let chat_delete_result = chat::Entity::delete_many()
             .filter(chat::Column::ParticipantId.eq(user_id))
             .exec(&my_ctx.db)
@@ -958,7 +871,7 @@ impl UserMutation {
 
         println!("Deleted {:?} chats", chat_delete_result);
 
-        return Ok(updated_user);
+        Ok(updated_user)
     }
 
     async fn forgot_password(
@@ -967,6 +880,8 @@ impl UserMutation {
         email: String,
     ) -> Result<String, async_graphql::Error> {
         let my_ctx = ctx.data::<Context>().unwrap();
+        rate_limit::check(ctx, AuthOperation::ForgotPassword, Some(&email)).await?;
+
         let user_opt = User::find_by_email(email.clone()).one(&my_ctx.db).await?;
         let user =
             user_opt.ok_or_else(|| async_graphql::Error::new("No user found with that email"))?;
@@ -975,31 +890,28 @@ impl UserMutation {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs() as usize;
-        let exp = now + 60 * 60;
-        let mut claims = BTreeMap::new();
-        claims.insert("sub", json!("reset"));
-        claims.insert("email", json!(email));
-        claims.insert("exp", json!(exp));
-        let token = claims
-            .sign_with_key(&my_ctx.email_key)
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        let exp = now + RESET_EXPIRATION_SECONDS;
+        // The token id is kept in Redis until the link is used, so it works once.
+        let token_id = new_change_id();
 
-        let reset_link = format!("https://ad-ee.tech/reset/{}", token);
-        let payload = json!({
-            "from": { "email": "info@ad-ee.tech", "name": "Adee" },
-            "to": [{ "email": user.email.clone().unwrap() }],
-            "subject": "Reset your password",
-            "text": format!("Click here to reset your password: {}", reset_link),
-            "html": format!("<p>Click <a href=\"{}\">here</a> to reset your password.</p>", reset_link)
-        });
-        let client = reqwest::Client::new();
-        client
-            .post("https://api.mailersend.com/v1/email")
-            .bearer_auth(&my_ctx.mailersend_token)
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| async_graphql::Error::new(format!("Email send error: {}", e)))?;
+        let mut conn = my_ctx.redis_pool.get().await?;
+        cmd("SET")
+            .arg(format!("password_reset:{}", token_id))
+            .arg(user.id)
+            .arg("EX")
+            .arg(RESET_EXPIRATION_SECONDS)
+            .query_async::<()>(&mut conn)
+            .await?;
+
+        enqueue(
+            &my_ctx.db,
+            Task::SendPasswordReset {
+                to: email,
+                token_id,
+                exp,
+            },
+        )
+        .await?;
 
         Ok("Password reset email sent".to_string())
     }
@@ -1034,21 +946,276 @@ impl UserMutation {
             .await?;
         let user = user_opt.ok_or_else(|| async_graphql::Error::new("User not found"))?;
 
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let hash = argon2
-            .hash_password(new_password.as_bytes(), &salt)
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
-        let hash_string = hash.to_string();
-        let parsed = PasswordHash::new(&hash_string)
-            .map_err(|e| async_graphql::Error::new(e.to_string()))?;
+        my_ctx
+            .password_policy
+            .check(&new_password, user.email.as_deref())
+            .await?;
+
+        let token_id = claims
+            .get("jti")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| async_graphql::Error::new("Invalid reset token"))?;
+        let mut conn = my_ctx.redis_pool.get().await?;
+        let reset_for: Option<i32> = cmd("GETDEL")
+            .arg(format!("password_reset:{}", token_id))
+            .query_async(&mut conn)
+            .await?;
+        if reset_for != Some(user.id) {
+            return Err(async_graphql::Error::new(
+                "This reset link has already been used",
+            ));
+        }
+
+        let password_hash = my_ctx.passwords.hash(new_password).await?;
 
         let now_naive = Utc::now().naive_utc();
         let mut active: user::ActiveModel = user.into();
-        active.password_hash = Set(Some(parsed.to_string()));
+        active.password_hash = Set(Some(password_hash));
         active.updated_at = Set(now_naive);
         active.update(&my_ctx.db).await?;
 
         Ok("Password has been reset successfully".to_string())
     }
+
+    async fn change_password(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        old_password: String,
+        new_password: String,
+    ) -> Result<String, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        rate_limit::check(ctx, AuthOperation::Login, Some(&user_id.to_string())).await?;
+
+        let user = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Wrong token"))?;
+
+        if user.password_hash.is_none() {
+            return Err(async_graphql::Error::new(
+                "Your account has no password yet, set one with a password reset",
+            ));
+        }
+        let old_password_ok = my_ctx
+            .passwords
+            .confirm(&old_password, user.password_hash.as_deref())
+            .await?;
+        if !old_password_ok {
+            return Err(async_graphql::Error::new("Wrong password"));
+        }
+        if old_password == new_password {
+            return Err(async_graphql::Error::new(
+                "New password must differ from the old one",
+            ));
+        }
+
+        my_ctx
+            .password_policy
+            .check(&new_password, user.email.as_deref())
+            .await?;
+
+        let hash = my_ctx.passwords.hash(new_password).await?;
+
+        user::ActiveModel {
+            password_hash: Set(Some(hash)),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        Ok("Password changed".to_string())
+    }
+
+    /// Starts moving the account to `new_email`: a confirmation link goes to
+    /// the new address and a notice with a cancel link to the current one.
+    async fn request_email_change(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        #[graphql(validator(email))] new_email: String,
+        password: String,
+    ) -> Result<String, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let user_id = user_id_from_token(ctx)?;
+        rate_limit::check(ctx, AuthOperation::ResendEmail, Some(&user_id.to_string())).await?;
+
+        let user = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("Wrong token"))?;
+
+        let password_ok = my_ctx
+            .passwords
+            .confirm(&password, user.password_hash.as_deref())
+            .await?;
+        if !password_ok {
+            return Err(async_graphql::Error::new("Wrong password"));
+        }
+
+        let new_email = new_email.trim().to_lowercase();
+        if user.email.as_deref() == Some(new_email.as_str()) {
+            return Err(async_graphql::Error::new("This is already your email"));
+        }
+        if User::find_by_email(new_email.clone())
+            .one(&my_ctx.db)
+            .await?
+            .is_some()
+        {
+            return Err(async_graphql::Error::new("Email is already in use"));
+        }
+
+        // Only the latest request is honoured; tokens carry its id.
+        let change_id = new_change_id();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as usize;
+        let exp = now + EMAIL_CHANGE_EXPIRATION * 60 * 60;
+
+        let mut conn = my_ctx.redis_pool.get().await?;
+        cmd("SET")
+            .arg(format!("email_change:{}", user.id))
+            .arg(&change_id)
+            .arg("EX")
+            .arg(EMAIL_CHANGE_EXPIRATION * 60 * 60)
+            .query_async::<()>(&mut conn)
+            .await?;
+
+        enqueue(
+            &my_ctx.db,
+            Task::SendEmailChangeConfirmation {
+                user_id: user.id,
+                new_email: new_email.clone(),
+                change_id: change_id.clone(),
+                exp,
+            },
+        )
+        .await?;
+
+        if let Some(old_email) = &user.email {
+            enqueue(
+                &my_ctx.db,
+                Task::SendEmailChangeNotice {
+                    to: old_email.clone(),
+                    user_id: user.id,
+                    new_email,
+                    change_id,
+                    exp,
+                },
+            )
+            .await?;
+        }
+
+        Ok("Confirmation email sent".to_string())
+    }
+
+    async fn confirm_email_change(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        token: String,
+    ) -> Result<user::Model, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let (user_id, claims) = verify_email_change_token(my_ctx, &token, "email_change").await?;
+        let new_email = claims
+            .get("email")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| async_graphql::Error::new("Invalid token"))?
+            .to_string();
+
+        let user = User::find_by_id(user_id)
+            .one(&my_ctx.db)
+            .await?
+            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
+
+        let taken = User::find_by_email(new_email.clone())
+            .one(&my_ctx.db)
+            .await?
+            .is_some_and(|other| other.id != user.id);
+        if taken {
+            return Err(async_graphql::Error::new("Email is already in use"));
+        }
+
+        let user: user::Model = user::ActiveModel {
+            email: Set(Some(new_email)),
+            email_verified: Set(true),
+            updated_at: Set(Utc::now().naive_utc()),
+            ..user.into()
+        }
+        .update(&my_ctx.db)
+        .await?;
+
+        let mut conn = my_ctx.redis_pool.get().await?;
+        cmd("DEL")
+            .arg(format!("email_change:{}", user.id))
+            .query_async::<()>(&mut conn)
+            .await?;
+
+        Ok(user)
+    }
+
+    /// Cancel link from the notice sent to the old address.
+    async fn cancel_email_change(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        token: String,
+    ) -> Result<String, async_graphql::Error> {
+        let my_ctx = ctx.data::<Context>().unwrap();
+        let (user_id, _) = verify_email_change_token(my_ctx, &token, "email_change_cancel").await?;
+
+        let mut conn = my_ctx.redis_pool.get().await?;
+        cmd("DEL")
+            .arg(format!("email_change:{}", user_id))
+            .query_async::<()>(&mut conn)
+            .await?;
+
+        Ok("Email change cancelled".to_string())
+    }
+}
+
+fn new_change_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Checks an email change token and that its request is still the pending one.
+async fn verify_email_change_token(
+    my_ctx: &Context,
+    token: &str,
+    subject: &str,
+) -> Result<(i32, BTreeMap<String, Value>), async_graphql::Error> {
+    let claims: BTreeMap<String, Value> = token
+        .verify_with_key(&my_ctx.email_key)
+        .map_err(|_| async_graphql::Error::new("Invalid token"))?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let exp = claims.get("exp").and_then(|v| v.as_u64()).unwrap_or(0);
+    if claims.get("sub").and_then(|v| v.as_str()) != Some(subject) || exp < now {
+        return Err(async_graphql::Error::new("Invalid or expired token"));
+    }
+
+    let user_id = claims
+        .get("id")
+        .and_then(|v| v.as_i64())
+        .ok_or_else(|| async_graphql::Error::new("Invalid token"))? as i32;
+    let change = claims.get("change").and_then(|v| v.as_str());
+
+    let mut conn = my_ctx.redis_pool.get().await?;
+    let pending: Option<String> = cmd("GET")
+        .arg(format!("email_change:{}", user_id))
+        .query_async(&mut conn)
+        .await?;
+    if pending.is_none() || pending.as_deref() != change {
+        return Err(async_graphql::Error::new(
+            "This email change was cancelled or replaced",
+        ));
+    }
+
+    Ok((user_id, claims))
 }
@@ -0,0 +1,378 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use entity::{
+    notification,
+    push_subscription::{self, Entity as PushSubscription},
+};
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use sea_orm::{ActiveEnum, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde_json::json;
+
+/// How long a push service should hold a message for an offline browser.
+const PUSH_TTL_SECONDS: u64 = 24 * 60 * 60;
+const VAPID_TOKEN_LIFETIME_SECONDS: u64 = 12 * 60 * 60;
+/// Hosts of the browser push services; subscriptions anywhere else are refused
+/// so the server can't be made to post to arbitrary, possibly internal, URLs.
+const PUSH_SERVICE_HOSTS: &[&str] = &[
+    "fcm.googleapis.com",
+    "push.services.mozilla.com",
+    "notify.windows.com",
+    "push.apple.com",
+];
+
+/// Checks that a subscription endpoint belongs to a known push service. With
+/// `allow_mock`, the local `/push-mock/` route is accepted over plain HTTP too.
+pub fn check_endpoint(endpoint: &str, allow_mock: bool) -> Result<(), String> {
+    let url = reqwest::Url::parse(endpoint).map_err(|_| "Invalid push endpoint".to_string())?;
+    if allow_mock
+        && matches!(url.scheme(), "http" | "https")
+        && url.path().starts_with("/push-mock/")
+    {
+        return Ok(());
+    }
+
+    // `domain()` is `None` for IP literals.
+    let known = url.domain().is_some_and(|host| {
+        PUSH_SERVICE_HOSTS
+            .iter()
+            .any(|known| host == *known || host.ends_with(&format!(".{}", known)))
+    });
+    if url.scheme() != "https" || url.port().is_some() || !known {
+        return Err("Unsupported push endpoint".to_string());
+    }
+    Ok(())
+}
+
+/// Sends VAPID-signed, aes128gcm-encrypted Web Push messages (RFC 8030/8291/8292).
+#[derive(Debug)]
+pub struct WebPush {
+    signing_key: SigningKey,
+    public_key: String,
+    subject: String,
+    client: reqwest::Client,
+}
+
+pub enum PushOutcome {
+    Delivered,
+    /// The push service no longer knows the subscription; it should be dropped.
+    Gone,
+}
+
+impl WebPush {
+    /// `private_key` is the base64url-encoded raw 32-byte P-256 scalar.
+    pub fn new(private_key: &str, subject: String) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(private_key.trim())
+            .map_err(|e| format!("Invalid VAPID private key: {}", e))?;
+        let signing_key = SigningKey::from_slice(&bytes)
+            .map_err(|e| format!("Invalid VAPID private key: {}", e))?;
+        let public_key =
+            URL_SAFE_NO_PAD.encode(signing_key.verifying_key().to_encoded_point(false));
+
+        Ok(Self {
+            signing_key,
+            public_key,
+            subject,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// The application server key browsers pass to `PushManager.subscribe()`.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    fn vapid_authorization(&self, endpoint: &str) -> Result<String, String> {
+        let audience = reqwest::Url::parse(endpoint)
+            .map_err(|e| e.to_string())?
+            .origin()
+            .ascii_serialization();
+        let expires = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + VAPID_TOKEN_LIFETIME_SECONDS;
+
+        let header = URL_SAFE_NO_PAD.encode(json!({ "typ": "JWT", "alg": "ES256" }).to_string());
+        let claims = URL_SAFE_NO_PAD
+            .encode(json!({ "aud": audience, "exp": expires, "sub": self.subject }).to_string());
+        let signing_input = format!("{}.{}", header, claims);
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+            self.public_key
+        ))
+    }
+
+    pub async fn send(
+        &self,
+        subscription: &push_subscription::Model,
+        payload: &[u8],
+    ) -> Result<PushOutcome, String> {
+        let p256dh = URL_SAFE_NO_PAD
+            .decode(&subscription.p256dh)
+            .map_err(|e| e.to_string())?;
+        let auth = URL_SAFE_NO_PAD
+            .decode(&subscription.auth)
+            .map_err(|e| e.to_string())?;
+        let body = ece::encrypt(&p256dh, &auth, payload).map_err(|e| e.to_string())?;
+
+        let response = self
+            .client
+            .post(&subscription.endpoint)
+            .header(
+                "Authorization",
+                self.vapid_authorization(&subscription.endpoint)?,
+            )
+            .header("TTL", PUSH_TTL_SECONDS.to_string())
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header("Urgency", "normal")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        match response.status().as_u16() {
+            200..=299 => Ok(PushOutcome::Delivered),
+            404 | 410 => Ok(PushOutcome::Gone),
+            status => Err(format!(
+                "Push to {} failed with {}: {}",
+                subscription.endpoint,
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// Pushes a notification to every browser the user has subscribed, pruning
+/// subscriptions the push service reports as gone.
+pub async fn push_notification(
+    db: &DatabaseConnection,
+    web_push: &WebPush,
+    notification: &notification::Model,
+) -> Result<(), sea_orm::DbErr> {
+    let subscriptions = PushSubscription::find()
+        .filter(push_subscription::Column::UserId.eq(notification.user_id))
+        .all(db)
+        .await?;
+
+    let gone = deliver(web_push, &subscriptions, payload(notification).as_bytes()).await;
+    if !gone.is_empty() {
+        PushSubscription::delete_many()
+            .filter(push_subscription::Column::Id.is_in(gone))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn payload(notification: &notification::Model) -> String {
+    json!({
+        "id": notification.id,
+        "kind": notification.kind.to_value(),
+        "title": notification.title,
+        "body": notification.body,
+        "link": notification.link,
+    })
+    .to_string()
+}
+
+/// Sends `payload` to each subscription and returns the ids of those the push
+/// service no longer knows.
+async fn deliver(
+    web_push: &WebPush,
+    subscriptions: &[push_subscription::Model],
+    payload: &[u8],
+) -> Vec<i32> {
+    let mut gone = Vec::new();
+    for subscription in subscriptions {
+        match web_push.send(subscription, payload).await {
+            Ok(PushOutcome::Delivered) => {}
+            Ok(PushOutcome::Gone) => gone.push(subscription.id),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    gone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::Utc;
+    use ece::LocalKeyPair;
+    use entity::notification::NotificationKind;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    /// What the mock push service received: the path, Authorization header and body.
+    type Received = Arc<Mutex<Vec<(String, String, Vec<u8>)>>>;
+
+    /// Serves `POST /push/{status}`, recording each push and answering with `status`.
+    fn mock_push_service() -> (String, Received) {
+        let received: Received = Arc::default();
+        let recorded = received.clone();
+        let server = HttpServer::new(move || {
+            let recorded = recorded.clone();
+            App::new().route(
+                "/push/{status}",
+                web::post().to(
+                    move |req: HttpRequest, status: web::Path<u16>, body: web::Bytes| {
+                        let recorded = recorded.clone();
+                        async move {
+                            let authorization = req
+                                .headers()
+                                .get("authorization")
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or_default()
+                                .to_string();
+                            recorded.lock().unwrap().push((
+                                req.path().to_string(),
+                                authorization,
+                                body.to_vec(),
+                            ));
+                            HttpResponse::new(StatusCode::from_u16(*status).unwrap())
+                        }
+                    },
+                ),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://127.0.0.1:{}", server.addrs()[0].port());
+        actix_web::rt::spawn(server.run());
+        (url, received)
+    }
+
+    fn web_push() -> WebPush {
+        WebPush::new(
+            &URL_SAFE_NO_PAD.encode([7u8; 32]),
+            "mailto:test@example.com".to_string(),
+        )
+        .unwrap()
+    }
+
+    /// A browser subscribing at `endpoint`, with the keys it decrypts pushes with.
+    fn subscribe(
+        id: i32,
+        endpoint: String,
+    ) -> (push_subscription::Model, Box<dyn LocalKeyPair>, [u8; 16]) {
+        let (browser_key, auth) = ece::generate_keypair_and_auth_secret().unwrap();
+        let subscription = push_subscription::Model {
+            id,
+            user_id: 5,
+            endpoint,
+            p256dh: URL_SAFE_NO_PAD.encode(browser_key.pub_as_raw().unwrap()),
+            auth: URL_SAFE_NO_PAD.encode(auth),
+            created_at: Utc::now().naive_utc(),
+        };
+        (subscription, browser_key, auth)
+    }
+
+    fn notification() -> notification::Model {
+        notification::Model {
+            id: 1,
+            user_id: 5,
+            kind: NotificationKind::PriceDrop,
+            title: "Price drop on a favorite advert".to_string(),
+            body: "\"Bike\" dropped from 100 EUR to 80 EUR.".to_string(),
+            link: Some("https://ad-ee.tech/advert/3".to_string()),
+            read_at: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn accepts_only_known_push_services() {
+        for endpoint in [
+            "https://fcm.googleapis.com/fcm/send/abc",
+            "https://updates.push.services.mozilla.com/wpush/v2/abc",
+            "https://wns2-par02p.notify.windows.com/w/?token=abc",
+            "https://web.push.apple.com/abc",
+        ] {
+            assert_eq!(check_endpoint(endpoint, false), Ok(()), "{}", endpoint);
+        }
+
+        for endpoint in [
+            "http://fcm.googleapis.com/fcm/send/abc",
+            "https://fcm.googleapis.com:8443/fcm/send/abc",
+            "https://127.0.0.1/push",
+            "https://[::1]/push",
+            "https://169.254.169.254/latest/meta-data",
+            "https://localhost/push",
+            "https://internal.example.com/push",
+            "https://fcm.googleapis.com.evil.example/push",
+            "file:///etc/passwd",
+            "not a url",
+        ] {
+            assert!(check_endpoint(endpoint, false).is_err(), "{}", endpoint);
+        }
+    }
+
+    #[test]
+    fn accepts_the_mock_route_only_when_enabled() {
+        let mock = "http://127.0.0.1:8080/push-mock/browser-1";
+        assert!(check_endpoint(mock, false).is_err());
+        assert_eq!(check_endpoint(mock, true), Ok(()));
+        assert!(check_endpoint("http://127.0.0.1:6379/", true).is_err());
+    }
+
+    #[actix_web::test]
+    async fn delivers_the_notification_to_the_subscription() {
+        let (url, received) = mock_push_service();
+        let (subscription, browser_key, auth) = subscribe(9, format!("{}/push/201", url));
+
+        let gone = deliver(
+            &web_push(),
+            &[subscription],
+            payload(&notification()).as_bytes(),
+        )
+        .await;
+        assert!(gone.is_empty());
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (_, authorization, body) = &received[0];
+        assert!(authorization.starts_with("vapid t="));
+
+        let payload = ece::decrypt(&browser_key.raw_components().unwrap(), &auth, body).unwrap();
+        let payload: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "id": 1,
+                "kind": "price_drop",
+                "title": "Price drop on a favorite advert",
+                "body": "\"Bike\" dropped from 100 EUR to 80 EUR.",
+                "link": "https://ad-ee.tech/advert/3",
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn reports_subscriptions_the_push_service_says_are_gone() {
+        let (url, received) = mock_push_service();
+        let (active, _, _) = subscribe(9, format!("{}/push/201", url));
+        let (expired, _, _) = subscribe(10, format!("{}/push/410", url));
+        let (failing, _, _) = subscribe(11, format!("{}/push/500", url));
+
+        let gone = deliver(
+            &web_push(),
+            &[active, expired, failing],
+            payload(&notification()).as_bytes(),
+        )
+        .await;
+
+        // Only the 410 is dropped; a server error may be temporary.
+        assert_eq!(gone, vec![10]);
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+}
@@ -1,7 +1,7 @@
 import { Injectable, UnauthorizedException } from '@nestjs/common';
 import { KyselyService } from './kysely.service';
 import { Kysely, sql } from 'kysely';
-import { Database, Advert, Chat } from 'types';
+import { Database, Advert, Chat, NotificationKind } from 'types';
 import { VoteService } from './vote.service';
 
 @Injectable()
@@ -128,7 +128,7 @@ export class AppService {
       .where('advert.id', '=', chat.advert_id)
       .executeTakeFirst()) as unknown as Advert;
 
-    if (advert.archived || !advert || !advert.available) {
+    if (!advert || advert.archived || advert.status !== 'active') {
       throw new UnauthorizedException('Post archived');
     }
 
@@ -152,6 +152,18 @@ export class AppService {
       .returningAll()
       .executeTakeFirst();
 
+    const recipient =
+      advert.user_id === parseInt(s.user.id)
+        ? chat.participant_id
+        : advert.user_id;
+    await this.notify(
+      recipient,
+      'new_message',
+      `New message about "${advert.title}"`,
+      content,
+      `https://ad-ee.tech/chat/${chat.id}`,
+    );
+
     return { message, participant: chat.participant_id, creator: advert.user_id };
   }
 
@@ -279,7 +291,9 @@ export class AppService {
         'advert.created_at as advert_created_at',
         'advert.updated_at as advert_updated_at',
         'advert.available',
+        'advert.status',
         'advert.price',
+        'advert.currency',
         'advert.photo_url',
         'advert.lat',
         'advert.lon',
@@ -317,6 +331,7 @@ export class AppService {
         'deal.id as deal_id',
         'deal.chat_id as deal_chat_id',
         'deal.price as deal_price',
+        'deal.currency as deal_currency',
         'deal.created_at as deal_created_at',
         'deal.requester_id as deal_requester_id',
       ])
@@ -389,7 +404,9 @@ export class AppService {
         created_at: row.advert_created_at,
         updated_at: row.advert_updated_at,
         available: row.available,
+        status: row.status,
         price: row.price,
+        currency: row.currency,
         photo_url: row.photo_url,
         lat: row.lat,
         lon: row.lon,
@@ -408,6 +425,7 @@ export class AppService {
             id: row.deal_id,
             chat_id: row.deal_chat_id,
             price: row.deal_price,
+            currency: row.deal_currency,
             created_at: row.deal_created_at,
             requester_id: row.deal_requester_id,
           }
@@ -472,7 +490,20 @@ export class AppService {
 
     switch (state) {
       case 'start':
-        newDeal = await this.startDeal(deal, chatId, price, user.id);
+        newDeal = await this.startDeal(
+          deal,
+          chatId,
+          price,
+          advert.currency,
+          user.id,
+        );
+        await this.notify(
+          advert.user_id === user.id ? chat.participant_id : advert.user_id,
+          'deal_offer',
+          `New offer for "${advert.title}"`,
+          `${price} ${advert.currency}`,
+          `https://ad-ee.tech/chat/${chatId}`,
+        );
         break;
       case 'stop':
         newDeal = await this.stopDeal(deal, chat.advert_id);
@@ -496,6 +527,7 @@ export class AppService {
     deal: any,
     chatId: number,
     price: string,
+    currency: string,
     requesterId: number,
   ) {
     if (deal) throw new UnauthorizedException('Deal already active');
@@ -503,7 +535,9 @@ export class AppService {
       .insertInto('deal')
       .values({
         chat_id: chatId,
-        price: parseFloat(price),
+        // numeric column: pass the string through so no cents are lost
+        price: String(price),
+        currency,
         requester_id: requesterId,
         status: 'pending',
       })
@@ -520,7 +554,7 @@ export class AppService {
 
     await this.db
       .updateTable('advert')
-      .set({ available: true })
+      .set({ available: true, status: 'active' })
       .where('advert.id', '=', postId)
       .execute();
 
@@ -566,7 +600,7 @@ export class AppService {
 
       await this.db
         .updateTable('advert')
-        .set({ available: false })
+        .set({ available: false, status: 'reserved' })
         .where('advert.id', '=', postId)
         .execute();
 
@@ -609,7 +643,12 @@ export class AppService {
 
       await this.db
         .updateTable('advert')
-        .set({ available: false, sold_to: chat.participant_id })
+        .set({
+          available: false,
+          status: 'sold',
+          sold_to: chat.participant_id,
+          sold_at: new Date(),
+        })
         .where('advert.id', '=', postId)
         .execute();
 
@@ -626,6 +665,32 @@ export class AppService {
     return deal;
   }
 
+  // Chat notifications are in-app only: this skips the backend's notify(), so
+  // no email is sent, and the backend doesn't offer the email channel for these
+  // kinds. The backend picks new rows up through the notification trigger,
+  // streams them to subscribed clients and sends them as Web Push.
+  async notify(
+    userId: number,
+    kind: NotificationKind,
+    title: string,
+    body: string,
+    link: string,
+  ) {
+    const preference = await this.db
+      .selectFrom('notification_preference')
+      .select('in_app')
+      .where('user_id', '=', userId)
+      .where('kind', '=', kind)
+      .executeTakeFirst();
+
+    if (preference && !preference.in_app) return;
+
+    await this.db
+      .insertInto('notification')
+      .values({ user_id: userId, kind, title, body, link })
+      .execute();
+  }
+
   async getUser(userId: number) {
     return await this.db
       .selectFrom('user')
@@ -8,6 +8,8 @@ export interface Database {
   deal: Deal;
   favorites: Favorites;
   message: Message;
+  notification: Notification;
+  notification_preference: NotificationPreference;
   payment: Payment;
   reviews: Reviews;
   seaql_migrations: SeaqlMigrations;
@@ -35,7 +37,9 @@ export interface Advert {
   created_at: Date;
   updated_at: Date;
   available: boolean;
-  price: number;
+  // NUMERIC columns come back from pg as strings
+  price: string;
+  currency: string;
   photo_url: string;
   lat: number;
   lon: number;
@@ -45,8 +49,11 @@ export interface Advert {
   description: string;
   user_id: number;
   sold_to?: number;
+  sold_at?: Date | null;
   archived: boolean;
-  old_price: number;
+  old_price: string;
+  status: 'draft' | 'active' | 'reserved' | 'sold' | 'expired' | 'archived';
+  expires_at?: Date | null;
 }
 
 export interface Chat {
@@ -61,7 +68,8 @@ export interface Chat {
 export interface Deal {
   id: Generated<number>;
   chat_id: number;
-  price: number;
+  price: string;
+  currency: string;
   created_at: Date;
   requester_id: number;
   status: string;
@@ -84,6 +92,31 @@ export interface Message {
   read_at?: Date | null;
 }
 
+export type NotificationKind =
+  | 'new_message'
+  | 'deal_offer'
+  | 'review_received'
+  | 'price_drop'
+  | 'advert_expiring';
+
+export interface Notification {
+  id: Generated<number>;
+  user_id: number;
+  kind: NotificationKind;
+  title: string;
+  body: string;
+  link?: string | null;
+  read_at?: Date | null;
+  created_at: Generated<Date>;
+}
+
+export interface NotificationPreference {
+  user_id: number;
+  kind: NotificationKind;
+  in_app: boolean;
+  email: boolean;
+}
+
 export interface Payment {
   id: Generated<number>;
   order_id: string;